@@ -1,10 +1,10 @@
 use clap::Parser;
 use error::Error::ConfigurationError;
-use sigma_convert::from_sigma;
+use sigma_convert::{from_sigma, from_sigma_rules};
 use std::{
     collections::HashMap,
     env::current_dir,
-    fs::{self, create_dir_all},
+    fs::{self, create_dir_all, DirEntry},
     path::{Path, PathBuf},
     process::exit,
 };
@@ -40,6 +40,15 @@ pub fn read_dir(dir_path: PathBuf, dest_type: String, cli_opts: &CliOptions) {
         eprintln!("The provided dir path does not exist or is not a directory.")
     }
     let dir_contents = dir_path.read_dir().unwrap();
+    if cli_opts.single_output {
+        convert_files(
+            &dir_path,
+            dir_contents.flatten().collect(),
+            dest_type,
+            cli_opts,
+        );
+        return;
+    }
     for dir_item in dir_contents.flatten() {
         convert_file(
             dir_item.path().display().to_string(),
@@ -49,14 +58,149 @@ pub fn read_dir(dir_path: PathBuf, dest_type: String, cli_opts: &CliOptions) {
     }
 }
 
+/// Converts all the rules of a dir into a single output file named after the dir.
+pub fn convert_files(
+    dir_path: &Path,
+    dir_items: Vec<DirEntry>,
+    dest_type: String,
+    cli_opts: &CliOptions,
+) {
+    let mut rules = vec![];
+    for dir_item in dir_items {
+        let path = dir_item.path();
+        if !path.is_file() {
+            eprintln!("Skipping {}: not a file", path.display());
+            continue;
+        }
+        match read_sigma_file(path.display().to_string().as_str()) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => eprintln!("Skipping {}: {}", path.display(), e),
+        }
+    }
+    let output_dir = current_dir().unwrap().join("output");
+    create_dir_all(output_dir.clone()).unwrap();
+    println!("Converting the sigma rules in {}...", dir_path.display());
+    let converted_str = from_sigma_rules(
+        &rules,
+        &dest_type.to_lowercase(),
+        load_mappings_file(cli_opts),
+        cli_opts.add_alerting.clone(),
+        cli_opts.add_fields.clone(),
+        cli_opts.replace_fields.clone(),
+        cli_opts.keep_fields.clone(),
+    );
+    let converted_file = output_dir.join(format!(
+        "{}_{}.{}",
+        dest_type.to_lowercase(),
+        dir_path.file_name().unwrap_or_default().to_str().unwrap(),
+        file_extension(&dest_type)
+    ));
+    match converted_str {
+        Ok(result) => {
+            fs::write(converted_file.clone(), result).unwrap();
+            println!(
+                "SUCCESS: Converted the sigma rules in {} to {}.\nOutput File: {:?}",
+                dir_path.display(),
+                dest_type,
+                converted_file.display()
+            );
+        }
+        Err(e) => eprintln!(
+            "ERROR: Could not convert sigma rules in {} to {}: {}",
+            dir_path.display(),
+            dest_type,
+            e
+        ),
+    }
+}
+
+/// The extension of the files holding the converted rules.
+fn file_extension(dest_type: &str) -> &'static str {
+    match dest_type.to_lowercase().as_str() {
+        "elastalert"
+        | "sentinelanalyticsruleyaml"
+        | "lokiruler"
+        | "velociraptorartifact"
+        | "velociraptorsigmamapping" => "yml",
+        "kibana"
+        | "humio"
+        | "humioalert"
+        | "awsopensearchsecurityanalytics"
+        | "opensearchsecurityanalytics"
+        | "awsopensearchmonitor"
+        | "opensearchmonitor"
+        | "sentinelanalyticsrule"
+        | "sumologicscheduledsearch"
+        | "sumologiccloudsiem"
+        | "sumologiccse"
+        | "qradarbuildingblock"
+        | "graylogeventdefinition"
+        | "logrhythmwebconsolesearch"
+        | "logrhythmaiengine"
+        | "logrhythmaie"
+        | "securonixpolicy"
+        | "devoalertdefinition"
+        | "dnifworkbook"
+        | "datadogdetectionrule"
+        | "datadogcloudsiem"
+        | "cortexxdrbioc"
+        | "sentinelonestarrule"
+        | "sentinelonestar"
+        | "carbonblackwatchlist"
+        | "osquerypack" => "json",
+        "qradarrulexml" | "arcsightesmrule" | "arcsightesm" | "wazuh" | "ossec" => "xml",
+        "splunksavedsearches" | "splunkcorrelationsearch" | "splunkes" => "conf",
+        "chronicle" => "yaral",
+        _ => "txt",
+    }
+}
+
+/// The mappings of the mappings file, along with the backend options given on the command line
+/// as `option.<key>` mappings.
+fn load_mappings_file(cli_opts: &CliOptions) -> Option<HashMap<String, String>> {
+    let mut mappings = if let Some(mapping_file) = cli_opts.mappings_file.clone() {
+        // Read the mappings from a mappings.txt file
+        let mappings_file_contents = fs::read_to_string(PathBuf::from(mapping_file.as_str()))
+            .expect("The mappings file could not be read.");
+        Some(load_mappings(&mappings_file_contents))
+    } else {
+        None
+    };
+    for (key, value) in &cli_opts.options {
+        mappings
+            .get_or_insert_with(HashMap::new)
+            .insert(format!("option.{key}"), value.clone());
+    }
+    mappings
+}
+
+/// Parses a `<key>=<value>` backend option.
+fn parse_option(option: &str) -> std::result::Result<(String, String), String> {
+    match option.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("expected <key>=<value>, got '{option}'")),
+    }
+}
+
+/// Each line maps a value to the comma separated keys it stands for (`<value>: <key>, <key>`).
+/// Values may hold colons (Eg: `C:\Logs\Sysmon.evtx`), the line is split at its last `: `.
 fn load_mappings(txt: &str) -> HashMap<String, String> {
     txt.trim()
         .lines()
-        .map(|s| s.split_at(s.find(':').unwrap()))
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| {
+            let (key, val) = match s.rfind(": ") {
+                Some(index) => s.split_at(index),
+                None => s.split_at(s.find(':').unwrap()),
+            };
+            (key, &val[1..])
+        })
         .map(|(key, val)| {
             (
                 key.trim(),
-                val[1..].split(',').map(|s| s.trim()).collect::<Vec<_>>(),
+                val.split(',').map(|s| s.trim()).collect::<Vec<_>>(),
             )
         })
         .collect::<HashMap<&str, Vec<&str>>>()
@@ -71,25 +215,14 @@ fn load_mappings(txt: &str) -> HashMap<String, String> {
 }
 
 pub fn convert_file(file_path: String, dest_type: String, cli_opts: &CliOptions) {
-    let mappings = if let Some(mapping_file) = cli_opts.mappings_file.clone() {
-        // Read the mappings from a mappings.txt file
-        let mappings_file_contents = fs::read_to_string(PathBuf::from(mapping_file.as_str()))
-            .expect("The mappings file could not be read.");
-        Some(load_mappings(&mappings_file_contents))
-    } else {
-        None
-    };
+    let mappings = load_mappings_file(cli_opts);
     // Parse the Sigma Rule
     match read_sigma_file(file_path.as_str()) {
         Ok(rule) => {
             let current_file = Path::new(file_path.as_str()).file_stem().unwrap();
             let output_dir = current_dir().unwrap().join("output");
             create_dir_all(output_dir.clone()).unwrap();
-            let file_extension = match dest_type.to_lowercase().as_str() {
-                "elastalert" => "yml",
-                "kibana" | "humio" | "humioalert" => "json",
-                _ => "txt",
-            };
+            let file_extension = file_extension(&dest_type);
             println!("Converting the sigma rule in {}...", file_path);
             let converted_str = from_sigma(
                 &rule,
@@ -147,9 +280,17 @@ pub struct CliOptions {
     /// The source dir to recursively convert.
     #[arg(short = 'd', long)]
     dir_source: Option<String>,
+    /// Convert all the rules of the source dir into a single output file, for the formats
+    /// able to hold several rules (Eg splunksavedsearches).
+    #[arg(long = "single-output")]
+    single_output: bool,
     /// The mappings.txt file for the current backend
     #[arg(short = 'm', long)]
     mappings_file: Option<String>,
+    /// A backend option (repeatable), Eg "variant.root=raw" for the Snowflake VARIANT column
+    /// holding the raw events. See the README for the options of each backend.
+    #[arg(short = 'o', long = "option", value_parser = parse_option)]
+    options: Vec<(String, String)>,
     /// Keep the following list of fields in the sigma rule(comma separated).
     /// Eg "title, author, tags". `Note: This only applies to the ElastAlert dest_type`
     #[arg(long = "keep-fields")]
@@ -158,7 +299,7 @@ pub struct CliOptions {
     /// Eg "index: tid1452-*". `Note: This only applies to the ElastAlert dest_type`
     #[arg(long = "replace-fields")]
     replace_fields: Option<String>,
-    /// Add an alerting mode to the list in the elastalert rule. `Note: This only applies to the ElastAlert dest_type`
+    /// Add an alerting mode to the list in the elastalert rule, or the comma separated actions of the Splunk saved and correlation searches. `Note: This only applies to the ElastAlert and Splunk search dest_types`
     #[arg(long = "add-alerting")]
     add_alerting: Option<String>,
     /// Add extra fields in the elastalert rule if required. `Note: This only applies to the ElastAlert dest_type`
@@ -1,18 +1,203 @@
 use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition, SigmaRule};
-use crate::prelude::rule::{RuleCondition, RuleOperator, SiemRule};
+use crate::prelude::rule::sigma::{
+    ConditionAggregation, ConditionTree, SigmaRule, SigmaRuleLogSource,
+};
+use crate::prelude::rule::{RuleOperator, SiemRule};
 use crate::prelude::types::LogString;
-use crate::prelude::SiemField;
-use crate::utils::parse_condition;
+use crate::prelude::{AlertSeverity, SiemField};
+use crate::utils::{
+    escape_string, join_predicates, parse_aggregation, parse_condition_tree, render_condition,
+    stable_hash, timeframe_seconds,
+};
 use std::collections::HashMap;
 use std::process::exit;
 
+/// The group the ESM rules are imported into.
+const RULE_GROUP: &str = "/All Rules/Real-time Rules/Sigma/";
+
+/// The artifacts the ArcSight backend is able to produce.
+#[derive(Clone, Debug, Default)]
+pub enum ArcSightOutput {
+    /// The query text.
+    #[default]
+    Query,
+    /// The archive XML of an ARB package holding the ESM rule.
+    EsmRule,
+}
+
 #[derive(Clone, Debug, Default)]
-pub struct ArcSightBackend {}
+pub struct ArcSightBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: ArcSightOutput,
+}
+
+impl ArcSightBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: ArcSightOutput) -> Self {
+        ArcSightBackend { mappings, output }
+    }
+
+    fn parse_condition(&self, rule: &SigmaRule) -> ConditionTree {
+        match parse_condition_tree(&rule.detection.condition) {
+            Ok(condition) => condition,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        }
+    }
+
+    /// The ESM rule conditions, as the `WhereClause` elements of the rule query.
+    fn build_where_clause(&self, rule: &SigmaRule) -> String {
+        let identifiers: Vec<LogString> =
+            rule.detection.search_identifiers.keys().cloned().collect();
+        let condition = self.parse_condition(rule).expand(&identifiers);
+        let mut predicates: Vec<String> = device_conditions(&rule.logsource)
+            .into_iter()
+            .map(|(field, value)| predicate_element(field, "EQ", value))
+            .collect();
+        predicates.push(self.tree_element(rule, &condition));
+        element("And", predicates)
+    }
+
+    fn tree_element(&self, rule: &SigmaRule, condition: &ConditionTree) -> String {
+        match condition {
+            ConditionTree::Identifier(name) => {
+                let groups = rule
+                    .detection
+                    .search_identifiers
+                    .get(name.as_ref())
+                    .map(|search| search.condition_groups())
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|group| {
+                        element(
+                            "And",
+                            group
+                                .iter()
+                                .map(|condition| {
+                                    operator_element(
+                                        &self.get_mapping(condition.field.to_string()),
+                                        &condition.operator,
+                                    )
+                                })
+                                .collect(),
+                        )
+                    })
+                    .collect();
+                element("Or", groups)
+            }
+            ConditionTree::And(nodes) => element(
+                "And",
+                nodes
+                    .iter()
+                    .map(|node| self.tree_element(rule, node))
+                    .collect(),
+            ),
+            ConditionTree::Or(nodes) => element(
+                "Or",
+                nodes
+                    .iter()
+                    .map(|node| self.tree_element(rule, node))
+                    .collect(),
+            ),
+            ConditionTree::Not(node) => not_element(self.tree_element(rule, node)),
+            // expanded away by build_where_clause
+            ConditionTree::AnyOf(_) | ConditionTree::AllOf(_) => String::new(),
+        }
+    }
+
+    fn build_esm_rule(&self, sigma_rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let priority = priority(&siem_rule.alert.severity);
+        let rule_id = resource_id(sigma_rule, "Rule");
+        let title = escape_xml(&sigma_rule.title);
+        let aggregation = aggregation
+            .map(|aggregation| self.build_aggregation(sigma_rule, &aggregation))
+            .unwrap_or_default();
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE Archive SYSTEM "../../schema/xml/archive/arcsight-archive.dtd">
+<Archive buildVersion="7.0.0">
+    <ArchiveCreationParameters>
+        <action>insert</action>
+        <excludeReferenceIDs/>
+        <format>xml.external.case.all</format>
+        <include>
+            <list>
+                <ref type="Rule" uri="{RULE_GROUP}{title}" id="{rule_id}"/>
+            </list>
+        </include>
+    </ArchiveCreationParameters>
+    <Rule ID="{rule_id}" Name="{title}" URI="{RULE_GROUP}">
+        <description>{}</description>
+        <Query ID="{}" Name="{title}">
+            <WhereClause>
+{}
+            </WhereClause>
+        </Query>
+{aggregation}        <Priority>{priority}</Priority>
+        <Action Trigger="OnFirstEvent">
+            <SetEventField Field="name" Value="{title}"/>
+            <SetEventField Field="priority" Value="{priority}"/>
+        </Action>
+    </Rule>
+</Archive>
+"#,
+            escape_xml(sigma_rule.description.as_deref().unwrap_or_default()),
+            resource_id(sigma_rule, "Query"),
+            indent(&self.build_where_clause(sigma_rule), 16),
+        )
+    }
+
+    /// Rules fire once the number of matching events in the time frame reaches the threshold,
+    /// the events being aggregated by the group-by field. Upper bounds can't be expressed.
+    fn build_aggregation(
+        &self,
+        sigma_rule: &SigmaRule,
+        aggregation: &ConditionAggregation,
+    ) -> String {
+        let matches = match aggregation.operator.as_ref() {
+            ">" => aggregation.value + 1,
+            ">=" => aggregation.value,
+            operator => {
+                eprintln!(
+                    "ArcSight rules fire on a minimum number of matches, the aggregation '{operator} {}' of '{}' is dropped",
+                    aggregation.value, sigma_rule.title
+                );
+                return String::new();
+            }
+        };
+        let seconds = timeframe_seconds(sigma_rule.detection.timeframe.as_deref().unwrap_or("5m"));
+        let mut fields = vec![];
+        if let Some(group_by) = &aggregation.group_by {
+            fields.push(format!(
+                "            <GroupBy Field=\"{}\"/>\n",
+                escape_xml(&self.get_mapping(group_by.to_string()))
+            ));
+        }
+        if let Some(field) = &aggregation.field {
+            fields.push(format!(
+                "            <UniqueField Field=\"{}\"/>\n",
+                escape_xml(&self.get_mapping(field.to_string()))
+            ));
+        }
+        format!(
+            "        <Aggregation NumberOfMatches=\"{matches}\" TimeFrame=\"{seconds}\">\n{}        </Aggregation>\n",
+            fields.join("")
+        )
+    }
+}
 
 impl RequiresMappings for ArcSightBackend {
     fn get_field_map(&self) -> Option<HashMap<String, String>> {
-        None
+        self.mappings.clone()
     }
 
     fn get_default_field_name(&self, args: Option<String>) -> String {
@@ -99,384 +284,225 @@ impl RequiresMappings for ArcSightBackend {
 
 impl BackEnd for ArcSightBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        self.build_query(&sigma_rule).clone()
+        match self.output {
+            ArcSightOutput::Query => {
+                let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+                    Ok(aggregation) => aggregation,
+                    Err(e) => {
+                        eprintln!("{:?}", e);
+                        exit(1);
+                    }
+                };
+                if aggregation.is_some() {
+                    eprintln!(
+                        "ArcSight searches match single events, the aggregation of '{}' is dropped: convert it to an ESM rule instead",
+                        sigma_rule.title
+                    );
+                }
+                self.build_query(&sigma_rule)
+            }
+            ArcSightOutput::EsmRule => self.build_esm_rule(&sigma_rule),
+        }
     }
 }
 
 impl QueryBuilder for ArcSightBackend {
     fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
-            Err(e) => {
-                eprintln!("{:?}", e);
-                exit(1);
-            }
-        };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().into_iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            let (vendor, product) = if let Some(product) = &rule.logsource.product {
-                match product.to_lowercase().as_str() {
-                    "windows" => (
-                        Some("Microsoft".to_string()),
-                        Some("Microsoft Windows".to_string()),
-                    ),
-                    "linux" => (Some("Unix".to_string()), None),
-                    _ => (None, None),
-                }
-            } else {
-                (None, None)
-            };
-            if let Some(vendor) = vendor {
-                let vendor_condition = RuleCondition {
-                    field: LogString::Owned("deviceVendor".to_string()),
-                    operator: RuleOperator::Equals(SiemField::Text(LogString::Owned(vendor))),
-                };
-                subrule_conditions.push(vendor_condition);
-            };
-            if let Some(product) = product {
-                let product_condition = RuleCondition {
-                    field: LogString::Owned("deviceProduct".to_string()),
-                    operator: RuleOperator::Equals(SiemField::Text(LogString::Owned(product))),
-                };
-                subrule_conditions.push(product_condition);
-            };
+        let query = render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            build_predicate(&self.get_mapping(field.to_string()), operator)
+        });
+        let mut predicates: Vec<String> = device_conditions(&rule.logsource)
+            .into_iter()
+            .map(|(field, value)| format!("{field} = \"{}\"", escape_string(value)))
+            .collect();
+        predicates.push(query);
+        join_predicates(predicates, "AND")
+    }
+}
 
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace("\\", "\\\\");
-                        query_str.push_str(
-                            format!("{} STARTSWITH \"{}\"", self.get_mapping(cond_name), pattern)
-                                .as_str(),
-                        );
-                        // Map the condition name
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace("\\", "\\\\");
-                        query_str.push_str(
-                            format!("{} ENDSWITH \"{}\"", self.get_mapping(cond_name), pattern)
-                                .as_str(),
-                        );
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace("\\", "\\\\");
-                        query_str.push_str(
-                            format!("{} CONTAINS \"{}\"", self.get_mapping(cond_name), pattern)
-                                .as_str(),
-                        )
-                    }
-                    RuleOperator::Equals(field) => match field {
-                        SiemField::Array(_) => {}
-                        _ => {
-                            query_str.push_str(
-                                format!(
-                                    "{} = \"{}\"",
-                                    self.get_mapping(cond_name),
-                                    field.to_string()
-                                )
-                                .as_str(),
-                            );
-                        }
-                    },
-                    RuleOperator::Any(cond_list) => {
-                        let cond_name = self.get_mapping(cond_name);
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{} = \"{}\"", cond_name, val));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries
-                                        .push(format!("{} STARTSWITH \"{}\"", cond_name, pattern));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries
-                                        .push(format!("{} ENDSWITH \"{}\"", cond_name, pattern));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries
-                                        .push(format!("{} CONTAINS \"{}\"", cond_name, pattern));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" OR ");
-                        query_str.push_str(format!("{}", joined_sub_queries).as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let cond_name = self.get_mapping(cond_name);
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{} = \"{}\"", cond_name, val));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "({} STARTSWITH \"{}\")",
-                                        cond_name, pattern
-                                    ));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries
-                                        .push(format!("({} ENDSWITH \"{}\")", cond_name, pattern));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries
-                                        .push(format!("({} CONTAINS \"{}\")", cond_name, pattern));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" AND ");
-                        query_str.push_str(format!("{}", joined_sub_queries).as_str());
-                    }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
+/// The device vendor and product of the logsource events.
+fn device_conditions(logsource: &SigmaRuleLogSource) -> Vec<(&'static str, &'static str)> {
+    match logsource
+        .product
+        .as_deref()
+        .unwrap_or_default()
+        .to_lowercase()
+        .as_str()
+    {
+        "windows" => vec![
+            ("deviceVendor", "Microsoft"),
+            ("deviceProduct", "Microsoft Windows"),
+        ],
+        "linux" => vec![("deviceVendor", "Unix")],
+        _ => vec![],
+    }
+}
+
+fn build_predicate(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => join_predicates(
+            values
+                .iter()
+                .map(|value| format!("{field} = \"{}\"", escape_string(value)))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::Equals(value) => {
+            format!("{field} = \"{}\"", escape_string(&value.to_string()))
+        }
+        RuleOperator::StartsWith(pattern) => {
+            format!("{field} STARTSWITH \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::EndsWith(pattern) => {
+            format!("{field} ENDSWITH \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::Contains(pattern) => {
+            format!("{field} CONTAINS \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::Matches(regex) => {
+            format!("{field} MATCHES \"{}\"", escape_string(regex.as_str()))
+        }
+        RuleOperator::SameNet((ip, net)) => format!("{field} IN SUBNET \"{ip}/{net}\""),
+        RuleOperator::IsNull(_) => format!("{field} IS NULL"),
+        RuleOperator::Any(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
             }
-            let mut condition_query = String::new();
-            condition_query.push('(');
-            condition_query.push_str(conditions_list.join(" AND ").as_str());
-            condition_query.push(')');
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
         }
+        _ => String::new(),
+    }
+}
 
-        // Check the conditions of this subrule
-        for condition in conditions.clone() {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace("*", "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" OR ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace("*", "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" AND ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" NOT ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace("*", "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" OR ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace("*", "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" AND ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" AND ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace("*", "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace("*", "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace("*", "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace("*", "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" OR ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace("*", "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace("*", "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace("*", "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace("*", "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-            }
+/// The ESM condition elements of a single field, the operators being the ones of the
+/// query text.
+fn operator_element(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => element(
+            "Or",
+            values
+                .iter()
+                .map(|value| predicate_element(field, "EQ", value))
+                .collect(),
+        ),
+        RuleOperator::Equals(value) => predicate_element(field, "EQ", &value.to_string()),
+        RuleOperator::StartsWith(pattern) => predicate_element(field, "StartsWith", pattern),
+        RuleOperator::EndsWith(pattern) => predicate_element(field, "EndsWith", pattern),
+        RuleOperator::Contains(pattern) => predicate_element(field, "Contains", pattern),
+        RuleOperator::Matches(regex) => predicate_element(field, "MatchesRegex", regex.as_str()),
+        RuleOperator::SameNet((ip, net)) => {
+            predicate_element(field, "InSubnet", &format!("{ip}/{net}"))
         }
-        format!(
-            "{} AND type != 2 | rex field = flexString1 mode=sed \"s//Sigma: {}/g\"",
-            query_str.clone(),
-            rule.title.to_string()
-        )
+        RuleOperator::IsNull(_) => format!(
+            "<Predicate Field=\"{}\" Operator=\"IsNull\"/>",
+            escape_xml(field)
+        ),
+        RuleOperator::Any(operators) => element(
+            "Or",
+            operators
+                .iter()
+                .map(|operator| operator_element(field, operator))
+                .collect(),
+        ),
+        RuleOperator::All(operators) => element(
+            "And",
+            operators
+                .iter()
+                .map(|operator| operator_element(field, operator))
+                .collect(),
+        ),
+        RuleOperator::Not(operator) => not_element(operator_element(field, operator)),
+        _ => String::new(),
+    }
+}
+
+fn predicate_element(field: &str, operator: &str, value: &str) -> String {
+    format!(
+        "<Predicate Field=\"{}\" Operator=\"{operator}\" Value=\"{}\"/>",
+        escape_xml(field),
+        escape_xml(value)
+    )
+}
+
+fn not_element(child: String) -> String {
+    if child.is_empty() {
+        child
+    } else {
+        format!("<Not>\n{}\n</Not>", indent(&child, 4))
+    }
+}
+
+/// Groups the children in an And/Or element, a single child standing for itself.
+fn element(name: &str, children: Vec<String>) -> String {
+    let children: Vec<String> = children.into_iter().filter(|c| !c.is_empty()).collect();
+    match children.len() {
+        0 => String::new(),
+        1 => children[0].clone(),
+        _ => format!(
+            "<{name}>\n{}\n</{name}>",
+            children
+                .iter()
+                .map(|child| indent(child, 4))
+                .collect::<Vec<String>>()
+                .join("\n")
+        ),
+    }
+}
+
+fn indent(text: &str, spaces: usize) -> String {
+    text.lines()
+        .map(|line| format!("{}{line}", " ".repeat(spaces)))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// ESM priorities go from 0 to 10.
+fn priority(severity: &AlertSeverity) -> u8 {
+    match severity {
+        AlertSeverity::INFORMATIONAL => 1,
+        AlertSeverity::LOW => 3,
+        AlertSeverity::MEDIUM => 5,
+        AlertSeverity::HIGH => 7,
+        AlertSeverity::CRITICAL => 9,
     }
 }
 
+/// ESM resources are identified by ids, derived from the rule so importing the same rule
+/// twice updates the same resources.
+fn resource_id(sigma_rule: &SigmaRule, kind: &str) -> String {
+    let rule = sigma_rule.id.as_deref().unwrap_or(&sigma_rule.title);
+    format!("{kind}-{:016X}", stable_hash(&format!("{kind}:{rule}")))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::backend::arcsight::ArcSightBackend;
-    use crate::backend::QueryBuilder;
+    use crate::backend::arcsight::{ArcSightBackend, ArcSightOutput};
+    use crate::backend::{BackEnd, QueryBuilder};
     use crate::parse_sigma_rule;
 
     #[test]
@@ -518,4 +544,61 @@ level: medium"##;
         let query = arcsight.build_query(&sigma_rule);
         println!("{}", query);
     }
+
+    #[test]
+    pub fn test_esm_rule() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let arcsight = ArcSightBackend::new(None, ArcSightOutput::EsmRule);
+        assert_eq!(
+            arcsight.build_query(&rule),
+            r#"(deviceVendor = "Microsoft" AND deviceProduct = "Microsoft Windows" AND (((deviceProcessName ENDSWITH "\\cmd.exe" OR deviceCustomString3 = "Cmd.Exe") AND sourceProcessName ENDSWITH "\\7zFM.exe") AND NOT ((destinationServiceName CONTAINS "/c" OR destinationServiceName CONTAINS "/k" OR destinationServiceName CONTAINS "/r") OR destinationServiceName IS NULL)))"#
+        );
+        let xml = arcsight.convert_rule(rule);
+        assert!(xml.contains("<Priority>7</Priority>"));
+        assert!(xml.contains(r#"<Rule ID="Rule-7DE49126CAA95824" "#));
+        assert!(xml.contains(r#"<Query ID="Query-FE746A65CF5A5696" "#));
+        assert!(xml.contains(
+            r#"                <And>
+                    <Predicate Field="deviceVendor" Operator="EQ" Value="Microsoft"/>
+                    <Predicate Field="deviceProduct" Operator="EQ" Value="Microsoft Windows"/>"#
+        ));
+        assert!(xml.contains(
+            r#"<Predicate Field="sourceProcessName" Operator="EndsWith" Value="\7zFM.exe"/>"#
+        ));
+        assert!(xml.contains(
+            r#"                        <Not>
+                            <Or>
+                                <Or>
+                                    <Predicate Field="destinationServiceName" Operator="Contains" Value="/c"/>"#
+        ));
+    }
+
+    #[test]
+    pub fn test_esm_aggregation() {
+        let rule = |operator: &str| {
+            parse_sigma_rule(&format!(
+                r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 1h
+    condition: selection | count() by TargetUserName {operator} 3
+"#
+            ))
+            .unwrap()
+        };
+        let arcsight = ArcSightBackend::new(None, ArcSightOutput::EsmRule);
+        assert!(arcsight
+            .convert_rule(rule(">"))
+            .contains(r#"<Aggregation NumberOfMatches="4" TimeFrame="3600">"#));
+        assert!(arcsight
+            .convert_rule(rule(">="))
+            .contains(r#"<Aggregation NumberOfMatches="3" TimeFrame="3600">"#));
+        assert!(!arcsight.convert_rule(rule("<")).contains("<Aggregation"));
+    }
 }
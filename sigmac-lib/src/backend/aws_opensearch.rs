@@ -1,17 +1,319 @@
 use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
-use crate::prelude::rule::RuleCondition;
-use crate::prelude::SiemField;
-use crate::sigma::components::rule::sigma::SigmaRule;
+use crate::prelude::{AlertSeverity, SiemField, SiemIp};
+use crate::sigma::components::rule::sigma::{SigmaRule, SigmaRuleLogSource};
 use crate::sigma::components::rule::RuleOperator;
 use crate::sigma::components::rule::SiemRule;
-use crate::sigma::utilities::types::LogString;
-use crate::utils::parse_condition;
-use std::collections::HashMap;
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{join_predicates, parse_aggregation, render_condition, timeframe_seconds};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeSet, HashMap};
 use std::process::exit;
 
+/// The artifacts the OpenSearch backend is able to produce.
 #[derive(Clone, Debug, Default)]
-pub struct AWSOpenSearchBackend {}
+pub enum OpenSearchOutput {
+    /// The bare Lucene query string.
+    #[default]
+    Query,
+    /// A Security Analytics detector rule along with the alias mappings of the fields it uses.
+    SecurityAnalytics,
+    /// An Alerting plugin query-level monitor.
+    Monitor,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AWSOpenSearchBackend {
+    output: OpenSearchOutput,
+}
+
+impl AWSOpenSearchBackend {
+    pub fn new(output: OpenSearchOutput) -> Self {
+        AWSOpenSearchBackend { output }
+    }
+
+    /// Builds the payloads for the Security Analytics rules and mappings APIs. The rule itself stays
+    /// in Sigma format, the rule fields are bound to the index fields through alias mappings.
+    fn build_security_analytics_rule(&self, sigma_rule: &SigmaRule) -> String {
+        let rule_fields: BTreeSet<String> = sigma_rule
+            .detection
+            .search_identifiers
+            .values()
+            .flat_map(|search| search.condition_groups())
+            .flatten()
+            .map(|condition| condition.field.to_string())
+            .filter(|field| !field.is_empty())
+            .collect();
+        let mut properties = Map::new();
+        for field in rule_fields {
+            let path = self.get_mapping(field.clone());
+            properties.insert(field, json!({"type": "alias", "path": path}));
+        }
+        let category = security_analytics_category(&sigma_rule.logsource);
+        let security_analytics = json!({
+            "category": category,
+            "rule": match &sigma_rule.source {
+                Some(source) => source.to_string(),
+                None => serde_yaml::to_string(sigma_rule).unwrap_or_default(),
+            },
+            "mappings": {
+                "index_name": index_pattern(&sigma_rule.logsource),
+                "rule_topic": category,
+                "partial": true,
+                "alias_mappings": {
+                    "properties": Value::Object(properties)
+                }
+            }
+        });
+        serde_json::to_string_pretty(&security_analytics).unwrap()
+    }
+
+    /// Wraps the query into a query-level monitor that runs on the rule timeframe (5 minutes
+    /// by default) over the events of that timeframe. It triggers as soon as a single event
+    /// matches, or once the aggregation of a group of events crosses its threshold.
+    fn build_monitor(&self, sigma_rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let minutes =
+            (timeframe_seconds(sigma_rule.detection.timeframe.as_deref().unwrap_or("5m")) / 60)
+                .max(1);
+        let mut search = json!({
+            "size": 0,
+            "query": {
+                "bool": {
+                    "filter": [
+                        {
+                            "query_string": {
+                                "query": self.build_query(sigma_rule),
+                                "analyze_wildcard": true
+                            }
+                        },
+                        {
+                            "range": {
+                                "@timestamp": {
+                                    "gte": format!("{{{{period_end}}}}||-{minutes}m"),
+                                    "lte": "{{period_end}}",
+                                    "format": "epoch_millis"
+                                }
+                            }
+                        }
+                    ]
+                }
+            }
+        });
+        let condition = match aggregation {
+            Some(aggregation) => {
+                // count(field) counts the distinct values of the field
+                let metric = match (aggregation.function.as_ref(), &aggregation.field) {
+                    ("count", Some(field)) => Some(
+                        json!({ "cardinality": { "field": self.get_mapping(field.to_string()) } }),
+                    ),
+                    (function, Some(field)) => {
+                        Some(json!({ function: { "field": self.get_mapping(field.to_string()) } }))
+                    }
+                    (_, None) => None,
+                };
+                let value = match metric {
+                    Some(_) => "b.metric.value",
+                    None => "b.doc_count",
+                };
+                let operator = match aggregation.operator.as_ref() {
+                    "=" => "==",
+                    operator => operator,
+                };
+                let mut groups = json!({
+                    "terms": {
+                        "field": match &aggregation.group_by {
+                            Some(group_by) => self.get_mapping(group_by.to_string()),
+                            None => "_index".to_string(),
+                        },
+                        "size": 1000
+                    }
+                });
+                if let Some(metric) = metric {
+                    groups["aggs"] = json!({ "metric": metric });
+                }
+                search["aggs"] = json!({ "groups": groups });
+                format!(
+                    "ctx.results[0].aggregations.groups.buckets.stream().anyMatch(b -> {value} {operator} {})",
+                    aggregation.value
+                )
+            }
+            None => "ctx.results[0].hits.total.value > 0".to_string(),
+        };
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let severity = match siem_rule.alert.severity {
+            AlertSeverity::CRITICAL => "1",
+            AlertSeverity::HIGH => "2",
+            AlertSeverity::MEDIUM => "3",
+            AlertSeverity::LOW => "4",
+            AlertSeverity::INFORMATIONAL => "5",
+        };
+        let monitor = json!({
+            "type": "monitor",
+            "monitor_type": "query_level_monitor",
+            "name": format!("SIGMA - {}", sigma_rule.title),
+            "enabled": true,
+            "schedule": {
+                "period": {
+                    "interval": minutes,
+                    "unit": "MINUTES"
+                }
+            },
+            "inputs": [{
+                "search": {
+                    "indices": [index_pattern(&sigma_rule.logsource)],
+                    "query": search
+                }
+            }],
+            "triggers": [{
+                "query_level_trigger": {
+                    "name": sigma_rule.title,
+                    "severity": severity,
+                    "condition": {
+                        "script": {
+                            "source": condition,
+                            "lang": "painless"
+                        }
+                    },
+                    "actions": []
+                }
+            }]
+        });
+        serde_json::to_string_pretty(&monitor).unwrap()
+    }
+}
+
+/// Renders the condition of a single field as a Lucene query, the keywords being searched in
+/// all the fields.
+fn build_predicate(field: &str, operator: &RuleOperator) -> String {
+    let term = |value: String| {
+        if field.is_empty() {
+            value
+        } else {
+            format!("{field}:{value}")
+        }
+    };
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => join_predicates(
+            values
+                .iter()
+                .map(|value| term(format!("\"{}\"", escape_phrase(value))))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::Equals(SiemField::Text(value)) => {
+            term(format!("\"{}\"", escape_phrase(value)))
+        }
+        RuleOperator::Equals(value) => term(escape_term(&value.to_string())),
+        RuleOperator::StartsWith(pattern) => term(format!("{}*", escape_term(pattern))),
+        RuleOperator::EndsWith(pattern) => term(format!("*{}", escape_term(pattern))),
+        RuleOperator::Contains(pattern) => term(format!("*{}*", escape_term(pattern))),
+        RuleOperator::Matches(regex) => term(format!("/{}/", regex.as_str().replace('/', "\\/"))),
+        RuleOperator::GT(value) => term(format!(">{value}")),
+        RuleOperator::GTE(value) => term(format!(">={value}")),
+        RuleOperator::LT(value) => term(format!("<{value}")),
+        RuleOperator::LTE(value) => term(format!("<={value}")),
+        RuleOperator::SameNet((ip, net)) => match ip {
+            SiemIp::V4(ip) => term(format!("\"{}/{net}\"", ipv4_to_str(*ip))),
+            SiemIp::V6(ip) => term(format!("\"{}/{net}\"", ipv6_to_str(*ip))),
+        },
+        RuleOperator::IsNull(_) => format!("NOT _exists_:{field}"),
+        RuleOperator::Any(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// Escapes the characters of the query string syntax, and the spaces, of an unquoted term.
+fn escape_term(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_whitespace() || "+-=&|><!(){}[]^\"~*?:\\/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes the body of a quoted phrase.
+fn escape_phrase(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Maps the logsource to one of the Security Analytics log types.
+fn security_analytics_category(logsource: &SigmaRuleLogSource) -> &'static str {
+    let product = logsource.product.as_deref().unwrap_or_default();
+    let category = logsource.category.as_deref().unwrap_or_default();
+    let service = logsource.service.as_deref().unwrap_or_default();
+    match (product, category, service) {
+        (_, "dns", _) => "dns",
+        (_, "firewall", _) | (_, "network", _) => "network",
+        (_, _, "cloudtrail") => "cloudtrail",
+        (_, _, "s3") => "s3",
+        ("windows", _, _) => "windows",
+        ("linux", _, _) => "linux",
+        ("macos", _, _) => "others_macos",
+        ("azure", _, _) => "azure",
+        ("m365", _, _) => "m365",
+        ("okta", _, _) => "okta",
+        ("github", _, _) => "github",
+        ("gcp", _, _) => "others_cloud",
+        (_, "proxy", _) => "others_proxy",
+        (_, "webserver", _) => "others_web",
+        _ => "others_application",
+    }
+}
+
+/// The event log channel of the services of the Windows logsources.
+fn windows_channel(logsource: &SigmaRuleLogSource) -> Option<&str> {
+    if logsource.product.as_deref() != Some("windows") {
+        return None;
+    }
+    Some(match logsource.service.as_deref()? {
+        "security" => "Security",
+        "system" => "System",
+        "application" => "Application",
+        "sysmon" => "Microsoft-Windows-Sysmon/Operational",
+        "powershell" => "Microsoft-Windows-PowerShell/Operational",
+        "powershell-classic" => "Windows PowerShell",
+        service => service,
+    })
+}
+
+/// The index pattern the events of the logsource are usually shipped to.
+fn index_pattern(logsource: &SigmaRuleLogSource) -> &'static str {
+    match logsource.product.as_deref().unwrap_or_default() {
+        "windows" => "winlogbeat-*",
+        "linux" => "auditbeat-*",
+        _ => "logs-*",
+    }
+}
 
 impl RequiresMappings for AWSOpenSearchBackend {
     fn get_field_map(&self) -> Option<HashMap<String, String>> {
@@ -102,343 +404,126 @@ impl RequiresMappings for AWSOpenSearchBackend {
 
 impl BackEnd for AWSOpenSearchBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        self.build_query(&sigma_rule)
+        match self.output {
+            OpenSearchOutput::Query => self.build_query(&sigma_rule),
+            OpenSearchOutput::SecurityAnalytics => self.build_security_analytics_rule(&sigma_rule),
+            OpenSearchOutput::Monitor => self.build_monitor(&sigma_rule),
+        }
     }
 }
 
 impl QueryBuilder for AWSOpenSearchBackend {
+    /// Renders the detection condition as a Lucene query string, restricted to the event
+    /// channel of Windows services.
     fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
-            Err(e) => {
-                eprintln!("{:?}", e);
-                exit(1);
-            }
-        };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            if let Some(service) = &rule.logsource.service {
-                let product_condition = RuleCondition {
-                    field: LogString::Owned("Channel".to_string()),
-                    operator: RuleOperator::Equals(SiemField::Text(LogString::Owned(
-                        service.to_string(),
-                    ))),
-                };
-                subrule_conditions.push(product_condition);
+        let query = render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            let field = if field.is_empty() {
+                String::new()
+            } else {
+                self.get_mapping(field.to_string())
             };
-
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let regex;
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\").replace('*', "");
-                        regex = format!("{}*", pattern);
-                        query_str.push_str(self.get_mapping(cond_name).as_str());
-                        query_str.push_str(format!(":{}", regex).as_str());
-                        // Map the condition name
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\").replace('*', "");
-                        regex = format!("*{}", pattern);
-                        query_str.push_str(self.get_mapping(cond_name).as_str());
-                        query_str.push_str(format!(":{}", regex).as_str());
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\").replace('*', "");
-                        regex = format!("*{}*", pattern);
-                        query_str.push_str(self.get_mapping(cond_name).as_str());
-                        query_str.push_str(format!(":{}", regex).as_str())
-                    }
-                    RuleOperator::Equals(field) => {
-                        match field {
-                            SiemField::Array(_) => {}
-                            _ => {
-                                // query_str.push('(');
-                                query_str.push_str(self.get_mapping(cond_name).as_str());
-                                query_str.push_str(format!(":{}", field).as_str());
-                                // query_str.push(')');
-                            }
-                        }
-                    }
-                    RuleOperator::Any(cond_list) => {
-                        let cond_name = self.get_mapping(cond_name);
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{}", val));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{}*", pattern));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("*{}", pattern));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("*{}*", pattern));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" OR ");
-                        query_str.push_str(format!("{cond_name}:({joined_sub_queries})").as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let cond_name = self.get_mapping(cond_name);
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{}:{}", cond_name, val));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{}:{}*", cond_name, pattern));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("{}:*{}", cond_name, pattern));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("{}:*{}*", cond_name, pattern));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" AND ");
-                        query_str.push_str(joined_sub_queries.to_string().as_str());
-                    }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
-            }
-            let mut condition_query = String::new();
-            condition_query.push('(');
-            condition_query.push_str(conditions_list.join(" AND ").as_str());
-            condition_query.push(')');
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
+            build_predicate(&field, operator)
+        });
+        match windows_channel(&rule.logsource) {
+            Some(channel) => join_predicates(
+                vec![
+                    format!(
+                        "{}:\"{}\"",
+                        self.get_mapping("Channel".to_string()),
+                        escape_phrase(channel)
+                    ),
+                    query,
+                ],
+                "AND",
+            ),
+            None => query,
         }
+    }
+}
 
-        // Check the conditions of this subrule
-        for condition in conditions {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" OR ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" AND ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" NOT ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" OR ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" AND ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" AND ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" OR ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        query_str.clone()
+#[cfg(test)]
+mod tests {
+    use crate::backend::aws_opensearch::{AWSOpenSearchBackend, OpenSearchOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+
+    #[test]
+    pub fn test_security_analytics_rule() {
+        let rule = include_str!("../sigma/components/rule/7zip_sigma_rule.yml");
+        let sigma_rule = parse_sigma_rule(rule).unwrap();
+        let backend = AWSOpenSearchBackend::new(OpenSearchOutput::SecurityAnalytics);
+        let output: serde_json::Value =
+            serde_json::from_str(&backend.convert_rule(sigma_rule)).unwrap();
+        assert_eq!(output["category"], "windows");
+        assert_eq!(output["rule"], rule);
+        assert_eq!(
+            output["mappings"]["alias_mappings"]["properties"]["ParentImage"]["path"],
+            "process.parent.executable"
+        );
+    }
+
+    #[test]
+    pub fn test_monitor() {
+        let rule = include_str!("../sigma/components/rule/7zip_sigma_rule.yml");
+        let sigma_rule = parse_sigma_rule(rule).unwrap();
+        let backend = AWSOpenSearchBackend::new(OpenSearchOutput::Monitor);
+        let output: serde_json::Value =
+            serde_json::from_str(&backend.convert_rule(sigma_rule)).unwrap();
+        assert_eq!(output["monitor_type"], "query_level_monitor");
+        assert_eq!(
+            output["triggers"][0]["query_level_trigger"]["severity"],
+            "2"
+        );
+        assert_eq!(output["inputs"][0]["search"]["indices"][0], "winlogbeat-*");
+        assert_eq!(
+            output["inputs"][0]["search"]["query"]["query"]["bool"]["filter"][0]["query_string"]
+                ["query"],
+            r#"(((process.executable:*\\cmd.exe OR winlog.event_data.OriginalFileName:"Cmd.Exe") AND process.parent.executable:*\\7zFM.exe) AND NOT ((winlog.event_data.CommandLine:*\/c* OR winlog.event_data.CommandLine:*\/k* OR winlog.event_data.CommandLine:*\/r*) OR NOT _exists_:winlog.event_data.CommandLine))"#
+        );
+    }
+
+    #[test]
+    pub fn test_monitor_aggregation() {
+        let sigma_rule = parse_sigma_rule(
+            r#"
+title: Password Spraying
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 1h
+    condition: (selection) | count(TargetUserName) by IpAddress > 20
+level: medium
+"#,
+        )
+        .unwrap();
+        let backend = AWSOpenSearchBackend::new(OpenSearchOutput::Monitor);
+        let output: serde_json::Value =
+            serde_json::from_str(&backend.convert_rule(sigma_rule.clone())).unwrap();
+        assert_eq!(output["schedule"]["period"]["interval"], 60);
+        let search = &output["inputs"][0]["search"]["query"];
+        // the monitor searches the query of the Query output
+        assert_eq!(
+            search["query"]["bool"]["filter"][0]["query_string"]["query"],
+            AWSOpenSearchBackend::default().convert_rule(sigma_rule.clone())
+        );
+        assert_eq!(
+            search["query"]["bool"]["filter"][0]["query_string"]["query"],
+            r#"(winlog.channel:"Security" AND winlog.event_id:4625)"#
+        );
+        assert_eq!(
+            search["query"]["bool"]["filter"][1]["range"]["@timestamp"]["gte"],
+            "{{period_end}}||-60m"
+        );
+        assert_eq!(
+            search["aggs"]["groups"]["aggs"]["metric"]["cardinality"]["field"],
+            "winlog.event_data.TargetUserName"
+        );
+        assert_eq!(
+            output["triggers"][0]["query_level_trigger"]["condition"]["script"]["source"],
+            "ctx.results[0].aggregations.groups.buckets.stream().anyMatch(b -> b.metric.value > 20)"
+        );
     }
 }
@@ -0,0 +1,401 @@
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::sigma::SigmaRule;
+use crate::prelude::rule::{RuleOperator, SiemRule};
+use crate::prelude::{AlertSeverity, SiemField, SiemIp};
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{join_predicates, parse_aggregation, render_condition, stable_hash};
+use chrono::NaiveDate;
+use serde_json::json;
+use std::collections::HashMap;
+use std::process::exit;
+
+/// Fields holding paths, which Carbon Black tokenizes on the path separators.
+const PATH_FIELDS: [&str; 6] = [
+    "process_name",
+    "parent_name",
+    "childproc_name",
+    "filemod_name",
+    "modload_name",
+    "regmod_name",
+];
+
+/// What the Carbon Black backend emits for a rule.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum CarbonBlackOutput {
+    /// The process search query.
+    #[default]
+    Query,
+    /// A watchlist report JSON, the query being its IOC.
+    WatchlistReport,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CarbonBlackBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: CarbonBlackOutput,
+}
+
+impl CarbonBlackBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: CarbonBlackOutput) -> Self {
+        CarbonBlackBackend { mappings, output }
+    }
+
+    /// Carbon Black matches single processes, so the aggregations cannot be converted.
+    fn check_aggregation(&self, sigma_rule: &SigmaRule) {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        if aggregation.is_some() {
+            eprintln!(
+                "Carbon Black matches single processes, the aggregation of '{}' is dropped",
+                sigma_rule.title
+            );
+        }
+    }
+
+    fn build_report(&self, sigma_rule: &SigmaRule) -> serde_json::Value {
+        self.check_aggregation(sigma_rule);
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let severity = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => 1,
+            AlertSeverity::LOW => 3,
+            AlertSeverity::MEDIUM => 5,
+            AlertSeverity::HIGH => 7,
+            AlertSeverity::CRITICAL => 10,
+        };
+        let id = report_id(sigma_rule);
+        // the rule date keeps the report stable across conversions
+        let timestamp = sigma_rule
+            .date
+            .as_deref()
+            .and_then(|date| {
+                NaiveDate::parse_from_str(date, "%Y/%m/%d")
+                    .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d"))
+                    .ok()
+            })
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|date| date.and_utc().timestamp())
+            .unwrap_or_default();
+        json!({
+            "id": id,
+            "timestamp": timestamp,
+            "title": sigma_rule.title,
+            "description": sigma_rule.description.as_deref().unwrap_or(&sigma_rule.title),
+            "severity": severity,
+            "link": sigma_rule
+                .references
+                .as_ref()
+                .and_then(|references| references.first()),
+            "tags": sigma_rule.tags.clone().unwrap_or_default(),
+            "iocs_v2": [{
+                "id": format!("{id}-query"),
+                "match_type": "query",
+                "values": [self.build_query(sigma_rule)],
+            }],
+        })
+    }
+}
+
+impl BackEnd for CarbonBlackBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        match self.output {
+            CarbonBlackOutput::Query => {
+                self.check_aggregation(&sigma_rule);
+                self.build_query(&sigma_rule)
+            }
+            CarbonBlackOutput::WatchlistReport => {
+                serde_json::to_string_pretty(&self.build_report(&sigma_rule)).unwrap_or_default()
+            }
+        }
+    }
+
+    /// The reports are gathered in a single document, as imported into a watchlist.
+    fn convert_rules(&self, sigma_rules: Vec<SigmaRule>) -> String {
+        match self.output {
+            CarbonBlackOutput::Query => sigma_rules
+                .into_iter()
+                .map(|sigma_rule| self.convert_rule(sigma_rule))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            CarbonBlackOutput::WatchlistReport => {
+                let reports: Vec<serde_json::Value> = sigma_rules
+                    .iter()
+                    .map(|sigma_rule| self.build_report(sigma_rule))
+                    .collect();
+                serde_json::to_string_pretty(&json!({ "reports": reports })).unwrap_or_default()
+            }
+        }
+    }
+}
+
+impl RequiresMappings for CarbonBlackBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        let mappings = HashMap::from([
+            ("ProcessId", "process_pid"),
+            ("Image", "process_name"),
+            ("OriginalFileName", "process_original_filename"),
+            ("Product", "process_product_name"),
+            ("Company", "process_company_name"),
+            ("Description", "process_file_description"),
+            ("CommandLine", "process_cmdline"),
+            ("User", "process_username"),
+            ("IntegrityLevel", "process_integrity_level"),
+            ("md5", "process_hash"),
+            ("sha256", "process_hash"),
+            ("Hashes", "process_hash"),
+            ("ParentProcessId", "parent_pid"),
+            ("ParentImage", "parent_name"),
+            ("ParentCommandLine", "parent_cmdline"),
+            ("SourceIp", "netconn_local_ipv4"),
+            ("SourcePort", "netconn_local_port"),
+            ("DestinationIp", "netconn_ipv4"),
+            ("DestinationPort", "netconn_port"),
+            ("DestinationHostname", "netconn_domain"),
+            ("QueryName", "netconn_domain"),
+            ("Protocol", "netconn_protocol"),
+            ("TargetFilename", "filemod_name"),
+            ("TargetObject", "regmod_name"),
+            ("ImageLoaded", "modload_name"),
+            ("Computer", "device_name"),
+            ("ComputerName", "device_name"),
+        ]);
+        let field = args.unwrap_or_default();
+        match mappings.get(field.as_str()) {
+            Some(mapping) => mapping.to_string(),
+            _ => field,
+        }
+    }
+}
+
+impl QueryBuilder for CarbonBlackBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            // keywords are searched in all the fields
+            let field = if field.is_empty() {
+                String::new()
+            } else {
+                self.get_mapping(field.to_string())
+            };
+            build_predicate(&rule.title, &field, operator)
+        })
+    }
+}
+
+fn build_predicate(title: &str, field: &str, operator: &RuleOperator) -> String {
+    let search = |value: String| {
+        if field.is_empty() {
+            value
+        } else {
+            format!("{field}:{value}")
+        }
+    };
+    let leading_wildcard = |value: String| {
+        eprintln!(
+            "Carbon Black doesn't support leading wildcards, '{}' searches {}",
+            title,
+            search(value.clone())
+        );
+        search(value)
+    };
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => search(format!(
+            "({})",
+            values
+                .iter()
+                .map(|value| escape_value(value))
+                .collect::<Vec<String>>()
+                .join(" OR ")
+        )),
+        RuleOperator::Equals(value) => search(escape_value(&value.to_string())),
+        RuleOperator::StartsWith(pattern) => search(format!("{}*", escape_value(pattern))),
+        RuleOperator::EndsWith(pattern) => match path_suffix(field, pattern) {
+            // path fields are tokenized, a suffix from a separator on is a token search
+            Some(token) => search(escape_value(token)),
+            None => leading_wildcard(format!("*{}", escape_value(pattern))),
+        },
+        RuleOperator::Contains(pattern) => leading_wildcard(format!("*{}*", escape_value(pattern))),
+        RuleOperator::GT(value) => search(format!("{{{value} TO *]")),
+        RuleOperator::GTE(value) => search(format!("[{value} TO *]")),
+        RuleOperator::LT(value) => search(format!("[* TO {value}}}")),
+        RuleOperator::LTE(value) => search(format!("[* TO {value}]")),
+        RuleOperator::SameNet((ip, net)) => {
+            let address = match ip {
+                SiemIp::V4(ip) => ipv4_to_str(*ip),
+                SiemIp::V6(ip) => ipv6_to_str(*ip),
+            };
+            search(format!("{address}\\/{net}"))
+        }
+        RuleOperator::IsNull(_) if !field.is_empty() => format!("-{field}:*"),
+        RuleOperator::Matches(regex) => {
+            eprintln!(
+                "Carbon Black has no regular expressions, '{}' drops the condition on '{field}' matching '{}'",
+                title,
+                regex.as_str()
+            );
+            String::new()
+        }
+        RuleOperator::Any(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(title, field, operator))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(title, field, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(title, field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// The last path component of a suffix starting at a separator, on the path fields.
+fn path_suffix<'a>(field: &str, pattern: &'a str) -> Option<&'a str> {
+    if !PATH_FIELDS.contains(&field) {
+        return None;
+    }
+    let token = pattern.strip_prefix(['\\', '/'])?;
+    if token.is_empty() || token.contains(['\\', '/']) {
+        None
+    } else {
+        Some(token)
+    }
+}
+
+/// Escapes the characters of the query syntax, the whitespaces included so the values
+/// are searched as a whole.
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_whitespace() || "+-&|!(){}[]^\"~*?:\\/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Deterministic report id, so a converted rule replaces its report on import.
+fn report_id(rule: &SigmaRule) -> String {
+    match &rule.id {
+        Some(id) => format!("sigma-{id}"),
+        None => format!("sigma-{:016x}", stable_hash(&rule.title)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::carbon_black::{build_predicate, CarbonBlackBackend, CarbonBlackOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+    use crate::prelude::rule::RuleOperator;
+    use crate::prelude::SiemField;
+
+    #[test]
+    pub fn test_query() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        assert_eq!(
+            CarbonBlackBackend::default().convert_rule(rule),
+            r#"(((process_name:cmd.exe OR process_original_filename:Cmd.Exe) AND parent_name:7zFM.exe) AND NOT ((process_cmdline:*\/c* OR process_cmdline:*\/k* OR process_cmdline:*\/r*) OR -process_cmdline:*))"#
+        );
+    }
+
+    #[test]
+    pub fn test_watchlist_report() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Internal SMB From Shell
+id: 5a1b2c3d-0000-4000-8000-000000000001
+date: 2023/01/02
+description: SMB connections of a shell
+references:
+    - https://attack.mitre.org/techniques/T1021/002/
+logsource:
+    product: windows
+    category: network_connection
+detection:
+    selection:
+        DestinationIp|cidr: 10.0.0.0/8
+        DestinationPort: 445
+        Image|endswith: \cmd.exe
+    condition: selection
+level: high
+"#,
+        )
+        .unwrap();
+        let backend = CarbonBlackBackend::new(None, CarbonBlackOutput::WatchlistReport);
+        let watchlist: serde_json::Value =
+            serde_json::from_str(&backend.convert_rules(vec![rule])).unwrap();
+        let report = &watchlist["reports"][0];
+        assert_eq!(report["id"], "sigma-5a1b2c3d-0000-4000-8000-000000000001");
+        assert_eq!(report["timestamp"], 1672617600);
+        assert_eq!(report["severity"], 7);
+        assert_eq!(
+            report["iocs_v2"][0]["values"][0],
+            r#"(netconn_ipv4:10.0.0.0\/8 AND netconn_port:445 AND process_name:cmd.exe)"#
+        );
+    }
+
+    #[test]
+    pub fn test_report_id_without_sigma_id() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 10m
+    condition: selection | count() by IpAddress > 10
+"#,
+        )
+        .unwrap();
+        let backend = CarbonBlackBackend::new(None, CarbonBlackOutput::WatchlistReport);
+        let report: serde_json::Value = serde_json::from_str(&backend.convert_rule(rule)).unwrap();
+        assert_eq!(report["id"], "sigma-aa34a28ef1cc572a");
+    }
+
+    #[test]
+    pub fn test_ranges() {
+        let ranges: Vec<String> = [
+            RuleOperator::GT(SiemField::U32(1024)),
+            RuleOperator::GTE(SiemField::U32(1024)),
+            RuleOperator::LT(SiemField::U32(1024)),
+            RuleOperator::LTE(SiemField::U32(1024)),
+        ]
+        .iter()
+        .map(|operator| build_predicate("Ports", "netconn_port", operator))
+        .collect();
+        assert_eq!(
+            ranges,
+            [
+                "netconn_port:{1024 TO *]",
+                "netconn_port:[1024 TO *]",
+                "netconn_port:[* TO 1024}",
+                "netconn_port:[* TO 1024]"
+            ]
+        );
+    }
+}
@@ -1,10 +1,8 @@
 use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
-use crate::prelude::rule::sigma::{
-    ConditionExpression, FalsePositives, SigmaDetectionCondition, SigmaRule,
-};
+use crate::prelude::rule::sigma::{FalsePositives, SigmaRule, SigmaRuleLogSource};
 use crate::prelude::rule::{RuleOperator, SiemRule};
-use crate::prelude::SiemField;
-use crate::utils::parse_condition;
+use crate::prelude::{AlertSeverity, SiemField};
+use crate::utils::{escape_string, join_predicates, parse_aggregation, render_condition};
 use std::collections::HashMap;
 use std::process::exit;
 
@@ -206,446 +204,330 @@ impl RequiresMappings for ChronicleBackend {
 
 impl BackEnd for ChronicleBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        let mut meta = vec![];
-        meta.push("version = \"0.01\"".to_string());
-        if let Some(author) = &sigma_rule.author {
-            meta.push(format!("author = \"{author}\""));
-        }
-        if let Some(description) = &sigma_rule.description {
-            meta.push(format!("description = \"{description}\""));
-        }
-        if let Some(reference) = &sigma_rule.references {
-            meta.push(format!("reference = \"{}\"", reference.join(", ")));
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let mut events = vec![];
+        if let Some(event_type) = event_type(&sigma_rule.logsource) {
+            events.push(format!("$e.metadata.event_type = \"{event_type}\""));
         }
-        if let Some(id) = &sigma_rule.id {
-            meta.push(format!("sigma_id = \"{id}\""));
+        events.push(self.build_query(&sigma_rule));
+
+        let mut sections = vec![];
+        let mut condition = "$e".to_string();
+        if let Some(aggregation) = aggregation {
+            // multi event rules need a match section, without group-by the events are matched
+            // per host
+            let (placeholder, field) = match &aggregation.group_by {
+                Some(group_by) => (
+                    yaral_identifier(group_by),
+                    self.get_mapping(group_by.to_string()),
+                ),
+                None => ("hostname".to_string(), "principal.hostname".to_string()),
+            };
+            events.push(format!("${placeholder} = $e.{field}"));
+            sections.push(format!(
+                "    match:\n        ${placeholder} over {}",
+                sigma_rule.detection.timeframe.as_deref().unwrap_or("5m")
+            ));
+            let outcome = match &aggregation.field {
+                Some(field) => format!(
+                    "{}($e.{})",
+                    outcome_function(&aggregation.function),
+                    self.get_mapping(field.to_string())
+                ),
+                None => "count_distinct($e.metadata.id)".to_string(),
+            };
+            sections.push(format!(
+                "    outcome:\n        $event_count = {outcome}\n        $risk_score = {}",
+                risk_score(&sigma_rule)
+            ));
+            condition = format!(
+                "$e and $event_count {} {}",
+                outcome_operator(&aggregation.operator),
+                aggregation.value
+            );
         }
-        if let Some(status) = &sigma_rule.status {
-            meta.push(format!("status = \"{status}\""));
+
+        let mut rule = format!(
+            "rule {} {{\n    meta:\n        {}\n    events:\n        {}\n",
+            yaral_identifier(&sigma_rule.title),
+            build_meta(&sigma_rule).join("\n        "),
+            events.join("\n        ")
+        );
+        for section in sections {
+            rule.push_str(&section);
+            rule.push('\n');
         }
-        if let Some(tags) = &sigma_rule.tags {
-            meta.push(format!("tags = \"{}\"", tags.join(", ")));
+        rule.push_str(&format!("    condition:\n        {condition}\n}}\n"));
+        rule
+    }
+}
+
+impl QueryBuilder for ChronicleBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        render_condition(rule, ("and", "or", "not"), &|field, operator| {
+            build_predicate(
+                &format!("$e.{}", self.get_mapping(field.to_string())),
+                operator,
+            )
+        })
+    }
+}
+
+/// Builds the predicate of a single field condition on an already mapped `$e.` field.
+fn build_predicate(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => join_predicates(
+            values
+                .iter()
+                .map(|value| text_predicate(field, value))
+                .collect(),
+            "or",
+        ),
+        RuleOperator::Equals(SiemField::Text(value)) => text_predicate(field, value),
+        // product_event_type is a string in UDM, even for numeric event ids
+        RuleOperator::Equals(value) if field.ends_with("metadata.product_event_type") => {
+            format!("{field} = \"{value}\"")
         }
-        if let Some(false_positives) = &sigma_rule.falsepositives {
-            meta.push(format!("falsepositives = \"{}\"", {
-                match false_positives {
-                    FalsePositives::Single(val) => val.to_string(),
-                    FalsePositives::List(vals) => vals.join(", "),
-                }
-            }));
+        RuleOperator::Equals(value) => format!("{field} = {value}"),
+        RuleOperator::StartsWith(pattern) => {
+            format!("re.regex({field}, `^{}`) nocase", escape_regex(pattern))
         }
-        if let Some(severity) = &sigma_rule.level {
-            meta.push(format!("severity = \"{severity}\""));
+        RuleOperator::EndsWith(pattern) => {
+            format!("re.regex({field}, `{}$`) nocase", escape_regex(pattern))
         }
-        if let Some(created) = &sigma_rule.date {
-            // TODO: Properly format the date
-            meta.push(format!("severity = \"{created}\""));
+        RuleOperator::Contains(pattern) => {
+            format!("re.regex({field}, `{}`) nocase", escape_regex(pattern))
         }
-        if let Some(product) = &sigma_rule.logsource.product {
-            meta.push(format!("product = \"{product}\""));
+        RuleOperator::Matches(regex) => {
+            format!(
+                "re.regex({field}, `{}`)",
+                regex.as_str().replace('`', "\\x60")
+            )
         }
-        if let Some(service) = &sigma_rule.logsource.service {
-            meta.push(format!("service = \"{service}\""));
+        RuleOperator::IsNull(_) => format!("{field} = \"\""),
+        RuleOperator::Any(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "or",
+        ),
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "and",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("not {predicate}")
+            }
         }
-        let query = self.build_query(&sigma_rule);
-        let siem_rule: SiemRule = sigma_rule.clone().into();
-        let rule = format!(
-            r#"rule {} {{
-    meta:
-        {}
-    events:
-        {}
-    condition:
-        ${}
-}}
-        "#,
-            sigma_rule.title.to_lowercase().replace([' ', '-'], "_"),
-            meta.join("\n\t\t"),
-            query,
-            siem_rule
-                .subrules
-                .as_ref()
-                .into_iter()
-                .collect::<Vec<_>>()
-                .first()
-                .unwrap()
-                .0
-        );
-
-        rule
+        _ => String::new(),
     }
 }
 
-impl QueryBuilder for ChronicleBackend {
-    fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
-            Err(e) => {
-                eprintln!("{:?}", e);
-                exit(1);
-            }
+fn text_predicate(field: &str, value: &str) -> String {
+    format!("{field} = \"{}\" nocase", escape_string(value))
+}
+
+/// Escapes a sigma value into a RE2 pattern. Backticks can't be escaped in YARA-L raw strings
+/// so they're written as `\x60`.
+fn escape_regex(value: &str) -> String {
+    regex::escape(value).replace('`', "\\x60")
+}
+
+fn build_meta(sigma_rule: &SigmaRule) -> Vec<String> {
+    let mut meta = vec![];
+    let mut push = |key: &str, value: &str| {
+        meta.push(format!("{key} = \"{}\"", escape_string(value.trim())));
+    };
+    push("version", "0.01");
+    if let Some(author) = &sigma_rule.author {
+        push("author", author);
+    }
+    if let Some(description) = &sigma_rule.description {
+        push("description", description);
+    }
+    if let Some(reference) = &sigma_rule.references {
+        push("reference", &reference.join(", "));
+    }
+    if let Some(id) = &sigma_rule.id {
+        push("sigma_id", id);
+    }
+    if let Some(status) = &sigma_rule.status {
+        push("status", status);
+    }
+    if let Some(tags) = &sigma_rule.tags {
+        push("tags", &tags.join(", "));
+    }
+    if let Some(false_positives) = &sigma_rule.falsepositives {
+        push(
+            "falsepositives",
+            &match false_positives {
+                FalsePositives::Single(val) => val.to_string(),
+                FalsePositives::List(vals) => vals.join(", "),
+            },
+        );
+    }
+    if sigma_rule.level.is_some() {
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let (severity, priority) = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => ("Informational", "Info"),
+            AlertSeverity::LOW => ("Low", "Low"),
+            AlertSeverity::MEDIUM => ("Medium", "Medium"),
+            AlertSeverity::HIGH => ("High", "High"),
+            AlertSeverity::CRITICAL => ("Critical", "Critical"),
         };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let mapped_cond_name = self.get_mapping(cond_name);
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!(
-                                "re.regex(${sub_rule_name}.{}, `{}.*`)",
-                                mapped_cond_name.as_str(),
-                                pattern
-                            )
-                            .as_str(),
-                        );
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!(
-                                "re.regex(${sub_rule_name}.{}, `.*{}`)",
-                                mapped_cond_name.as_str(),
-                                pattern
-                            )
-                            .as_str(),
-                        );
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!(
-                                "re.regex(${sub_rule_name}.{}, `.*{}.*`)",
-                                mapped_cond_name.as_str(),
-                                pattern
-                            )
-                            .as_str(),
-                        );
-                    }
-                    RuleOperator::Equals(field) => match field {
-                        SiemField::Array(elements) => {
-                            let joined_query = elements
-                                .iter()
-                                .map(|val| {
-                                    format!("${sub_rule_name}.{} = \"{}\"", mapped_cond_name, val)
-                                })
-                                .collect::<Vec<_>>()
-                                .join(" or ");
-                            query_str.push_str(format!("({joined_query})").as_str())
-                        }
-                        _ => {
-                            query_str.push_str(
-                                format!(
-                                    "${sub_rule_name}.{} = \"{}\"",
-                                    mapped_cond_name.as_str(),
-                                    field
-                                )
-                                .as_str(),
-                            );
-                        }
-                    },
-                    RuleOperator::Any(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!(
-                                        "${sub_rule_name}.{} = \"{}\"",
-                                        cond_name, val
-                                    ));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "re.regex(${sub_rule_name}.{}, `{}.*`)",
-                                        cond_name, pattern
-                                    ));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "re.regex(${sub_rule_name}.{}, `.*{}`)",
-                                        cond_name, pattern
-                                    ));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!(
-                                        "re.regex(${sub_rule_name}.{}, `.*{}.*`)",
-                                        cond_name, pattern
-                                    ));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" or ");
-                        query_str.push_str(format!("({joined_sub_queries})",).as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!(
-                                        "${sub_rule_name}.{} = \"{}\"",
-                                        cond_name, val
-                                    ));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "re.regex(${sub_rule_name}.{}, `{}.*`)",
-                                        cond_name, pattern
-                                    ));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "re.regex(${sub_rule_name}.{}, `.*{}`)",
-                                        cond_name, pattern
-                                    ));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!(
-                                        "re.regex(${sub_rule_name}.{}, `.*{}.*`)",
-                                        cond_name, pattern
-                                    ));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" and ");
-                        query_str.push_str(format!("({joined_sub_queries})",).as_str());
-                    }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
-            }
-            let mut condition_query = String::new();
-            condition_query.push_str(format!("({})", conditions_list.join(" and ")).as_str());
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
-        }
+        push("severity", severity);
+        push("priority", priority);
+    }
+    if let Some(created) = &sigma_rule.date {
+        push("created", &created.replace('/', "-"));
+    }
+    if let Some(product) = &sigma_rule.logsource.product {
+        push("product", product);
+    }
+    if let Some(service) = &sigma_rule.logsource.service {
+        push("service", service);
+    }
+    meta
+}
 
-        // Check the conditions of this subrule
-        for condition in conditions {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" or ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" and ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" not ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" or ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" and ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" and ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" or ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" and ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" not ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" or ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" and ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" or ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" or ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" and ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" not ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" or ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" and ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-            }
+/// YARA-L rule and variable names only allow letters, digits and underscores and can't start with a digit.
+fn yaral_identifier(title: &str) -> String {
+    let mut name = String::with_capacity(title.len());
+    for c in title.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else if !name.ends_with('_') {
+            name.push('_');
         }
-        query_str.clone()
+    }
+    let name = name.trim_matches('_');
+    if name.starts_with(|c: char| c.is_ascii_digit()) || name.is_empty() {
+        format!("sigma_{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+fn risk_score(sigma_rule: &SigmaRule) -> u8 {
+    match sigma_rule.level.as_deref().unwrap_or_default() {
+        "critical" => 100,
+        "high" => 75,
+        "medium" => 50,
+        "low" => 25,
+        _ => 10,
+    }
+}
+
+fn outcome_function(function: &str) -> &'static str {
+    match function {
+        "min" => "min",
+        "max" => "max",
+        "avg" => "avg",
+        "sum" => "sum",
+        _ => "count_distinct",
+    }
+}
+
+fn outcome_operator(operator: &str) -> &str {
+    match operator {
+        "==" => "=",
+        operator => operator,
+    }
+}
+
+/// UDM event type of the sigma logsource category, if any.
+fn event_type(logsource: &SigmaRuleLogSource) -> Option<&'static str> {
+    Some(match logsource.category.as_deref().unwrap_or_default() {
+        "process_creation" => "PROCESS_LAUNCH",
+        "process_termination" => "PROCESS_TERMINATION",
+        "process_access" => "PROCESS_OPEN",
+        "create_remote_thread" => "PROCESS_INJECTION",
+        "image_load" | "driver_load" => "PROCESS_MODULE_LOAD",
+        "network_connection" => "NETWORK_CONNECTION",
+        "dns_query" | "dns" => "NETWORK_DNS",
+        "file_event" => "FILE_CREATION",
+        "file_delete" => "FILE_DELETION",
+        "file_change" => "FILE_MODIFICATION",
+        "registry_add" => "REGISTRY_CREATION",
+        "registry_set" => "REGISTRY_MODIFICATION",
+        "registry_delete" => "REGISTRY_DELETION",
+        "proxy" => "NETWORK_HTTP",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::chronicle::ChronicleBackend;
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+
+    #[test]
+    pub fn test_rule_condition() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let yaral = ChronicleBackend::default().convert_rule(rule);
+        assert!(yaral.starts_with("rule suspicious_7zip_subprocess {\n"));
+        assert!(yaral.contains("        severity = \"High\"\n        priority = \"High\"\n        created = \"2022-04-17\"\n"));
+        assert!(yaral.contains("        $e.metadata.event_type = \"PROCESS_LAUNCH\"\n        (((re.regex($e.target.process.file.full_path, `\\\\cmd\\.exe$`) nocase or $e.target.file.full_path = \"Cmd.Exe\" nocase) and re.regex($e.src.process.file.full_path, `\\\\7zFM\\.exe$`) nocase) and not ((re.regex($e.target.process.command_line, `/c`) nocase or "));
+        assert!(yaral.contains(" or $e.target.process.command_line = \"\"))\n"));
+        assert!(yaral.ends_with("    condition:\n        $e\n}\n"));
+    }
+
+    #[test]
+    pub fn test_correlation_rule() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 10m
+    condition: selection | count() by IpAddress > 10
+level: medium
+"#,
+        )
+        .unwrap();
+        let yaral = ChronicleBackend::default().convert_rule(rule);
+        assert!(yaral.contains("        $e.metadata.product_event_type = \"4625\"\n        $ipaddress = $e.principal.ip\n    match:\n        $ipaddress over 10m\n    outcome:\n        $event_count = count_distinct($e.metadata.id)\n"));
+        assert!(yaral.ends_with("    condition:\n        $e and $event_count > 10\n}\n"));
+    }
+
+    #[test]
+    pub fn test_correlation_rule_without_group_by() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 5m
+    condition: selection | count() > 10
+"#,
+        )
+        .unwrap();
+        let yaral = ChronicleBackend::default().convert_rule(rule);
+        assert!(yaral.contains("        $hostname = $e.principal.hostname\n    match:\n        $hostname over 5m\n    outcome:\n"));
+        assert!(yaral.ends_with("    condition:\n        $e and $event_count > 10\n}\n"));
     }
 }
@@ -0,0 +1,426 @@
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::sigma::{ConditionAggregation, SigmaRule, SigmaRuleLogSource};
+use crate::prelude::rule::{RuleOperator, SiemRule};
+use crate::prelude::{AlertSeverity, SiemField, SiemIp};
+use crate::sigma::components::mitre::MitreTactics;
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{
+    escape_string, join_predicates, logsource_mapping, parse_aggregation, render_condition,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::process::exit;
+
+/// What the Cortex XDR backend emits for a rule.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum CortexXdrOutput {
+    /// The XQL query.
+    #[default]
+    Query,
+    /// A BIOC rule JSON, as taken by the BIOC insert API.
+    Bioc,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CortexXdrBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: CortexXdrOutput,
+}
+
+impl CortexXdrBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: CortexXdrOutput) -> Self {
+        CortexXdrBackend { mappings, output }
+    }
+
+    /// The dataset of the logsource, which the `logsource.*` mappings can override.
+    fn dataset(&self, logsource: &SigmaRuleLogSource) -> String {
+        logsource_mapping(self.mappings.as_ref(), logsource)
+            .unwrap_or_else(|| "xdr_data".to_string())
+    }
+
+    /// The XDR field of a sigma field. The process fields are the ones of the created
+    /// process (`action_process_*`) in process creation events, and the ones of the process
+    /// acting (`actor_process_*`) in the other events.
+    fn field(&self, field: &str, category: &str) -> String {
+        if let Some(mapped) = self
+            .mappings
+            .as_ref()
+            .and_then(|mappings| mappings.get(field))
+        {
+            return mapped.to_string();
+        }
+        if category != "process_creation" {
+            let actor = match field {
+                "Image" => Some("actor_process_image_path"),
+                "CommandLine" => Some("actor_process_command_line"),
+                "ProcessId" => Some("actor_process_os_pid"),
+                "User" => Some("actor_effective_username"),
+                "IntegrityLevel" => Some("actor_process_integrity_level"),
+                "CurrentDirectory" => Some("actor_process_cwd"),
+                _ => None,
+            };
+            if let Some(actor) = actor {
+                return actor.to_string();
+            }
+        }
+        let mapped = self.get_default_field_name(Some(field.to_string()));
+        if mapped == field {
+            eprintln!(
+                "Cortex XDR has no '{field}' field, map it with a '{field}: <XDR field>' mapping"
+            );
+        }
+        mapped
+    }
+
+    /// The XQL stages selecting the events: dataset and event type filter. Sigma values match
+    /// regardless of the case, as the string comparisons once case sensitivity is turned off.
+    fn source(&self, logsource: &SigmaRuleLogSource) -> String {
+        let dataset = format!(
+            "config case_sensitive = false\n| dataset = {}",
+            self.dataset(logsource)
+        );
+        let event_type = match logsource.category.as_deref().unwrap_or_default() {
+            "process_creation" => {
+                Some("event_type = ENUM.PROCESS and event_sub_type = ENUM.PROCESS_START")
+            }
+            "network_connection" | "firewall" => Some("event_type = ENUM.NETWORK"),
+            "file_event" | "file_change" | "file_rename" | "file_delete" | "file_access" => {
+                Some("event_type = ENUM.FILE")
+            }
+            "registry_event" | "registry_add" | "registry_set" | "registry_delete" => {
+                Some("event_type = ENUM.REGISTRY")
+            }
+            "image_load" | "driver_load" => Some("event_type = ENUM.LOAD_IMAGE"),
+            "dns_query" => Some("event_type = ENUM.NETWORK and dns_query_name != null"),
+            _ => None,
+        };
+        match event_type {
+            Some(event_type) => format!("{dataset}\n| filter {event_type}"),
+            None => dataset,
+        }
+    }
+
+    fn build_xql(&self, rule: &SigmaRule, aggregation: Option<&ConditionAggregation>) -> String {
+        let category = rule.logsource.category.as_deref().unwrap_or_default();
+        let mut xql = self.source(&rule.logsource);
+        let filter = self.build_query(rule);
+        if !filter.is_empty() {
+            xql.push_str(&format!("\n| filter {filter}"));
+        }
+        let aggregation = match aggregation {
+            Some(aggregation) => aggregation,
+            None => return xql,
+        };
+        let function = match (aggregation.function.as_ref(), &aggregation.field) {
+            ("count", Some(field)) => format!("count_distinct({})", self.field(field, category)),
+            ("count", None) => "count()".to_string(),
+            (function, Some(field)) => format!("{function}({})", self.field(field, category)),
+            (function, None) => format!("{function}()"),
+        };
+        xql.push_str(&format!("\n| comp {function} as event_count"));
+        if let Some(group_by) = &aggregation.group_by {
+            xql.push_str(&format!(" by {}", self.field(group_by, category)));
+        }
+        let operator = match aggregation.operator.as_ref() {
+            "==" => "=",
+            operator => operator,
+        };
+        xql.push_str(&format!(
+            "\n| filter event_count {operator} {}",
+            aggregation.value
+        ));
+        match &rule.detection.timeframe {
+            Some(timeframe) => format!("config timeframe = {timeframe}\n| {xql}"),
+            None => xql,
+        }
+    }
+
+    fn build_bioc(&self, sigma_rule: &SigmaRule, aggregated: bool) -> String {
+        if aggregated {
+            eprintln!(
+                "BIOC rules match single events, the aggregation of '{}' is dropped",
+                sigma_rule.title
+            );
+        }
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let severity = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => "SEV_010_INFO",
+            AlertSeverity::LOW => "SEV_020_LOW",
+            AlertSeverity::MEDIUM => "SEV_030_MEDIUM",
+            AlertSeverity::HIGH => "SEV_040_HIGH",
+            AlertSeverity::CRITICAL => "SEV_050_CRITICAL",
+        };
+        let bioc = json!({
+            "name": sigma_rule.title,
+            "type": siem_rule.mitre.tactics.first().map(bioc_type).unwrap_or("OTHER"),
+            "severity": severity,
+            "comment": sigma_rule.description.as_deref().unwrap_or_default(),
+            "status": "ENABLED",
+            "is_xql": true,
+            "indicator": self.build_xql(sigma_rule, None),
+            "mitre_tactic_id_and_name": siem_rule
+                .mitre
+                .tactics
+                .iter()
+                .map(|tactic| format!("{tactic:?} - {}", tactic.name()))
+                .collect::<Vec<String>>(),
+            "mitre_technique_id_and_name": siem_rule
+                .mitre
+                .techniques
+                .iter()
+                .map(|technique| technique.to_string())
+                .collect::<Vec<String>>(),
+        });
+        serde_json::to_string_pretty(&bioc).unwrap_or_default()
+    }
+}
+
+impl BackEnd for CortexXdrBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        match self.output {
+            CortexXdrOutput::Query => self.build_xql(&sigma_rule, aggregation.as_ref()),
+            CortexXdrOutput::Bioc => self.build_bioc(&sigma_rule, aggregation.is_some()),
+        }
+    }
+}
+
+impl RequiresMappings for CortexXdrBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        let mappings = HashMap::from([
+            ("ProcessId", "action_process_os_pid"),
+            ("Image", "action_process_image_path"),
+            ("OriginalFileName", "action_process_image_name"),
+            ("Product", "action_process_signature_product"),
+            ("Company", "action_process_signature_vendor"),
+            ("CommandLine", "action_process_image_command_line"),
+            ("CurrentDirectory", "action_process_cwd"),
+            ("User", "action_process_username"),
+            ("IntegrityLevel", "action_process_integrity_level"),
+            ("md5", "action_process_image_md5"),
+            ("sha256", "action_process_image_sha256"),
+            ("ParentProcessId", "actor_process_os_pid"),
+            ("ParentImage", "actor_process_image_path"),
+            ("ParentCommandLine", "actor_process_command_line"),
+            ("ParentUser", "actor_effective_username"),
+            ("SourceIp", "action_local_ip"),
+            ("SourcePort", "action_local_port"),
+            ("DestinationIp", "action_remote_ip"),
+            ("DestinationPort", "action_remote_port"),
+            ("DestinationHostname", "action_external_hostname"),
+            ("Protocol", "action_network_protocol"),
+            ("TargetFilename", "action_file_path"),
+            ("TargetObject", "action_registry_key_name"),
+            ("Details", "action_registry_data"),
+            ("ImageLoaded", "action_module_path"),
+            ("QueryName", "dns_query_name"),
+            ("Computer", "agent_hostname"),
+            ("ComputerName", "agent_hostname"),
+        ]);
+        let field = args.unwrap_or_default();
+        match mappings.get(field.as_str()) {
+            Some(mapping) => mapping.to_string(),
+            _ => field,
+        }
+    }
+}
+
+impl QueryBuilder for CortexXdrBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        let category = rule.logsource.category.as_deref().unwrap_or_default();
+        render_condition(rule, ("and", "or", "not"), &|field, operator| {
+            build_predicate(&self.field(field, category), operator)
+        })
+    }
+}
+
+fn build_predicate(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => in_list(field, values),
+        RuleOperator::Equals(SiemField::Text(value)) => {
+            format!("{field} = \"{}\"", escape_string(value))
+        }
+        RuleOperator::Equals(value) => format!("{field} = {value}"),
+        RuleOperator::StartsWith(pattern) => {
+            format!(
+                "{field} ~= \"(?i)^{}\"",
+                escape_string(&regex::escape(pattern))
+            )
+        }
+        RuleOperator::EndsWith(pattern) => {
+            format!(
+                "{field} ~= \"(?i){}$\"",
+                escape_string(&regex::escape(pattern))
+            )
+        }
+        RuleOperator::Contains(pattern) => {
+            format!("{field} contains \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::Matches(regex) => {
+            format!("{field} ~= \"{}\"", escape_string(regex.as_str()))
+        }
+        RuleOperator::SameNet((ip, net)) => {
+            let address = match ip {
+                SiemIp::V4(ip) => ipv4_to_str(*ip),
+                SiemIp::V6(ip) => ipv6_to_str(*ip),
+            };
+            format!("{field} incidr \"{address}/{net}\"")
+        }
+        RuleOperator::GT(value) => format!("{field} > {value}"),
+        RuleOperator::GTE(value) => format!("{field} >= {value}"),
+        RuleOperator::LT(value) => format!("{field} < {value}"),
+        RuleOperator::LTE(value) => format!("{field} <= {value}"),
+        RuleOperator::IsNull(_) => format!("{field} = null"),
+        RuleOperator::Any(operators) => {
+            // Several text equalities of the same field are a list membership
+            let values: Option<Vec<String>> = operators
+                .iter()
+                .map(|operator| match operator.as_ref() {
+                    RuleOperator::Equals(SiemField::Text(value)) => Some(value.to_string()),
+                    _ => None,
+                })
+                .collect();
+            match values {
+                Some(values) if values.len() > 1 => in_list(field, &values),
+                _ => join_predicates(
+                    operators
+                        .iter()
+                        .map(|operator| build_predicate(field, operator))
+                        .collect(),
+                    "or",
+                ),
+            }
+        }
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "and",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("not {predicate}")
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+fn in_list<T: AsRef<str>>(field: &str, values: &[T]) -> String {
+    format!(
+        "{field} in ({})",
+        values
+            .iter()
+            .map(|value| format!("\"{}\"", escape_string(value.as_ref())))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+/// The BIOC rule type of an ATT&CK tactic.
+fn bioc_type(tactic: &MitreTactics) -> &'static str {
+    match tactic.name() {
+        "Collection" => "COLLECTION",
+        "Credential Access" => "CREDENTIAL_ACCESS",
+        "Defense Evasion" => "EVASION",
+        "Execution" => "EXECUTION",
+        "Exfiltration" => "EXFILTRATION",
+        "Initial Access" => "INFILTRATION",
+        "Lateral Movement" => "LATERAL_MOVEMENT",
+        "Persistence" => "PERSISTENCE",
+        "Privilege Escalation" => "PRIVILEGE_ESCALATION",
+        "Reconnaissance" | "Discovery" => "RECONNAISSANCE",
+        "Impact" => "TAMPERING",
+        _ => "OTHER",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::cortex_xdr::{CortexXdrBackend, CortexXdrOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+
+    #[test]
+    pub fn test_xql() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Suspicious Shell From Office
+logsource:
+    product: windows
+    category: process_creation
+detection:
+    selection:
+        ParentImage|endswith: \winword.exe
+        Image|endswith:
+            - \cmd.exe
+            - \powershell.exe
+    filter:
+        CommandLine|contains: ScanTool
+    condition: selection and not filter
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            CortexXdrBackend::default().convert_rule(rule),
+            r#"config case_sensitive = false
+| dataset = xdr_data
+| filter event_type = ENUM.PROCESS and event_sub_type = ENUM.PROCESS_START
+| filter (((action_process_image_path ~= "(?i)\\\\cmd\\.exe$" or action_process_image_path ~= "(?i)\\\\powershell\\.exe$") and actor_process_image_path ~= "(?i)\\\\winword\\.exe$") and not action_process_image_command_line contains "ScanTool")"#
+        );
+    }
+
+    #[test]
+    pub fn test_bioc() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Outbound SMB To Internal Network
+description: SMB connections of a shell
+logsource:
+    product: windows
+    category: network_connection
+detection:
+    selection:
+        DestinationIp|cidr: 10.0.0.0/8
+        DestinationPort: 445
+        Image: C:\Windows\System32\cmd.exe
+    condition: selection
+level: high
+tags:
+    - attack.lateral_movement
+    - attack.t1021.002
+"#,
+        )
+        .unwrap();
+        let backend = CortexXdrBackend::new(None, CortexXdrOutput::Bioc);
+        let bioc: serde_json::Value = serde_json::from_str(&backend.convert_rule(rule)).unwrap();
+        assert_eq!(bioc["type"], "LATERAL_MOVEMENT");
+        assert_eq!(bioc["severity"], "SEV_040_HIGH");
+        assert_eq!(
+            bioc["mitre_tactic_id_and_name"][0],
+            "TA0008 - Lateral Movement"
+        );
+        assert_eq!(bioc["mitre_technique_id_and_name"][0], "T1021.002");
+        assert_eq!(
+            bioc["indicator"],
+            r#"config case_sensitive = false
+| dataset = xdr_data
+| filter event_type = ENUM.NETWORK
+| filter (action_remote_ip incidr "10.0.0.0/8" and action_remote_port = 445 and actor_process_image_path = "C:\\Windows\\System32\\cmd.exe")"#
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use crate::backend::sql::SqlDialect;
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::RuleOperator;
+use crate::prelude::SiemField;
+use crate::sigma::components::rule::sigma::{SigmaRule, SigmaRuleLogSource};
+use crate::utils::{backend_option, join_predicates, logsource_mapping, parse_aggregation};
+use std::collections::HashMap;
+use std::process::exit;
+
+/// The engine querying the data lake.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DataLakeEngine {
+    /// Google BigQuery Standard SQL.
+    #[default]
+    BigQuery,
+    /// Spark SQL (Ex: Databricks).
+    Spark,
+}
+
+impl DataLakeEngine {
+    fn dialect(&self) -> SqlDialect {
+        match self {
+            DataLakeEngine::BigQuery => SqlDialect::BIGQUERY,
+            DataLakeEngine::Spark => SqlDialect::SPARK,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DataLakeBackend {
+    mappings: Option<HashMap<String, String>>,
+    engine: DataLakeEngine,
+}
+
+impl DataLakeBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, engine: DataLakeEngine) -> Self {
+        DataLakeBackend { mappings, engine }
+    }
+
+    /// The table holding the events of the logsource, which the `logsource.*` mappings
+    /// can override.
+    fn table(&self, logsource: &SigmaRuleLogSource) -> String {
+        logsource_mapping(self.mappings.as_ref(), logsource)
+            .unwrap_or_else(|| self.engine.dialect().table.to_string())
+    }
+
+    /// The SQL expression of a sigma field. Dotted fields are nested fields, each part being
+    /// quoted on its own. With a `json.root` option the fields are paths in that JSON column
+    /// (Ex: `get_json_object(raw, '$.winlog.event_data.Image')`).
+    fn column(&self, field: &str) -> String {
+        let dialect = self.engine.dialect();
+        let field = self.get_mapping(field.to_string());
+        match backend_option(self.mappings.as_ref(), "json.root") {
+            Some(root) => {
+                let path: String = field.split('.').map(|key| self.json_key(key)).collect();
+                let path = dialect.escape_string(&format!("${path}"));
+                match self.engine {
+                    DataLakeEngine::BigQuery => format!("JSON_VALUE({root}, '{path}')"),
+                    DataLakeEngine::Spark => format!("get_json_object({root}, '{path}')"),
+                }
+            }
+            None => field
+                .split('.')
+                .map(|part| dialect.quote_identifier(part))
+                .collect::<Vec<String>>()
+                .join("."),
+        }
+    }
+
+    /// Step of a JSON path, quoted unless the key is a plain word.
+    fn json_key(&self, key: &str) -> String {
+        if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            format!(".{key}")
+        } else {
+            match self.engine {
+                DataLakeEngine::BigQuery => format!(".\"{key}\""),
+                DataLakeEngine::Spark => format!("['{key}']"),
+            }
+        }
+    }
+
+    fn build_predicate(&self, field: &str, operator: &RuleOperator) -> String {
+        let dialect = self.engine.dialect();
+        match operator {
+            // The JSON values are extracted as strings
+            RuleOperator::Equals(SiemField::Text(_))
+            | RuleOperator::Equals(SiemField::Array(_)) => {
+                dialect.build_predicate(&self.column(field), operator)
+            }
+            RuleOperator::Equals(value)
+                if backend_option(self.mappings.as_ref(), "json.root").is_some() =>
+            {
+                format!(
+                    "{} = '{}'",
+                    self.column(field),
+                    dialect.escape_string(&value.to_string())
+                )
+            }
+            RuleOperator::Any(operators) => join_predicates(
+                operators
+                    .iter()
+                    .map(|operator| self.build_predicate(field, operator))
+                    .collect(),
+                "OR",
+            ),
+            RuleOperator::All(operators) => join_predicates(
+                operators
+                    .iter()
+                    .map(|operator| self.build_predicate(field, operator))
+                    .collect(),
+                "AND",
+            ),
+            RuleOperator::Not(operator) => {
+                let predicate = self.build_predicate(field, operator);
+                if predicate.is_empty() {
+                    predicate
+                } else {
+                    format!("NOT {predicate}")
+                }
+            }
+            _ => dialect.build_predicate(&self.column(field), operator),
+        }
+    }
+}
+
+impl BackEnd for DataLakeBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let table = self.table(&sigma_rule.logsource);
+        let filter = self.build_query(&sigma_rule);
+        match aggregation {
+            Some(aggregation) => {
+                self.engine
+                    .dialect()
+                    .build_aggregation(&aggregation, &table, &filter, &|field| self.column(field))
+            }
+            None => format!("SELECT * FROM {table} WHERE {filter}"),
+        }
+    }
+}
+
+impl QueryBuilder for DataLakeBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        self.engine
+            .dialect()
+            .render_where(rule, &|field, operator| {
+                self.build_predicate(field, operator)
+            })
+    }
+}
+
+impl RequiresMappings for DataLakeBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        args.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::data_lake::{DataLakeBackend, DataLakeEngine};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_bigquery() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Suspicious Shell
+logsource:
+    product: windows
+    service: sysmon
+detection:
+    selection:
+        EventID: 1
+        Image|endswith: \cmd_x.exe
+        event_data.Parent-Image|contains: Temp
+    filter:
+        CommandLine|re: /c [a-z]+
+        SourceIp|cidr: 10.1.0.0/16
+    condition: selection and not filter
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([
+            (
+                "logsource.windows.sysmon".to_string(),
+                "`sec-lake.windows.sysmon`".to_string(),
+            ),
+            ("EventID".to_string(), "winlog.event_id".to_string()),
+        ]);
+        let query =
+            DataLakeBackend::new(Some(mappings), DataLakeEngine::BigQuery).convert_rule(rule);
+        assert!(query.starts_with("SELECT * FROM `sec-lake.windows.sysmon` WHERE "));
+        assert!(query.contains("winlog.event_id = 1"));
+        assert!(query.contains(r"LOWER(Image) LIKE '%\\\\cmd\\_x.exe'"));
+        assert!(query.contains(r"LOWER(event_data.`Parent-Image`) LIKE '%temp%'"));
+        assert!(query.contains(r"REGEXP_CONTAINS(CommandLine, '/c [a-z]+')"));
+        assert!(query.contains(
+            "NET.IP_TRUNC(NET.SAFE_IP_FROM_STRING(SourceIp), 16) = NET.IP_FROM_STRING('10.1.0.0')"
+        ));
+    }
+
+    #[test]
+    pub fn test_spark_json() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+        TargetUserName|re: ^adm
+    timeframe: 10m
+    condition: selection | count() by IpAddress > 10
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([
+            (
+                "logsource.windows".to_string(),
+                "lake.windows_events".to_string(),
+            ),
+            ("option.json.root".to_string(), "raw".to_string()),
+            (
+                "IpAddress".to_string(),
+                "winlog.event_data.Ip-Address".to_string(),
+            ),
+        ]);
+        let query = DataLakeBackend::new(Some(mappings), DataLakeEngine::Spark).convert_rule(rule);
+        assert_eq!(
+            query,
+            "SELECT get_json_object(raw, '$.winlog.event_data[\\'Ip-Address\\']'), COUNT(*) AS event_count FROM lake.windows_events WHERE (get_json_object(raw, '$.EventID') = '4625' AND get_json_object(raw, '$.TargetUserName') rlike '^adm') GROUP BY get_json_object(raw, '$.winlog.event_data[\\'Ip-Address\\']') HAVING COUNT(*) > 10"
+        );
+    }
+}
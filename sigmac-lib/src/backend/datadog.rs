@@ -0,0 +1,361 @@
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::sigma::{SigmaRule, SigmaRuleLogSource};
+use crate::prelude::rule::{RuleOperator, SiemRule};
+use crate::prelude::{AlertSeverity, SiemField, SiemIp};
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{
+    join_predicates, logsource_mapping, parse_aggregation, render_condition, timeframe_seconds,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::process::exit;
+
+/// Attributes Datadog reserves, searched without the `@` facet prefix.
+const RESERVED_ATTRIBUTES: [&str; 7] = [
+    "host", "source", "service", "status", "env", "version", "trace_id",
+];
+
+/// Evaluation windows (in seconds) accepted by the detection rules.
+const EVALUATION_WINDOWS: [u64; 8] = [0, 60, 300, 600, 900, 1800, 3600, 7200];
+
+/// What the Datadog backend emits for a rule.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum DatadogOutput {
+    /// The Logs search query.
+    #[default]
+    Query,
+    /// A Cloud SIEM detection rule JSON.
+    DetectionRule,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DatadogBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: DatadogOutput,
+}
+
+impl DatadogBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: DatadogOutput) -> Self {
+        DatadogBackend { mappings, output }
+    }
+
+    /// The search restricting the query to the logs of the logsource, which the `logsource.*`
+    /// mappings can override (Ex: `source:(sysmon OR windows)`).
+    fn source_filter(&self, logsource: &SigmaRuleLogSource) -> Option<String> {
+        if let Some(filter) = logsource_mapping(self.mappings.as_ref(), logsource) {
+            return Some(filter);
+        }
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let service = logsource.service.as_deref().unwrap_or_default();
+        [service, product]
+            .into_iter()
+            .find(|part| !part.is_empty())
+            .map(|source| format!("source:{}", escape_value(source)))
+    }
+
+    /// The attribute of a sigma field, facets being prefixed with `@`.
+    fn attribute(&self, field: &str) -> String {
+        let attribute = self.get_mapping(field.to_string());
+        if attribute.starts_with('@') || RESERVED_ATTRIBUTES.contains(&attribute.as_str()) {
+            attribute
+        } else {
+            format!("@{attribute}")
+        }
+    }
+
+    fn build_search(&self, rule: &SigmaRule) -> String {
+        let query = self.build_query(rule);
+        match self.source_filter(&rule.logsource) {
+            Some(source) if query.is_empty() => source,
+            Some(source) => format!("{source} {query}"),
+            None => query,
+        }
+    }
+
+    fn build_detection_rule(&self, sigma_rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let status = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => "info",
+            AlertSeverity::LOW => "low",
+            AlertSeverity::MEDIUM => "medium",
+            AlertSeverity::HIGH => "high",
+            AlertSeverity::CRITICAL => "critical",
+        };
+        let mut query = json!({
+            "name": "a",
+            "query": self.build_search(sigma_rule),
+            "aggregation": "count",
+            "groupByFields": [],
+            "distinctFields": [],
+        });
+        // Without aggregation every matching log raises a signal.
+        let mut condition = "a > 0".to_string();
+        if let Some(aggregation) = aggregation {
+            if let Some(group_by) = &aggregation.group_by {
+                query["groupByFields"] = json!([self.attribute(group_by)]);
+            }
+            match (aggregation.function.as_ref(), &aggregation.field) {
+                ("count", Some(field)) => {
+                    query["aggregation"] = json!("cardinality");
+                    query["distinctFields"] = json!([self.attribute(field)]);
+                }
+                ("count", None) => {}
+                (function, _) => eprintln!(
+                    "Datadog detection rules can't aggregate with {function}, '{}' counts the logs",
+                    sigma_rule.title
+                ),
+            }
+            let operator = match aggregation.operator.as_ref() {
+                "=" => "==",
+                operator => operator,
+            };
+            condition = format!("a {operator} {}", aggregation.value);
+        }
+        let window = evaluation_window(sigma_rule.detection.timeframe.as_deref().unwrap_or("5m"));
+        let mut tags = vec!["source:sigma".to_string()];
+        if let Some(id) = &sigma_rule.id {
+            tags.push(format!("sigma_id:{id}"));
+        }
+        for tactic in &siem_rule.mitre.tactics {
+            let tag = format!(
+                "tactic:{tactic:?}-{}",
+                tactic.name().to_lowercase().replace(' ', "-")
+            );
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        for technique in &siem_rule.mitre.techniques {
+            let tag = format!("technique:{technique}");
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        let mut message = sigma_rule
+            .description
+            .as_deref()
+            .unwrap_or(&sigma_rule.title)
+            .to_string();
+        if let Some(references) = &sigma_rule.references {
+            message.push_str("\n\nReferences:");
+            for reference in references {
+                message.push_str(&format!("\n- {reference}"));
+            }
+        }
+        let detection_rule = json!({
+            "name": sigma_rule.title,
+            "type": "log_detection",
+            "isEnabled": true,
+            "message": message,
+            "queries": [query],
+            "cases": [{
+                "name": sigma_rule.level.as_deref().unwrap_or(status),
+                "status": status,
+                "condition": condition,
+                "notifications": [],
+            }],
+            "options": {
+                "detectionMethod": "threshold",
+                "evaluationWindow": window,
+                "keepAlive": window.max(300),
+                "maxSignalDuration": 86400,
+            },
+            "tags": tags,
+        });
+        serde_json::to_string_pretty(&detection_rule).unwrap_or_default()
+    }
+}
+
+impl BackEnd for DatadogBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        match self.output {
+            DatadogOutput::Query => self.build_search(&sigma_rule),
+            DatadogOutput::DetectionRule => self.build_detection_rule(&sigma_rule),
+        }
+    }
+}
+
+impl RequiresMappings for DatadogBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        args.unwrap_or_default()
+    }
+}
+
+impl QueryBuilder for DatadogBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            // keywords are searched in the message
+            let attribute = if field.is_empty() {
+                String::new()
+            } else {
+                self.attribute(field)
+            };
+            build_predicate(&attribute, operator)
+        })
+    }
+}
+
+fn build_predicate(attribute: &str, operator: &RuleOperator) -> String {
+    let search = |value: String| {
+        if attribute.is_empty() {
+            value
+        } else {
+            format!("{attribute}:{value}")
+        }
+    };
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => search(format!(
+            "({})",
+            values
+                .iter()
+                .map(|value| escape_value(value))
+                .collect::<Vec<String>>()
+                .join(" OR ")
+        )),
+        RuleOperator::Equals(value) => search(escape_value(&value.to_string())),
+        RuleOperator::StartsWith(pattern) => search(format!("{}*", escape_value(pattern))),
+        RuleOperator::EndsWith(pattern) => search(format!("*{}", escape_value(pattern))),
+        RuleOperator::Contains(pattern) => search(format!("*{}*", escape_value(pattern))),
+        RuleOperator::GT(value) => search(format!(">{value}")),
+        RuleOperator::GTE(value) => search(format!(">={value}")),
+        RuleOperator::LT(value) => search(format!("<{value}")),
+        RuleOperator::LTE(value) => search(format!("<={value}")),
+        RuleOperator::IsNull(_) if !attribute.is_empty() => format!("-{attribute}:*"),
+        RuleOperator::SameNet((ip, net)) if !attribute.is_empty() => {
+            let address = match ip {
+                SiemIp::V4(ip) => ipv4_to_str(*ip),
+                SiemIp::V6(ip) => ipv6_to_str(*ip),
+            };
+            format!("CIDR({attribute},{address}/{net})")
+        }
+        RuleOperator::Matches(regex) => {
+            eprintln!(
+                "Datadog log search has no regular expressions, the condition on '{attribute}' matching '{}' is dropped",
+                regex.as_str()
+            );
+            String::new()
+        }
+        RuleOperator::Any(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(attribute, operator))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(attribute, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(attribute, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// Escapes the characters having a meaning in the search syntax, the whitespaces included
+/// so the values are searched as a whole.
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_whitespace() || "+-=&|><!(){}[]^\"~*?:\\/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The smallest evaluation window holding the sigma timeframe (`30s`, `5m`, `1h`, `2d`).
+fn evaluation_window(timeframe: &str) -> u64 {
+    let seconds = timeframe_seconds(timeframe);
+    EVALUATION_WINDOWS
+        .into_iter()
+        .find(|window| *window >= seconds)
+        .unwrap_or(7200)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::datadog::{DatadogBackend, DatadogOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_query() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let mappings = HashMap::from([
+            (
+                "CommandLine".to_string(),
+                "process.command_line".to_string(),
+            ),
+            (
+                "logsource.windows".to_string(),
+                "source:windows".to_string(),
+            ),
+        ]);
+        assert_eq!(
+            DatadogBackend::new(Some(mappings), DatadogOutput::Query).convert_rule(rule),
+            r#"source:windows (((@Image:*\\cmd.exe OR @OriginalFileName:Cmd.Exe) AND @ParentImage:*\\7zFM.exe) AND NOT ((@process.command_line:*\/c* OR @process.command_line:*\/k* OR @process.command_line:*\/r*) OR -@process.command_line:*))"#
+        );
+    }
+
+    #[test]
+    pub fn test_detection_rule() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+id: 5a1b2c3d-0000-4000-8000-000000000001
+description: Failed logons of many users from a host
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 10m
+    condition: selection | count(TargetUserName) by IpAddress > 10
+level: high
+tags:
+    - attack.credential_access
+    - attack.t1110.001
+"#,
+        )
+        .unwrap();
+        let backend = DatadogBackend::new(None, DatadogOutput::DetectionRule);
+        let detection_rule: serde_json::Value =
+            serde_json::from_str(&backend.convert_rule(rule)).unwrap();
+        let query = &detection_rule["queries"][0];
+        assert_eq!(query["query"], "source:security @EventID:4625");
+        assert_eq!(query["aggregation"], "cardinality");
+        assert_eq!(query["distinctFields"][0], "@TargetUserName");
+        assert_eq!(query["groupByFields"][0], "@IpAddress");
+        assert_eq!(detection_rule["cases"][0]["status"], "high");
+        assert_eq!(detection_rule["cases"][0]["condition"], "a > 10");
+        assert_eq!(detection_rule["options"]["evaluationWindow"], 600);
+        let tags = detection_rule["tags"].as_array().unwrap();
+        assert!(tags.contains(&"tactic:TA0006-credential-access".into()));
+        assert!(tags.contains(&"technique:T1110.001".into()));
+    }
+}
@@ -1,17 +1,190 @@
 use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition, SigmaRule};
+use crate::prelude::rule::sigma::{ConditionAggregation, SigmaRule, SigmaRuleLogSource};
 use crate::prelude::rule::{RuleOperator, SiemRule};
-use crate::prelude::SiemField;
-use crate::utils::parse_condition;
+use crate::prelude::{AlertSeverity, SiemField};
+use crate::utils::{
+    escape_string, join_predicates, logsource_mapping, parse_aggregation, render_condition,
+    snake_case,
+};
+use serde_json::json;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::process::exit;
 
+/// The artifacts the Devo backend is able to produce.
 #[derive(Clone, Debug, Default)]
-pub struct DevoBackend {}
+pub enum DevoOutput {
+    /// The LINQ query.
+    #[default]
+    Query,
+    /// An alert definition JSON, as expected by the alerts API.
+    AlertDefinition,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DevoBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: DevoOutput,
+}
+
+impl DevoBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: DevoOutput) -> Self {
+        DevoBackend { mappings, output }
+    }
+
+    /// The Devo table holding the events of the logsource, which the `logsource.*` mappings
+    /// can override.
+    fn table(&self, logsource: &SigmaRuleLogSource) -> String {
+        if let Some(table) = logsource_mapping(self.mappings.as_ref(), logsource) {
+            return table;
+        }
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let category = logsource.category.as_deref().unwrap_or_default();
+        let table = match (product, category) {
+            ("linux", _) | ("macos", _) => "box.unix.all",
+            ("aws", _) => "cloud.aws.cloudtrail",
+            ("azure", _) => "cloud.azure.ad.audit",
+            ("gcp", _) => "cloud.gcp.audit",
+            ("okta", _) => "auth.okta.events",
+            (_, "firewall") => "firewall.all.traffic",
+            (_, "proxy") => "proxy.all.access",
+            (_, "webserver") => "web.all.access",
+            _ => "box.all.win",
+        };
+        table.to_string()
+    }
+
+    /// The LINQ query of the rule: the table, the columns derived from the raw message,
+    /// the detection condition and the grouping of aggregations.
+    fn build_linq(&self, rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let derived = RefCell::new(vec![]);
+        let filter = self.build_filter(rule, &derived);
+        let mut query = format!("from {}", self.table(&rule.logsource));
+        for column in derived.into_inner() {
+            query.push_str(&format!("\nselect {column}"));
+        }
+        if !filter.is_empty() {
+            query.push_str(&format!("\nwhere {filter}"));
+        }
+        if let Some(aggregation) = aggregation {
+            query.push_str(&self.build_grouping(rule, &aggregation));
+        }
+        query
+    }
+
+    /// Renders the condition, the derived columns the fields map to being collected.
+    fn build_filter(&self, rule: &SigmaRule, derived: &RefCell<Vec<String>>) -> String {
+        render_condition(rule, ("and", "or", "not"), &|field, operator| {
+            build_predicate(&self.column(field, derived), operator)
+        })
+    }
+
+    /// The column of a sigma field. Fields mapped to a `select <expression> as <column>` are
+    /// derived columns, their expression being recorded to be selected before filtering.
+    fn column(&self, field: &str, derived: &RefCell<Vec<String>>) -> String {
+        let mapping = self.get_mapping(field.to_string());
+        // mappings may carry notes after a `#` or extra clauses after a ` / `
+        let mapping = mapping
+            .split(" / ")
+            .next()
+            .unwrap_or_default()
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        match mapping.strip_prefix("select ") {
+            Some(column) => {
+                let alias = column
+                    .rsplit(" as ")
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string();
+                let mut derived = derived.borrow_mut();
+                if !derived.iter().any(|known| known == column) {
+                    derived.push(column.to_string());
+                }
+                alias
+            }
+            None => mapping,
+        }
+    }
+
+    fn build_grouping(&self, rule: &SigmaRule, aggregation: &ConditionAggregation) -> String {
+        let period = rule.detection.timeframe.as_deref().unwrap_or("5m");
+        let derived = RefCell::new(vec![]);
+        let mut grouping = format!("\ngroup every {period}");
+        if let Some(group_by) = &aggregation.group_by {
+            grouping.push_str(&format!(" by {}", self.column(group_by, &derived)));
+        }
+        let function = match (aggregation.function.as_ref(), &aggregation.field) {
+            ("count", Some(field)) => format!("ustring_count({})", self.column(field, &derived)),
+            ("count", None) => "count()".to_string(),
+            (function, Some(field)) => format!("{function}({})", self.column(field, &derived)),
+            (function, None) => format!("{function}()"),
+        };
+        let operator = match aggregation.operator.as_ref() {
+            "==" => "=",
+            operator => operator,
+        };
+        grouping.push_str(&format!(
+            "\nselect {function} as events\nwhere events {operator} {}",
+            aggregation.value
+        ));
+        grouping
+    }
+
+    fn build_alert_definition(&self, sigma_rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let priority = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => 0,
+            AlertSeverity::LOW => 3,
+            AlertSeverity::MEDIUM => 5,
+            AlertSeverity::HIGH => 7,
+            AlertSeverity::CRITICAL => 10,
+        };
+        // Aggregations are already thresholded by the query, every row it emits is an alert.
+        let trigger = json!({
+            "kind": "each",
+            "query": self.build_linq(sigma_rule),
+            "keys": aggregation
+                .and_then(|aggregation| aggregation.group_by)
+                .map(|group_by| vec![self.get_mapping(group_by.to_string())])
+                .unwrap_or_default()
+        });
+        let alert = json!({
+            "name": snake_case(&sigma_rule.title),
+            "message": sigma_rule.title,
+            "description": sigma_rule.description.as_deref().unwrap_or_default(),
+            "subcategory": "lib.my.sigma",
+            "priority": priority,
+            "correlationTrigger": trigger,
+            "actionPolicyId": [],
+            "alertDefinitionType": "standard",
+            "tags": sigma_rule.tags.clone().unwrap_or_default()
+        });
+        serde_json::to_string_pretty(&alert).unwrap_or_default()
+    }
+}
 
 impl RequiresMappings for DevoBackend {
     fn get_field_map(&self) -> Option<HashMap<String, String>> {
-        None
+        self.mappings.clone()
     }
 
     fn get_default_field_name(&self, args: Option<String>) -> String {
@@ -76,340 +249,141 @@ impl RequiresMappings for DevoBackend {
 
 impl BackEnd for DevoBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        let query = self.build_query(&sigma_rule);
-        format!("from box.all.win where {query} select *")
+        match self.output {
+            DevoOutput::Query => self.build_linq(&sigma_rule),
+            DevoOutput::AlertDefinition => self.build_alert_definition(&sigma_rule),
+        }
     }
 }
 
 impl QueryBuilder for DevoBackend {
     fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
-            Err(e) => {
-                eprintln!("{:?}", e);
-                exit(1);
-            }
-        };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let mapped_cond_name = self.get_mapping(cond_name);
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(format!("{mapped_cond_name} = startswith({mapped_cond_name}, \"{pattern}\")").as_str());
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!(
-                                "{mapped_cond_name} = endswith({mapped_cond_name}, \"{pattern}\")"
-                            )
-                            .as_str(),
-                        );
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!(
-                                "{mapped_cond_name} = weakhas({mapped_cond_name}, \"{pattern}\")"
-                            )
-                            .as_str(),
-                        );
-                    }
-                    RuleOperator::Equals(field) => match field {
-                        SiemField::Array(elements) => {
-                            let joined_query = elements.join(", ");
-                            query_str.push_str(
-                                format!("has({mapped_cond_name}, {joined_query})").as_str(),
-                            )
-                        }
-                        _ => {
-                            query_str.push_str(format!("has({mapped_cond_name}, {field})").as_str())
-                        }
-                    },
-                    RuleOperator::Any(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        let mut eq_sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    eq_sub_queries.push(val.to_string());
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{mapped_cond_name} = startswith({mapped_cond_name}, \"{pattern}\")"));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("{mapped_cond_name} = endswith({mapped_cond_name}, \"{pattern}\")"));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("{mapped_cond_name} = weakhas({mapped_cond_name}, \"{pattern}\")"));
-                                }
-                                _ => {}
-                            }
-                        }
-                        if !eq_sub_queries.is_empty() {
-                            let joined_eq_sub_queries = eq_sub_queries.join(", ");
-                            sub_queries.push(format!("has({cond_name}, {joined_eq_sub_queries})"));
-                        }
-                        let joined_sub_queries = sub_queries.join(", ");
-                        query_str.push_str(joined_sub_queries.as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("has({cond_name}, {val})"));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{mapped_cond_name} = startswith({mapped_cond_name}, \"{pattern}\")"));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("{mapped_cond_name} = endswith({mapped_cond_name}, \"{pattern}\")"));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("{mapped_cond_name} = weakhas({mapped_cond_name}, \"{pattern}\")"));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" and ");
-                        query_str.push_str(joined_sub_queries.as_str());
-                    }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
+        self.build_filter(rule, &RefCell::new(vec![]))
+    }
+}
+
+fn build_predicate(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => in_set(field, values),
+        RuleOperator::Equals(SiemField::Text(value)) => {
+            format!("{field} = \"{}\"", escape_string(value))
+        }
+        RuleOperator::Equals(value) => format!("{field} = {value}"),
+        RuleOperator::StartsWith(pattern) => {
+            format!("startswith({field}, \"{}\")", escape_string(pattern))
+        }
+        RuleOperator::EndsWith(pattern) => {
+            format!("endswith({field}, \"{}\")", escape_string(pattern))
+        }
+        RuleOperator::Contains(pattern) => {
+            format!("weakhas({field}, \"{}\")", escape_string(pattern))
+        }
+        RuleOperator::Matches(regex) => {
+            format!(
+                "matches({field}, re(\"{}\"))",
+                escape_string(regex.as_str())
+            )
+        }
+        RuleOperator::IsNull(_) => format!("isnull({field})"),
+        RuleOperator::Any(operators) => {
+            // Several text equalities of the same field are a set membership
+            let values: Option<Vec<String>> = operators
+                .iter()
+                .map(|operator| match operator.as_ref() {
+                    RuleOperator::Equals(SiemField::Text(value)) => Some(value.to_string()),
+                    _ => None,
+                })
+                .collect();
+            match values {
+                Some(values) if values.len() > 1 => in_set(field, &values),
+                _ => join_predicates(
+                    operators
+                        .iter()
+                        .map(|operator| build_predicate(field, operator))
+                        .collect(),
+                    "or",
+                ),
             }
-            let mut condition_query = String::new();
-            condition_query.push_str(format!("({})", conditions_list.join(" and ")).as_str());
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
         }
-
-        // Check the conditions of this subrule
-        for condition in conditions {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" or ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" and ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" not ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" or ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" and ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" and ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" or ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" and ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" not ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" or ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" and ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" or ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" or ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" and ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" not ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" or ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" and ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "and",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("not {predicate}")
             }
         }
-        query_str.clone()
+        _ => String::new(),
+    }
+}
+
+fn in_set<T: AsRef<str>>(field: &str, values: &[T]) -> String {
+    format!(
+        "{field} in {{{}}}",
+        values
+            .iter()
+            .map(|value| format!("\"{}\"", escape_string(value.as_ref())))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::devo::{DevoBackend, DevoOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+
+    #[test]
+    pub fn test_linq_query() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        assert_eq!(
+            DevoBackend::default().convert_rule(rule),
+            r#"from box.all.win
+where (((endswith(serviceFileName, "\\cmd.exe") or serviceFileName = "Cmd.Exe") and endswith(ParentImage, "\\7zFM.exe")) and not ((weakhas(procCmdLine, "/c") or weakhas(procCmdLine, "/k") or weakhas(procCmdLine, "/r")) or isnull(procCmdLine)))"#
+        );
+    }
+
+    #[test]
+    pub fn test_alert_definition() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+        TargetUserName:
+            - admin
+            - root
+    timeframe: 10m
+    condition: selection | count() by WorkstationName > 10
+level: high
+"#,
+        )
+        .unwrap();
+        let backend = DevoBackend::new(None, DevoOutput::AlertDefinition);
+        let alert: serde_json::Value = serde_json::from_str(&backend.convert_rule(rule)).unwrap();
+        assert_eq!(alert["name"], "many_failed_logons");
+        assert_eq!(alert["priority"], 7);
+        assert_eq!(alert["correlationTrigger"]["keys"][0], "workstation");
+        assert_eq!(
+            alert["correlationTrigger"]["query"],
+            r#"from box.all.win
+where (eventID = 4625 and targetUsername in {"admin", "root"})
+group every 10m by workstation
+select count() as events
+where events > 10"#
+        );
     }
 }
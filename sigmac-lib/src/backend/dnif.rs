@@ -1,361 +1,363 @@
-use crate::backend::{BackEnd, QueryBuilder};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition, SigmaRule};
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::sigma::{SigmaRule, SigmaRuleLogSource};
 use crate::prelude::rule::{RuleOperator, SiemRule};
-use crate::prelude::SiemField;
-use crate::utils::parse_condition;
+use crate::prelude::{AlertSeverity, SiemField};
+use crate::utils::{
+    escape_string, join_predicates, logsource_mapping, parse_aggregation, render_condition,
+    stable_hash,
+};
+use serde_json::json;
+use std::collections::HashMap;
 use std::process::exit;
 
+/// What the DNIF backend emits for a rule.
 #[derive(Clone, Debug, Default)]
-pub struct DNIFBackend {}
+pub enum DNIFOutput {
+    /// The stream query.
+    #[default]
+    Query,
+    /// A workbook JSON holding the query and the signal it raises.
+    Workbook,
+}
 
-impl BackEnd for DNIFBackend {
-    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        let query = self.build_query(&sigma_rule);
-        format!("stream=windows where {query}")
-    }
+#[derive(Clone, Debug, Default)]
+pub struct DNIFBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: DNIFOutput,
 }
 
-impl QueryBuilder for DNIFBackend {
-    fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
+impl DNIFBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: DNIFOutput) -> Self {
+        DNIFBackend { mappings, output }
+    }
+
+    /// The DNIF stream holding the events of the logsource, which the `logsource.*` mappings
+    /// can override.
+    fn stream(&self, logsource: &SigmaRuleLogSource) -> String {
+        if let Some(stream) = logsource_mapping(self.mappings.as_ref(), logsource) {
+            return stream;
+        }
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let category = logsource.category.as_deref().unwrap_or_default();
+        let stream = match (product, category) {
+            ("windows", _) => "windows",
+            ("linux", _) | ("macos", _) => "linux",
+            ("aws", _) | ("azure", _) | ("gcp", _) | ("m365", _) | ("okta", _) => "cloud",
+            (_, "firewall") => "firewall",
+            (_, "proxy") => "proxy",
+            (_, "dns") => "dns",
+            (_, "webserver") => "web",
+            ("", "") => "windows",
+            ("", category) => category,
+            (product, _) => product,
+        };
+        stream.to_string()
+    }
+
+    fn build_workbook(&self, sigma_rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
             Err(e) => {
                 eprintln!("{:?}", e);
                 exit(1);
             }
         };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let mapped_cond_name = cond_name;
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!("{mapped_cond_name} == \"startswith {pattern}\"").as_str(),
-                        );
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!("{mapped_cond_name} == \"endswith {pattern}\"").as_str(),
-                        );
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!("{mapped_cond_name} == \"contains {pattern}\"").as_str(),
-                        );
-                    }
-                    RuleOperator::Equals(field) => match field {
-                        SiemField::Array(elements) => {
-                            let joined_query = elements.join(", ");
-                            query_str.push_str(
-                                format!("{mapped_cond_name} IN ({joined_query})").as_str(),
-                            )
-                        }
-                        _ => query_str
-                            .push_str(format!("{mapped_cond_name} == \"{field}\"").as_str()),
-                    },
-                    RuleOperator::Any(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        let mut eq_sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    eq_sub_queries.push(format!("\"{val}\""));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{mapped_cond_name} == \"startswith {pattern}\""
-                                    ));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{mapped_cond_name} == \"endswith {pattern}\""
-                                    ));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{mapped_cond_name} == \"contains {pattern}\""
-                                    ));
-                                }
-                                _ => {}
-                            }
-                        }
-                        if !eq_sub_queries.is_empty() {
-                            let joined_eq_sub_queries = eq_sub_queries.join(", ");
-                            sub_queries.push(format!("{cond_name} IN ({joined_eq_sub_queries})"));
-                        }
-                        let joined_sub_queries = sub_queries.join(" ");
-                        query_str.push_str(joined_sub_queries.as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        let mut eq_sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    eq_sub_queries.push(format!("\"{val}\""));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{mapped_cond_name} == \"startswith {pattern}\""
-                                    ));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{mapped_cond_name} == \"endswith {pattern}\""
-                                    ));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{mapped_cond_name} == \"contains {pattern}\""
-                                    ));
-                                }
-                                _ => {}
-                            }
-                        }
-                        if !eq_sub_queries.is_empty() {
-                            let joined_eq_sub_queries = eq_sub_queries.join(", ");
-                            sub_queries.push(format!("{cond_name} IN ({joined_eq_sub_queries})"));
-                        }
-                        let joined_sub_queries = sub_queries.join(" and ");
-                        query_str.push_str(joined_sub_queries.as_str());
-                    }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
-            }
-            let mut condition_query = String::new();
-            condition_query.push_str(conditions_list.join(" and ").as_str());
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let severity = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => "info",
+            AlertSeverity::LOW => "low",
+            AlertSeverity::MEDIUM => "medium",
+            AlertSeverity::HIGH => "high",
+            AlertSeverity::CRITICAL => "critical",
+        };
+        let mut signal = json!({
+            "name": sigma_rule.title,
+            "severity": severity,
+            "tactics": siem_rule
+                .mitre
+                .tactics
+                .iter()
+                .map(|tactic| tactic.name().to_string())
+                .collect::<Vec<String>>(),
+            "techniques": siem_rule
+                .mitre
+                .techniques
+                .iter()
+                .map(|technique| technique.to_string())
+                .collect::<Vec<String>>(),
+        });
+        // Aggregations are left to the signal: it is raised once the threshold of events
+        // sharing the group-by value is met within the timeframe.
+        if let Some(aggregation) = aggregation {
+            signal["threshold"] = json!({
+                "function": aggregation.function.to_string(),
+                "field": aggregation
+                    .field
+                    .map(|field| self.get_mapping(field.to_string())),
+                "group_by": aggregation
+                    .group_by
+                    .map(|group_by| self.get_mapping(group_by.to_string())),
+                "operator": aggregation.operator.to_string(),
+                "value": aggregation.value,
+                "window": sigma_rule.detection.timeframe.as_deref().unwrap_or("5m"),
+            });
+        }
+        let mut blocks = vec![];
+        if let Some(description) = &sigma_rule.description {
+            blocks.push(json!({
+                "type": "markdown",
+                "name": "Description",
+                "content": description,
+            }));
         }
+        blocks.push(json!({
+            "type": "query",
+            "name": "Detection",
+            "query": self.build_stream_query(sigma_rule),
+            "signal": signal,
+        }));
+        let workbook = json!({
+            "id": workbook_id(sigma_rule),
+            "name": sigma_rule.title,
+            "tags": sigma_rule.tags.clone().unwrap_or_default(),
+            "blocks": blocks,
+        });
+        serde_json::to_string_pretty(&workbook).unwrap_or_default()
+    }
 
-        // Check the conditions of this subrule
-        for condition in conditions {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" or ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" and ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" not ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" or ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" and ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" and ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" or ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" and ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" not ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" or ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" and ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
+    /// The stream query, with the aggregation of the rule as an `aggregate` stage followed
+    /// by the threshold filter.
+    fn build_aggregated_query(&self, sigma_rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let mut query = self.build_stream_query(sigma_rule);
+        if let Some(aggregation) = aggregation {
+            let function = match (aggregation.function.as_ref(), &aggregation.field) {
+                ("count", Some(field)) => {
+                    format!("count_unique({})", self.get_mapping(field.to_string()))
                 }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" or ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" or ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" and ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" not ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" or ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" and ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
+                (function, Some(field)) => {
+                    format!("{function}({})", self.get_mapping(field.to_string()))
                 }
+                (function, None) => format!("{function}()"),
+            };
+            query.push_str(&format!(" | aggregate {function} as total"));
+            if let Some(group_by) = &aggregation.group_by {
+                query.push_str(&format!(" by {}", self.get_mapping(group_by.to_string())));
             }
+            let operator = match aggregation.operator.as_ref() {
+                "=" => "==",
+                operator => operator,
+            };
+            query.push_str(&format!(" | where total {operator} {}", aggregation.value));
         }
-        query_str.clone()
+        query
+    }
+
+    fn build_stream_query(&self, sigma_rule: &SigmaRule) -> String {
+        format!(
+            "stream={} where {}",
+            self.stream(&sigma_rule.logsource),
+            self.build_query(sigma_rule)
+        )
+    }
+}
+
+impl BackEnd for DNIFBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        match self.output {
+            DNIFOutput::Query => self.build_aggregated_query(&sigma_rule),
+            DNIFOutput::Workbook => self.build_workbook(&sigma_rule),
+        }
+    }
+}
+
+impl RequiresMappings for DNIFBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        args.unwrap_or_default()
+    }
+}
+
+impl QueryBuilder for DNIFBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        render_condition(rule, ("and", "or", "not"), &|field, operator| {
+            build_predicate(&self.get_mapping(field.to_string()), operator)
+        })
+    }
+}
+
+fn build_predicate(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => in_list(field, values),
+        RuleOperator::Equals(SiemField::Text(value)) => {
+            format!("{field} == \"{}\"", escape_string(value))
+        }
+        RuleOperator::Equals(value) => format!("{field} == {value}"),
+        RuleOperator::StartsWith(pattern) => {
+            format!("{field} like \"{}%\"", escape_like(pattern))
+        }
+        RuleOperator::EndsWith(pattern) => {
+            format!("{field} like \"%{}\"", escape_like(pattern))
+        }
+        RuleOperator::Contains(pattern) => {
+            format!("{field} like \"%{}%\"", escape_like(pattern))
+        }
+        RuleOperator::Matches(regex) => {
+            format!("{field} regex \"{}\"", escape_string(regex.as_str()))
+        }
+        RuleOperator::IsNull(_) => format!("{field} is null"),
+        RuleOperator::Any(operators) => {
+            // Several text equalities of the same field are a list membership
+            let values: Option<Vec<String>> = operators
+                .iter()
+                .map(|operator| match operator.as_ref() {
+                    RuleOperator::Equals(SiemField::Text(value)) => Some(value.to_string()),
+                    _ => None,
+                })
+                .collect();
+            match values {
+                Some(values) if values.len() > 1 => in_list(field, &values),
+                _ => join_predicates(
+                    operators
+                        .iter()
+                        .map(|operator| build_predicate(field, operator))
+                        .collect(),
+                    "or",
+                ),
+            }
+        }
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "and",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("not {predicate}")
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+fn in_list<T: AsRef<str>>(field: &str, values: &[T]) -> String {
+    format!(
+        "{field} IN ({})",
+        values
+            .iter()
+            .map(|value| format!("\"{}\"", escape_string(value.as_ref())))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+/// Escapes the `like` wildcards of a value on top of the string escaping.
+fn escape_like(value: &str) -> String {
+    escape_string(value).replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Deterministic workbook id, so a converted rule updates its workbook on import.
+fn workbook_id(rule: &SigmaRule) -> String {
+    format!(
+        "sigma-{:016x}",
+        stable_hash(rule.id.as_deref().unwrap_or(rule.title.as_ref()))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::dnif::{DNIFBackend, DNIFOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_query() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        assert_eq!(
+            DNIFBackend::default().convert_rule(rule),
+            r#"stream=windows where (((Image like "%\\cmd.exe" or OriginalFileName == "Cmd.Exe") and ParentImage like "%\\7zFM.exe") and not ((CommandLine like "%/c%" or CommandLine like "%/k%" or CommandLine like "%/r%") or CommandLine is null))"#
+        );
+    }
+
+    #[test]
+    pub fn test_workbook() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+id: 5a1b2c3d-0000-4000-8000-000000000001
+description: Failed logons from a single workstation
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+        TargetUserName:
+            - admin
+            - root
+    timeframe: 10m
+    condition: selection | count() by WorkstationName > 10
+level: high
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([(
+            "logsource.windows.security".to_string(),
+            "winsec".to_string(),
+        )]);
+        let backend = DNIFBackend::new(Some(mappings), DNIFOutput::Workbook);
+        let workbook: serde_json::Value =
+            serde_json::from_str(&backend.convert_rule(rule)).unwrap();
+        assert_eq!(workbook["name"], "Many Failed Logons");
+        assert_eq!(workbook["blocks"][0]["type"], "markdown");
+        let block = &workbook["blocks"][1];
+        assert_eq!(
+            block["query"],
+            r#"stream=winsec where (EventID == 4625 and TargetUserName IN ("admin", "root"))"#
+        );
+        assert_eq!(block["signal"]["severity"], "high");
+        assert_eq!(block["signal"]["threshold"]["group_by"], "WorkstationName");
+        assert_eq!(block["signal"]["threshold"]["value"], 10);
+        assert_eq!(block["signal"]["threshold"]["window"], "10m");
+        assert_eq!(workbook["id"], "sigma-a854486bcf40521b");
+    }
+
+    #[test]
+    pub fn test_aggregated_query() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Password Spraying
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 10m
+    condition: selection | count(TargetUserName) by IpAddress > 20
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([("IpAddress".to_string(), "SrcIP".to_string())]);
+        assert_eq!(
+            DNIFBackend::new(Some(mappings), DNIFOutput::Query).convert_rule(rule),
+            "stream=windows where EventID == 4625 | aggregate count_unique(TargetUserName) as total by SrcIP | where total > 20"
+        );
     }
 }
@@ -0,0 +1,368 @@
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::sigma::{ConditionAggregation, SigmaRule, SigmaRuleLogSource};
+use crate::prelude::rule::RuleOperator;
+use crate::prelude::{SiemField, SiemIp};
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{
+    join_predicates, logsource_mapping, parse_aggregation, parse_timeframe, render_condition,
+};
+use std::collections::HashMap;
+use std::process::exit;
+
+/// Converts Sigma rules into Elastic ES|QL queries over the ECS fields.
+#[derive(Clone, Debug, Default)]
+pub struct EsqlBackend {
+    mappings: Option<HashMap<String, String>>,
+}
+
+impl EsqlBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>) -> Self {
+        EsqlBackend { mappings }
+    }
+
+    /// The index pattern of the logsource, which the `logsource.*` mappings can override.
+    fn index(&self, logsource: &SigmaRuleLogSource) -> String {
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let mapped = logsource_mapping(self.mappings.as_ref(), logsource);
+        match mapped {
+            Some(index) => index,
+            None => match product {
+                "windows" => "winlogbeat-*, logs-windows.*".to_string(),
+                "linux" => "auditbeat-*, logs-auditd.*".to_string(),
+                _ => "logs-*".to_string(),
+            },
+        }
+    }
+
+    /// The `STATS ... BY` of the aggregation and its threshold, the events being counted per
+    /// timeframe window.
+    fn build_stats(&self, rule: &SigmaRule, aggregation: &ConditionAggregation) -> String {
+        let value = match (
+            aggregation.function.to_lowercase().as_str(),
+            &aggregation.field,
+        ) {
+            ("count", Some(field)) => format!("COUNT_DISTINCT({})", self.column(field)),
+            ("count", None) => "COUNT(*)".to_string(),
+            (function, Some(field)) => {
+                format!("{}({})", function.to_uppercase(), self.column(field))
+            }
+            (function, None) => {
+                eprintln!(
+                    "'{}': the {function} aggregation needs a field, the events are counted instead",
+                    rule.title
+                );
+                "COUNT(*)".to_string()
+            }
+        };
+        let mut groups = vec![];
+        if let Some(timeframe) = &rule.detection.timeframe {
+            groups.push(format!(
+                "window = DATE_TRUNC({}, @timestamp)",
+                time_span(timeframe)
+            ));
+        }
+        if let Some(group_by) = &aggregation.group_by {
+            groups.push(self.column(group_by));
+        }
+        let by = if groups.is_empty() {
+            String::new()
+        } else {
+            format!(" BY {}", groups.join(", "))
+        };
+        let operator = match aggregation.operator.as_ref() {
+            "=" => "==",
+            operator => operator,
+        };
+        format!(
+            "| STATS event_count = {value}{by}\n| WHERE event_count {operator} {}",
+            aggregation.value
+        )
+    }
+
+    /// The ES|QL name of a sigma field, quoted when it isn't a plain dotted name.
+    fn column(&self, field: &str) -> String {
+        let column = self.get_mapping(field.to_string());
+        if column
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '@')
+        {
+            column
+        } else {
+            format!("`{}`", column.replace('`', "``"))
+        }
+    }
+}
+
+impl BackEnd for EsqlBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let query = format!(
+            "FROM {}\n| WHERE {}",
+            self.index(&sigma_rule.logsource),
+            self.build_query(&sigma_rule)
+        );
+        match aggregation {
+            Some(aggregation) => {
+                format!("{query}\n{}", self.build_stats(&sigma_rule, &aggregation))
+            }
+            None => query,
+        }
+    }
+}
+
+impl RequiresMappings for EsqlBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        let mappings = HashMap::from([
+            ("EventID", "event.code"),
+            ("Channel", "winlog.channel"),
+            ("Provider_Name", "winlog.provider_name"),
+            ("Computer", "host.name"),
+            ("ComputerName", "host.name"),
+            ("ProcessGuid", "process.entity_id"),
+            ("ProcessId", "process.pid"),
+            ("Image", "process.executable"),
+            ("OriginalFileName", "process.pe.original_file_name"),
+            ("CommandLine", "process.command_line"),
+            ("CurrentDirectory", "process.working_directory"),
+            ("User", "user.name"),
+            ("Hashes", "process.hash.sha256"),
+            ("ParentProcessGuid", "process.parent.entity_id"),
+            ("ParentProcessId", "process.parent.pid"),
+            ("ParentImage", "process.parent.executable"),
+            ("ParentCommandLine", "process.parent.command_line"),
+            ("TargetFilename", "file.path"),
+            ("ImageLoaded", "file.path"),
+            ("Signed", "file.code_signature.signed"),
+            ("SignatureStatus", "file.code_signature.status"),
+            ("SourceIp", "source.ip"),
+            ("SourceHostname", "source.domain"),
+            ("SourcePort", "source.port"),
+            ("DestinationIp", "destination.ip"),
+            ("DestinationHostname", "destination.domain"),
+            ("DestinationPort", "destination.port"),
+            ("Protocol", "network.transport"),
+            ("SourceProcessGuid", "process.entity_id"),
+            ("SourceProcessId", "process.pid"),
+            ("SourceImage", "process.executable"),
+            ("TargetImage", "winlog.event_data.TargetImage"),
+            ("TargetObject", "registry.path"),
+            ("Details", "registry.data.strings"),
+            ("PipeName", "file.name"),
+            ("QueryName", "dns.question.name"),
+            ("ScriptBlockText", "powershell.file.script_block_text"),
+            ("AccountDomain", "user.domain"),
+            ("AccountName", "user.name"),
+            ("TargetUserName", "user.name"),
+            ("TargetDomainName", "user.domain"),
+            ("IpAddress", "source.ip"),
+            ("IpPort", "source.port"),
+            ("WorkstationName", "source.domain"),
+            ("NewProcessName", "process.executable"),
+            ("ParentProcessName", "process.parent.name"),
+        ]);
+        let field = args.unwrap_or_default();
+        match mappings.get(field.as_str()) {
+            Some(mapping) => mapping.to_string(),
+            _ => format!("winlog.event_data.{field}"),
+        }
+    }
+}
+
+impl QueryBuilder for EsqlBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            // keywords are searched in the event message
+            let column = if field.is_empty() {
+                "message".to_string()
+            } else {
+                self.column(field)
+            };
+            build_predicate(&column, operator)
+        })
+    }
+}
+
+fn build_predicate(column: &str, operator: &RuleOperator) -> String {
+    // text is matched without minding the case by lowering the field
+    let lowered = format!("TO_LOWER({column})");
+    let like = |pattern: String| format!("{lowered} LIKE {}", string(&pattern.to_lowercase()));
+    let list = |values: Vec<&str>| {
+        format!(
+            "{lowered} IN ({})",
+            values
+                .iter()
+                .map(|value| string(&value.to_lowercase()))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    };
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => {
+            list(values.iter().map(|value| value.as_ref()).collect())
+        }
+        RuleOperator::Equals(SiemField::Text(value)) => {
+            format!("{lowered} == {}", string(&value.to_lowercase()))
+        }
+        RuleOperator::Equals(value) => format!("{column} == {value}"),
+        RuleOperator::StartsWith(pattern) => like(format!("{}*", escape_like(pattern))),
+        RuleOperator::EndsWith(pattern) => like(format!("*{}", escape_like(pattern))),
+        RuleOperator::Contains(pattern) => like(format!("*{}*", escape_like(pattern))),
+        RuleOperator::Matches(regex) => {
+            format!("{column} RLIKE {}", string(&rlike(regex.as_str())))
+        }
+        RuleOperator::GT(value) => format!("{column} > {value}"),
+        RuleOperator::GTE(value) => format!("{column} >= {value}"),
+        RuleOperator::LT(value) => format!("{column} < {value}"),
+        RuleOperator::LTE(value) => format!("{column} <= {value}"),
+        RuleOperator::SameNet((ip, net)) => {
+            let address = match ip {
+                SiemIp::V4(ip) => ipv4_to_str(*ip),
+                SiemIp::V6(ip) => ipv6_to_str(*ip),
+            };
+            format!("CIDR_MATCH({column}, \"{address}/{net}\")")
+        }
+        RuleOperator::IsNull(_) => format!("{column} IS NULL"),
+        RuleOperator::Any(operators) => {
+            // Several text equalities of the same field are a membership in their list
+            let values: Option<Vec<&str>> = operators
+                .iter()
+                .map(|operator| match operator.as_ref() {
+                    RuleOperator::Equals(SiemField::Text(value)) => Some(value.as_ref()),
+                    _ => None,
+                })
+                .collect();
+            match values {
+                Some(values) if values.len() > 1 => list(values),
+                _ => join_predicates(
+                    operators
+                        .iter()
+                        .map(|operator| build_predicate(column, operator))
+                        .collect(),
+                    "OR",
+                ),
+            }
+        }
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(column, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(column, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// An ES|QL string literal, triple quoted (without escapes) when it holds backslashes
+/// or quotes.
+fn string(value: &str) -> String {
+    if !value.contains(['\\', '"']) {
+        format!("\"{value}\"")
+    } else if !value.contains("\"\"\"") && !value.ends_with('"') {
+        format!("\"\"\"{value}\"\"\"")
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// Escapes the LIKE wildcards of a literal value.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('*', "\\*")
+        .replace('?', "\\?")
+}
+
+/// RLIKE matches the whole value with the Lucene syntax, which has no anchors: the sigma
+/// regex is padded unless anchored.
+fn rlike(regex: &str) -> String {
+    let (start, regex) = match regex.strip_prefix('^') {
+        Some(regex) => ("", regex),
+        None => (".*", regex),
+    };
+    let (regex, end) = match regex.strip_suffix('$') {
+        Some(stripped) if !stripped.ends_with('\\') => (stripped, ""),
+        _ => (regex, ".*"),
+    };
+    if start.is_empty() && end.is_empty() {
+        regex.to_string()
+    } else {
+        format!("{start}({regex}){end}")
+    }
+}
+
+/// The ES|QL time span of a sigma timeframe (`30s`, `5m`, `1h`, `2d`).
+fn time_span(timeframe: &str) -> String {
+    let (amount, unit) = parse_timeframe(timeframe);
+    let unit = match unit {
+        's' => "seconds",
+        'h' => "hours",
+        'd' => "days",
+        _ => "minutes",
+    };
+    format!("{amount} {unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::esql::EsqlBackend;
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+
+    #[test]
+    pub fn test_query() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        assert_eq!(
+            EsqlBackend::default().convert_rule(rule),
+            r#"FROM winlogbeat-*, logs-windows.*
+| WHERE (((TO_LOWER(process.executable) LIKE """*\\cmd.exe""" OR TO_LOWER(process.pe.original_file_name) == "cmd.exe") AND TO_LOWER(process.parent.executable) LIKE """*\\7zfm.exe""") AND NOT ((TO_LOWER(process.command_line) LIKE "*/c*" OR TO_LOWER(process.command_line) LIKE "*/k*" OR TO_LOWER(process.command_line) LIKE "*/r*") OR process.command_line IS NULL))"#
+        );
+    }
+
+    #[test]
+    pub fn test_stats() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Internal Scan
+logsource:
+    product: zeek
+    service: conn
+detection:
+    selection:
+        DestinationIp|cidr: 10.0.0.0/8
+        Protocol:
+            - tcp
+            - udp
+    condition: selection | count(DestinationPort) by SourceIp > 100
+    timeframe: 10m
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            EsqlBackend::default().convert_rule(rule),
+            r#"FROM logs-*
+| WHERE (CIDR_MATCH(destination.ip, "10.0.0.0/8") AND TO_LOWER(network.transport) IN ("tcp", "udp"))
+| STATS event_count = COUNT_DISTINCT(destination.port) BY window = DATE_TRUNC(10 minutes, @timestamp), source.ip
+| WHERE event_count > 100"#
+        );
+    }
+}
@@ -1,332 +1,381 @@
-use crate::backend::{BackEnd, QueryBuilder};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
-use crate::prelude::SiemField;
-use crate::sigma::components::rule::sigma::SigmaRule;
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::sigma::{ConditionAggregation, SigmaRule, SigmaRuleLogSource};
+use crate::prelude::{AlertSeverity, SiemField};
 use crate::sigma::components::rule::RuleOperator;
 use crate::sigma::components::rule::SiemRule;
-use crate::utils::parse_condition;
+use crate::utils::{
+    join_predicates, logsource_mapping, parse_aggregation, render_condition, stable_uuid,
+    timeframe_seconds,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::process::exit;
 
+/// The id of the "All messages" stream every Graylog setup has.
+const DEFAULT_STREAM: &str = "000000000000000000000001";
+
+/// The artifacts the Graylog backend is able to produce.
 #[derive(Clone, Debug, Default)]
-pub struct GrayLogBackend {}
+pub enum GrayLogOutput {
+    /// The bare search query.
+    #[default]
+    Query,
+    /// A content pack holding the Event Definition of the rule.
+    EventDefinition,
+}
 
-impl BackEnd for GrayLogBackend {
-    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        self.build_query(&sigma_rule)
-    }
+#[derive(Clone, Debug, Default)]
+pub struct GrayLogBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: GrayLogOutput,
 }
 
-impl QueryBuilder for GrayLogBackend {
-    fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
+impl GrayLogBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: GrayLogOutput) -> Self {
+        GrayLogBackend { mappings, output }
+    }
+
+    /// The ids of the streams holding the logsource events, from the `logsource.*` mappings.
+    fn streams(&self, logsource: &SigmaRuleLogSource) -> Vec<String> {
+        match logsource_mapping(self.mappings.as_ref(), logsource) {
+            Some(streams) => streams
+                .split(',')
+                .map(|stream| stream.trim().to_string())
+                .filter(|stream| !stream.is_empty())
+                .collect(),
+            None => vec![DEFAULT_STREAM.to_string()],
+        }
+    }
+
+    fn build_event_definition(&self, sigma_rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
             Err(e) => {
                 eprintln!("{:?}", e);
                 exit(1);
             }
         };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let mapped_cond_name = cond_name;
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\").replace('*', "");
-                        query_str.push_str(format!("{mapped_cond_name}:{pattern}*").as_str());
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\").replace('*', "");
-                        query_str.push_str(format!("{mapped_cond_name}:*{pattern}").as_str());
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\").replace('*', "");
-                        query_str.push_str(format!("{mapped_cond_name}:*{pattern}*").as_str());
-                    }
-                    RuleOperator::Equals(field) => match field {
-                        SiemField::Array(_) => {}
-                        _ => {
-                            query_str.push_str(format!("{mapped_cond_name}:{field}").as_str());
-                        }
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let priority = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL | AlertSeverity::LOW => 1,
+            AlertSeverity::MEDIUM => 2,
+            AlertSeverity::HIGH | AlertSeverity::CRITICAL => 3,
+        };
+        let interval =
+            timeframe_seconds(sigma_rule.detection.timeframe.as_deref().unwrap_or("5m")) * 1000;
+        let (group_by, series, conditions) = match &aggregation {
+            Some(aggregation) => self.build_aggregation(aggregation),
+            None => (vec![], vec![], json!({ "expression": null })),
+        };
+        let streams: Vec<Value> = self
+            .streams(&sigma_rule.logsource)
+            .into_iter()
+            .map(|stream| json!({ "@type": "string", "@value": stream }))
+            .collect();
+        let content_pack = json!({
+            "v": "1",
+            "id": rule_uuid(sigma_rule, "content_pack"),
+            "rev": 1,
+            "name": sigma_rule.title,
+            "summary": sigma_rule.title,
+            "description": sigma_rule.description.as_deref().unwrap_or_default(),
+            "vendor": sigma_rule.author.as_deref().unwrap_or("sigma"),
+            "url": "",
+            "parameters": [],
+            "entities": [{
+                "v": "1",
+                "type": { "name": "event_definition", "version": "1" },
+                "id": rule_uuid(sigma_rule, "event_definition"),
+                "data": {
+                    "title": { "@type": "string", "@value": sigma_rule.title },
+                    "description": {
+                        "@type": "string",
+                        "@value": sigma_rule.description.as_deref().unwrap_or_default()
                     },
-                    RuleOperator::Any(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{}", val));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{}*", pattern));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("*{}", pattern));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("*{}*", pattern));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" ");
-                        query_str.push_str(format!("{cond_name}:({joined_sub_queries})").as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{}:{}", cond_name, val));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{}:{}*", cond_name, pattern));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("{}:*{}", cond_name, pattern));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("{}:*{}*", cond_name, pattern));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" AND ");
-                        query_str.push_str(joined_sub_queries.to_string().as_str());
+                    "priority": { "@type": "integer", "@value": priority },
+                    "alert": { "@type": "boolean", "@value": true },
+                    "config": {
+                        "type": { "@type": "string", "@value": "aggregation-v1" },
+                        "query": { "@type": "string", "@value": self.build_query(sigma_rule) },
+                        "query_parameters": [],
+                        "streams": streams,
+                        "group_by": group_by,
+                        "series": series,
+                        "conditions": conditions,
+                        "search_within_ms": { "@type": "long", "@value": interval },
+                        "execute_every_ms": { "@type": "long", "@value": interval }
+                    },
+                    "field_spec": {},
+                    "key_spec": [],
+                    "notification_settings": {
+                        "grace_period_ms": { "@type": "long", "@value": 0 },
+                        "backlog_size": { "@type": "integer", "@value": 0 }
+                    },
+                    "notifications": [],
+                    "storage": [{
+                        "type": { "@type": "string", "@value": "persist-to-streams-v1" },
+                        "streams": [{ "@type": "string", "@value": "000000000000000000000002" }]
+                    }]
+                },
+                "constraints": [{ "type": "server-version", "version": ">=4.0.0" }]
+            }]
+        });
+        serde_json::to_string_pretty(&content_pack).unwrap_or_default()
+    }
+
+    /// The group by fields, series and conditions of an aggregation event definition.
+    fn build_aggregation(
+        &self,
+        aggregation: &ConditionAggregation,
+    ) -> (Vec<Value>, Vec<Value>, Value) {
+        let group_by: Vec<Value> = aggregation
+            .group_by
+            .iter()
+            .map(
+                |field| json!({ "@type": "string", "@value": self.get_mapping(field.to_string()) }),
+            )
+            .collect();
+        let field = aggregation
+            .field
+            .as_ref()
+            .map(|field| self.get_mapping(field.to_string()));
+        let function = match (aggregation.function.as_ref(), &field) {
+            ("count", Some(_)) => "card",
+            (function, _) => function,
+        };
+        let series_id = format!("{function}-{}", field.as_deref().unwrap_or_default());
+        let series = json!({
+            "id": { "@type": "string", "@value": series_id },
+            "function": { "@type": "string", "@value": function },
+            "field": field.map(|field| json!({ "@type": "string", "@value": field }))
+        });
+        let expression = match aggregation.operator.as_ref() {
+            "==" => "==",
+            ">=" => ">=",
+            "<=" => "<=",
+            "<" => "<",
+            _ => ">",
+        };
+        let conditions = json!({
+            "expression": {
+                "expr": expression,
+                "left": { "expr": "number-ref", "ref": series_id },
+                "right": { "expr": "number", "value": aggregation.value }
+            }
+        });
+        (group_by, vec![series], conditions)
+    }
+}
+
+impl BackEnd for GrayLogBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        match self.output {
+            GrayLogOutput::Query => {
+                let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+                    Ok(aggregation) => aggregation,
+                    Err(e) => {
+                        eprintln!("{:?}", e);
+                        exit(1);
                     }
-                    _ => {}
                 };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
+                if aggregation.is_some() {
+                    eprintln!(
+                        "Graylog searches do not aggregate, the aggregation of '{}' is dropped: convert it to an Event Definition instead",
+                        sigma_rule.title
+                    );
                 }
+                self.build_query(&sigma_rule)
             }
-            let mut condition_query = String::new();
-            condition_query.push('(');
-            condition_query.push_str(conditions_list.join(" AND ").as_str());
-            condition_query.push(')');
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
+            GrayLogOutput::EventDefinition => self.build_event_definition(&sigma_rule),
         }
+    }
+}
 
-        // Check the conditions of this subrule
-        for condition in conditions {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" OR ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" AND ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" NOT ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" OR ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" AND ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" AND ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" OR ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
+impl RequiresMappings for GrayLogBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        args.unwrap_or_default()
+    }
+}
+
+impl QueryBuilder for GrayLogBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            build_predicate(&escape_term(&self.get_mapping(field.to_string())), operator)
+        })
+    }
+}
+
+fn build_predicate(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => join_predicates(
+            values
+                .iter()
+                .map(|value| term(field, &format!("\"{}\"", escape_phrase(value))))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::Equals(SiemField::Text(value)) => {
+            term(field, &format!("\"{}\"", escape_phrase(value)))
+        }
+        RuleOperator::Equals(value) => term(field, &escape_term(&value.to_string())),
+        RuleOperator::StartsWith(pattern) => term(field, &format!("{}*", escape_term(pattern))),
+        // Leading wildcards are disabled by default in Graylog, regexes don't need them.
+        RuleOperator::EndsWith(pattern) => term(field, &format!("/.*{}/", escape_regex(pattern))),
+        RuleOperator::Contains(pattern) => term(field, &format!("/.*{}.*/", escape_regex(pattern))),
+        RuleOperator::Matches(regex) => term(field, &lucene_regex(regex.as_str())),
+        RuleOperator::SameNet((ip, net)) => term(field, &format!("\"{ip}/{net}\"")),
+        RuleOperator::IsNull(_) => format!("NOT _exists_:{field}"),
+        RuleOperator::Any(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
             }
         }
-        query_str.clone()
+        _ => String::new(),
+    }
+}
+
+/// Keywords (conditions without a field) are searched in the message.
+fn term(field: &str, value: &str) -> String {
+    if field.is_empty() {
+        value.to_string()
+    } else {
+        format!("{field}:{value}")
+    }
+}
+
+/// Escapes the characters of the search syntax, and the spaces, of an unquoted term.
+fn escape_term(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_whitespace() || "+-=&|><!(){}[]^\"~*?:\\/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes the body of a quoted phrase.
+fn escape_phrase(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes a literal value to be used inside a `/.../` regex.
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if ".?+*|{}[]()\"\\#@&<>~/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Graylog regexes match whole terms without `^`/`$` anchors, so an unanchored sigma regex
+/// is surrounded with `.*`.
+fn lucene_regex(pattern: &str) -> String {
+    let (start, pattern) = match pattern.strip_prefix('^') {
+        Some(pattern) => ("", pattern),
+        None => (".*", pattern),
+    };
+    let (pattern, end) = match pattern.strip_suffix('$') {
+        Some(pattern) if !pattern.ends_with('\\') => (pattern, ""),
+        _ => (pattern, ".*"),
+    };
+    let pattern = pattern.replace('/', "\\/");
+    if start.is_empty() && end.is_empty() {
+        format!("/{pattern}/")
+    } else {
+        format!("/{start}({pattern}){end}/")
+    }
+}
+
+/// Content pack entities are identified by UUIDs, derived from the rule so converting the same
+/// rule twice gives the same content pack.
+fn rule_uuid(sigma_rule: &SigmaRule, entity: &str) -> String {
+    let rule = sigma_rule.id.as_deref().unwrap_or(&sigma_rule.title);
+    stable_uuid(&format!("{entity}:{rule}")).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::graylog::{GrayLogBackend, GrayLogOutput};
+    use crate::backend::{BackEnd, QueryBuilder};
+    use crate::parse_sigma_rule;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_query() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        assert_eq!(
+            GrayLogBackend::default().build_query(&rule),
+            r#"(((Image:/.*\\cmd\.exe/ OR OriginalFileName:"Cmd.Exe") AND ParentImage:/.*\\7zFM\.exe/) AND NOT ((CommandLine:/.*\/c.*/ OR CommandLine:/.*\/k.*/ OR CommandLine:/.*\/r.*/) OR NOT _exists_:CommandLine))"#
+        );
+    }
+
+    #[test]
+    pub fn test_event_definition() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+        TargetUserName|startswith: "adm in/x"
+    timeframe: 10m
+    condition: selection | count() by IpAddress > 10
+level: high
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([(
+            "logsource.windows".to_string(),
+            "5f1e4a1c2ab9e24b6e2c1a0f".to_string(),
+        )]);
+        let backend = GrayLogBackend::new(Some(mappings), GrayLogOutput::EventDefinition);
+        assert_eq!(
+            backend.build_query(&rule),
+            r#"(EventID:4625 AND TargetUserName:adm\ in\/x*)"#
+        );
+        let content_pack: serde_json::Value =
+            serde_json::from_str(&backend.convert_rule(rule)).unwrap();
+        assert_eq!(
+            content_pack["entities"][0]["id"],
+            "5a415ee8-0895-5a98-86cc-469c978c76ec"
+        );
+        let data = &content_pack["entities"][0]["data"];
+        assert_eq!(data["priority"]["@value"], 3);
+        let config = &data["config"];
+        assert_eq!(config["streams"][0]["@value"], "5f1e4a1c2ab9e24b6e2c1a0f");
+        assert_eq!(config["search_within_ms"]["@value"], 600000);
+        assert_eq!(config["group_by"][0]["@value"], "IpAddress");
+        assert_eq!(config["series"][0]["function"]["@value"], "count");
+        assert_eq!(config["conditions"]["expression"]["right"]["value"], 10);
     }
 }
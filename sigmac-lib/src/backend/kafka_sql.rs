@@ -1,341 +1,213 @@
-use crate::backend::{BackEnd, QueryBuilder};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
-use crate::prelude::SiemField;
-use crate::sigma::components::rule::sigma::SigmaRule;
-use crate::sigma::components::rule::RuleOperator;
-use crate::sigma::components::rule::SiemRule;
-use crate::utils::parse_condition;
+use crate::backend::sql::SqlDialect;
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::sigma::components::rule::sigma::{ConditionAggregation, SigmaRule, SigmaRuleLogSource};
+use crate::utils::{
+    backend_option, logsource_mapping, parse_aggregation, parse_timeframe, upper_snake_case,
+};
+use std::collections::HashMap;
 use std::process::exit;
 
+/// What the ksqlDB backend emits for a rule.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum KafkaSqlOutput {
+    /// A transient push query.
+    #[default]
+    Query,
+    /// A persistent `CREATE STREAM ... AS SELECT` (or `CREATE TABLE` for aggregations).
+    Stream,
+}
+
 #[derive(Clone, Debug, Default)]
-pub struct KafkaSqlBackend {}
+pub struct KafkaSqlBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: KafkaSqlOutput,
+}
 
-impl BackEnd for KafkaSqlBackend {
-    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        let query = self.build_query(&sigma_rule);
-        format!("SELECT * FROM TABLE_NAME WHERE {query};")
+impl KafkaSqlBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: KafkaSqlOutput) -> Self {
+        KafkaSqlBackend { mappings, output }
+    }
+
+    /// The ksqlDB stream holding the events of the sigma logsource.
+    fn source_stream(&self, logsource: &SigmaRuleLogSource) -> String {
+        if let Some(name) = logsource_mapping(self.mappings.as_ref(), logsource) {
+            return name;
+        }
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let service = logsource.service.as_deref().unwrap_or_default();
+        let category = logsource.category.as_deref().unwrap_or_default();
+        let parts: Vec<&str> = [product, service, category]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect();
+        if parts.is_empty() {
+            "EVENTS".to_string()
+        } else {
+            upper_snake_case(&parts.join("_"))
+        }
+    }
+
+    /// `WINDOW TUMBLING` over the rule timeframe, or `WINDOW HOPPING` when the
+    /// `window.advance` option gives its interval.
+    fn window(&self, rule: &SigmaRule) -> String {
+        let size = ksql_duration(rule.detection.timeframe.as_deref().unwrap_or("5m"));
+        match backend_option(self.mappings.as_ref(), "window.advance") {
+            Some(advance) => format!(
+                "WINDOW HOPPING (SIZE {size}, ADVANCE BY {})",
+                ksql_duration(advance)
+            ),
+            None => format!("WINDOW TUMBLING (SIZE {size})"),
+        }
+    }
+
+    /// The windowed aggregation of the matching events, `None` when ksqlDB can't group them.
+    fn build_aggregation(
+        &self,
+        rule: &SigmaRule,
+        aggregation: &ConditionAggregation,
+        stream: &str,
+    ) -> Option<String> {
+        let dialect = SqlDialect::KSQL;
+        let group_by = match &aggregation.group_by {
+            Some(group_by) => dialect.quote_identifier(&self.get_mapping(group_by.to_string())),
+            None => {
+                eprintln!(
+                    "ksqlDB aggregations need a group-by field, '{}' is converted without its threshold",
+                    rule.title
+                );
+                return None;
+            }
+        };
+        let function = aggregation.function.to_uppercase();
+        let value = match (function.as_str(), &aggregation.field) {
+            ("COUNT", Some(field)) => format!(
+                "COUNT_DISTINCT({})",
+                dialect.quote_identifier(&self.get_mapping(field.to_string()))
+            ),
+            (_, Some(field)) => format!(
+                "{function}({})",
+                dialect.quote_identifier(&self.get_mapping(field.to_string()))
+            ),
+            _ => format!("{function}(*)"),
+        };
+        let operator = match aggregation.operator.as_ref() {
+            "==" => "=",
+            operator => operator,
+        };
+        Some(format!(
+            "SELECT {group_by}, {value} AS EVENT_COUNT FROM {stream} {} WHERE {} GROUP BY {group_by} HAVING {value} {operator} {} EMIT CHANGES",
+            self.window(rule),
+            self.build_query(rule),
+            aggregation.value
+        ))
     }
 }
 
-impl QueryBuilder for KafkaSqlBackend {
-    fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
+impl BackEnd for KafkaSqlBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
             Err(e) => {
                 eprintln!("{:?}", e);
                 exit(1);
             }
         };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let mapped_cond_name = cond_name;
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str
-                            .push_str(format!("{mapped_cond_name}=ilike '{pattern}%'").as_str());
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str
-                            .push_str(format!("{mapped_cond_name}=ilike '%{pattern}'").as_str());
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str
-                            .push_str(format!("{mapped_cond_name}=ilike '%{pattern}%'").as_str());
-                    }
-                    RuleOperator::Equals(field) => match field {
-                        SiemField::Array(elements) => {
-                            let joined_query = elements
-                                .iter()
-                                .map(|val| format!("{mapped_cond_name} = '{val}'"))
-                                .collect::<Vec<_>>()
-                                .join(" or ");
-                            query_str.push_str(format!("({joined_query})").as_str())
-                        }
-                        _ => {
-                            query_str.push_str(format!("{mapped_cond_name} = '{field}'").as_str());
-                        }
-                    },
-                    RuleOperator::Any(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{cond_name} = '{val}'"));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{cond_name}=ilike '{pattern}%'"));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("{cond_name}=ilike '%{pattern}'"));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("{cond_name}=ilike '%{pattern}%'"));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" OR ");
-                        query_str.push_str(format!("({})", joined_sub_queries).as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{cond_name} = '{val}'"));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{cond_name}=ilike '{pattern}%'"));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("{cond_name}=ilike '%{pattern}'"));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("{cond_name}=ilike '%{pattern}%'"));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" AND ");
-                        query_str.push_str(joined_sub_queries.as_str());
-                    }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
-            }
-            let mut condition_query = String::new();
-            condition_query.push_str(format!("({})", conditions_list.join(" AND ")).as_str());
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
+        let stream = self.source_stream(&sigma_rule.logsource);
+        let aggregated = aggregation
+            .and_then(|aggregation| self.build_aggregation(&sigma_rule, &aggregation, &stream));
+        let (kind, select) = match aggregated {
+            Some(select) => ("TABLE", select),
+            None => (
+                "STREAM",
+                format!(
+                    "SELECT * FROM {stream} WHERE {} EMIT CHANGES",
+                    self.build_query(&sigma_rule)
+                ),
+            ),
+        };
+        match self.output {
+            KafkaSqlOutput::Query => format!("{select};"),
+            // Windowed aggregations can only be materialized as tables in ksqlDB.
+            KafkaSqlOutput::Stream => format!(
+                "CREATE {kind} SIGMA_{} AS {select};",
+                upper_snake_case(&sigma_rule.title)
+            ),
         }
+    }
+}
 
-        // Check the conditions of this subrule
-        for condition in conditions {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" OR ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" AND ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" NOT ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" or ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" AND ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" AND ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" or ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" or ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" and ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" not ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" or ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" and ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        query_str.clone()
+impl RequiresMappings for KafkaSqlBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        args.unwrap_or_default()
+    }
+}
+
+impl QueryBuilder for KafkaSqlBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        SqlDialect::KSQL.build_where(rule, &|field| self.get_mapping(field.to_string()))
+    }
+}
+
+/// Converts a sigma timeframe (`30s`, `5m`, `1h`, `2d`) into a ksqlDB duration.
+fn ksql_duration(timeframe: &str) -> String {
+    let (amount, unit) = parse_timeframe(timeframe);
+    let unit = match unit {
+        's' => "SECONDS",
+        'h' => "HOURS",
+        'd' => "DAYS",
+        _ => "MINUTES",
+    };
+    format!("{amount} {unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::kafka_sql::{KafkaSqlBackend, KafkaSqlOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_stream() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let mappings = HashMap::from([("logsource.windows".to_string(), "WINLOGBEAT".to_string())]);
+        let stream =
+            KafkaSqlBackend::new(Some(mappings), KafkaSqlOutput::Stream).convert_rule(rule);
+        assert!(stream.starts_with("CREATE STREAM SIGMA_"));
+        assert!(stream.contains(" AS SELECT * FROM WINLOGBEAT WHERE ((("));
+        assert!(stream.contains("LCASE(Image) LIKE '%\\\\cmd.exe' ESCAPE '\\'"));
+        assert!(stream.ends_with(" EMIT CHANGES;"));
+    }
+
+    #[test]
+    pub fn test_windowed_aggregation() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 10m
+    condition: selection | count() by IpAddress > 10
+level: medium
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            KafkaSqlBackend::new(None, KafkaSqlOutput::Stream).convert_rule(rule.clone()),
+            "CREATE TABLE SIGMA_MANY_FAILED_LOGONS AS SELECT IpAddress, COUNT(*) AS EVENT_COUNT FROM WINDOWS_SECURITY WINDOW TUMBLING (SIZE 10 MINUTES) WHERE EventID = 4625 GROUP BY IpAddress HAVING COUNT(*) > 10 EMIT CHANGES;"
+        );
+        let mappings = HashMap::from([("option.window.advance".to_string(), "1m".to_string())]);
+        assert!(KafkaSqlBackend::new(Some(mappings), KafkaSqlOutput::Query)
+            .convert_rule(rule)
+            .contains("FROM WINDOWS_SECURITY WINDOW HOPPING (SIZE 10 MINUTES, ADVANCE BY 1 MINUTES) WHERE"));
     }
 }
@@ -1,27 +1,318 @@
 use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
+use crate::prelude::rule::sigma::{
+    ConditionAggregation, ConditionExpression, ConditionTree, SigmaDetectionCondition,
+    SigmaRuleLogSource,
+};
 use crate::prelude::rule::RuleCondition;
-use crate::prelude::SiemField;
+use crate::prelude::{AlertSeverity, SiemField};
 use crate::sigma::components::rule::sigma::SigmaRule;
 use crate::sigma::components::rule::RuleOperator;
 use crate::sigma::components::rule::SiemRule;
 use crate::sigma::utilities::types::LogString;
-use crate::utils::parse_condition;
+use crate::utils::{
+    logsource_mapping, parse_aggregation, parse_condition, parse_condition_tree, timeframe_seconds,
+};
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::process::exit;
 
+/// The artifacts the LogRhythm backend is able to produce.
 #[derive(Clone, Debug, Default)]
-pub struct LogRhythmBackend {}
+pub enum LogRhythmOutput {
+    /// The single line query.
+    #[default]
+    Query,
+    /// A web console search, filtering on the metadata fields of the log source types.
+    WebConsoleSearch,
+    /// An AI Engine rule made of a single log observed block.
+    AiEngineRule,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LogRhythmBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: LogRhythmOutput,
+}
+
+impl LogRhythmBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: LogRhythmOutput) -> Self {
+        LogRhythmBackend { mappings, output }
+    }
+
+    /// The LogRhythm log source types of the sigma logsource, the `logsource.*` mappings
+    /// holding a comma separated list of them.
+    fn log_source_types(&self, logsource: &SigmaRuleLogSource) -> Vec<String> {
+        if let Some(types) = logsource_mapping(self.mappings.as_ref(), logsource) {
+            return types
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect();
+        }
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let service = logsource.service.as_deref().unwrap_or_default();
+        let name = match (product, service) {
+            ("windows", "security") => "MS Windows Event Logging XML - Security",
+            ("windows", "system") => "MS Windows Event Logging XML - System",
+            ("windows", "application") => "MS Windows Event Logging XML - Application",
+            ("windows", "powershell") | ("windows", "powershell-classic") => {
+                "MS Windows Event Logging XML - PowerShell"
+            }
+            ("windows", "sysmon") | ("windows", "") => "MS Windows Event Logging XML - Sysmon",
+            ("windows", _) => "MS Windows Event Logging XML - Security",
+            ("linux", _) => "Syslog - Linux Host",
+            ("aws", _) => "AWS CloudTrail",
+            ("azure", _) => "Cloud - Azure Event Hub",
+            ("okta", _) => "Okta System Log",
+            _ => return vec![],
+        };
+        vec![name.to_string()]
+    }
+
+    /// The name of the LogRhythm metadata field holding a sigma field.
+    fn metadata_field(&self, field: &str) -> String {
+        let mapped = self.get_mapping(field.to_string());
+        let metadata = match mapped.trim_end_matches(".text") {
+            "process.executable" => "Process Name",
+            "process.parent.executable" => "Parent Process Path",
+            "process.parent.name" => "Parent Process Name",
+            "process.command_line" => "Command",
+            "process.pid" => "Process ID",
+            "process.parent.pid" => "Parent Process ID",
+            "winlog.event_id" => "Vendor Message ID",
+            "winlog.computer_name" => "Host (Impacted)",
+            "file.path" | "registry.path" => "Object",
+            "file.name" => "Object Name",
+            "source.ip" => "IP Address (Origin)",
+            "source.domain" => "Host (Origin)",
+            "source.port" => "TCP/UDP Port (Origin)",
+            "destination.ip" => "IP Address (Impacted)",
+            "destination.domain" => "Host (Impacted)",
+            "destination.port" => "TCP/UDP Port (Impacted)",
+            "network.protocol" => "Protocol",
+            "dns.question.name" => "Domain (Impacted)",
+            "user.name" => "User (Origin)",
+            "user.domain" => "Domain (Origin)",
+            "file.code_signature.status" => "Status",
+            _ => return mapped,
+        };
+        metadata.to_string()
+    }
+
+    /// The filter group of the detection condition. Sibling values of a filter item are ORed,
+    /// negations exclude the matching logs.
+    fn build_filter_group(&self, rule: &SigmaRule) -> Value {
+        let condition = match parse_condition_tree(&rule.detection.condition) {
+            Ok(condition) => condition,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let identifiers: Vec<LogString> =
+            rule.detection.search_identifiers.keys().cloned().collect();
+        self.tree_filter(rule, &condition.expand(&identifiers))
+    }
+
+    fn tree_filter(&self, rule: &SigmaRule, condition: &ConditionTree) -> Value {
+        match condition {
+            ConditionTree::Identifier(name) => {
+                let groups = rule
+                    .detection
+                    .search_identifiers
+                    .get(name.as_ref())
+                    .map(|search| search.condition_groups())
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|group| {
+                        filter_group(
+                            "And",
+                            group
+                                .iter()
+                                .map(|condition| {
+                                    self.filter_item(
+                                        &rule.title,
+                                        &self.metadata_field(&condition.field),
+                                        &condition.operator,
+                                    )
+                                })
+                                .collect(),
+                        )
+                    })
+                    .collect();
+                filter_group("Or", groups)
+            }
+            ConditionTree::And(nodes) => filter_group(
+                "And",
+                nodes
+                    .iter()
+                    .map(|node| self.tree_filter(rule, node))
+                    .collect(),
+            ),
+            ConditionTree::Not(node) => exclude(self.tree_filter(rule, node)),
+            ConditionTree::Or(nodes) => filter_group(
+                "Or",
+                nodes
+                    .iter()
+                    .map(|node| self.tree_filter(rule, node))
+                    .collect(),
+            ),
+            // expanded away by build_filter_group
+            ConditionTree::AnyOf(_) | ConditionTree::AllOf(_) => filter_group("Or", vec![]),
+        }
+    }
+
+    /// The filter of the condition of a field. The operators LogRhythm has no match type for
+    /// give an item without values, which `filter_group` drops.
+    fn filter_item(&self, title: &str, field: &str, operator: &RuleOperator) -> Value {
+        match operator {
+            RuleOperator::Any(operators) => {
+                let values: Option<Vec<Value>> = operators
+                    .iter()
+                    .map(|operator| filter_value(operator))
+                    .collect();
+                match values {
+                    Some(values) => json!({
+                        "filterType": field,
+                        "filterMode": "Include",
+                        "values": values
+                    }),
+                    None => filter_group(
+                        "Or",
+                        operators
+                            .iter()
+                            .map(|operator| self.filter_item(title, field, operator))
+                            .collect(),
+                    ),
+                }
+            }
+            RuleOperator::All(operators) => filter_group(
+                "And",
+                operators
+                    .iter()
+                    .map(|operator| self.filter_item(title, field, operator))
+                    .collect(),
+            ),
+            RuleOperator::Not(operator) => exclude(self.filter_item(title, field, operator)),
+            RuleOperator::Equals(SiemField::Array(values)) => json!({
+                "filterType": field,
+                "filterMode": "Include",
+                "values": values
+                    .iter()
+                    .map(|value| json!({ "matchType": "Equals", "value": value }))
+                    .collect::<Vec<Value>>()
+            }),
+            operator => {
+                let values: Vec<Value> = filter_value(operator).into_iter().collect();
+                if values.is_empty() {
+                    eprintln!(
+                        "LogRhythm has no filter for the condition of '{title}' on '{field}', it is dropped"
+                    );
+                }
+                json!({
+                    "filterType": field,
+                    "filterMode": "Include",
+                    "values": values
+                })
+            }
+        }
+    }
+
+    fn build_web_console_search(&self, sigma_rule: &SigmaRule) -> String {
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let search = json!({
+            "name": sigma_rule.title,
+            "description": sigma_rule.description.as_deref().unwrap_or_default(),
+            "severity": severity_name(&siem_rule.alert.severity),
+            "maxMsgsToQuery": 1000,
+            "queryTimeout": 60,
+            "dateCriteria": {
+                "useInsertedDate": false,
+                "lastIntervalValue": 24,
+                "lastIntervalUnit": "Hours"
+            },
+            "logSourceTypes": self.log_source_types(&sigma_rule.logsource),
+            "queryFilter": {
+                "msgFilterType": "Grouped",
+                "filterGroup": self.build_filter_group(sigma_rule)
+            }
+        });
+        serde_json::to_string_pretty(&search).unwrap_or_default()
+    }
+
+    fn build_ai_engine_rule(&self, sigma_rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let mut block = json!({
+            "blockNumber": 1,
+            "blockType": "Log",
+            "logSourceTypes": self.log_source_types(&sigma_rule.logsource),
+            "primaryCriteria": self.build_filter_group(sigma_rule),
+            "groupBy": [],
+            "threshold": null,
+            "evaluationPeriodSeconds": timeframe_seconds(
+                sigma_rule.detection.timeframe.as_deref().unwrap_or("5m")
+            )
+        });
+        if let Some(aggregation) = &aggregation {
+            block["groupBy"] = json!(aggregation
+                .group_by
+                .iter()
+                .map(|field| self.metadata_field(field))
+                .collect::<Vec<String>>());
+            block["threshold"] = self.build_threshold(aggregation);
+        }
+        let rule = json!({
+            "name": format!("Sigma: {}", sigma_rule.title),
+            "description": sigma_rule.description.as_deref().unwrap_or_default(),
+            "commonEventName": sigma_rule.title,
+            "riskRating": risk_rating(&siem_rule.alert.severity),
+            "severity": severity_name(&siem_rule.alert.severity),
+            "enabled": true,
+            "sigmaId": sigma_rule.id.as_deref().unwrap_or_default(),
+            "ruleBlocks": [block]
+        });
+        serde_json::to_string_pretty(&rule).unwrap_or_default()
+    }
+
+    /// Counts of logs, or of unique values of a field, compared to the aggregation value.
+    fn build_threshold(&self, aggregation: &ConditionAggregation) -> Value {
+        let (kind, field) = match (aggregation.function.as_ref(), &aggregation.field) {
+            ("count", Some(field)) => ("UniqueValues", Some(self.metadata_field(field))),
+            ("count", None) => ("LogCount", None),
+            (_, field) => (
+                "Quantity",
+                field.as_ref().map(|field| self.metadata_field(field)),
+            ),
+        };
+        json!({
+            "type": kind,
+            "field": field,
+            "operator": aggregation.operator.as_ref(),
+            "value": aggregation.value
+        })
+    }
+}
 
 impl BackEnd for LogRhythmBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        self.build_query(&sigma_rule)
+        match self.output {
+            LogRhythmOutput::Query => self.build_query(&sigma_rule),
+            LogRhythmOutput::WebConsoleSearch => self.build_web_console_search(&sigma_rule),
+            LogRhythmOutput::AiEngineRule => self.build_ai_engine_rule(&sigma_rule),
+        }
     }
 }
 
 impl RequiresMappings for LogRhythmBackend {
     fn get_field_map(&self) -> Option<HashMap<String, String>> {
-        None
+        self.mappings.clone()
     }
 
     fn get_default_field_name(&self, args: Option<String>) -> String {
@@ -42,6 +333,7 @@ impl RequiresMappings for LogRhythmBackend {
             ("ParentProcessId", "process.parent.pid"),
             ("ParentImage", "process.parent.executable"),
             ("ParentCommandLine", "process.parent.command_line"),
+            ("CommandLine", "process.command_line"),
             ("TargetFilename", "file.path"),
             ("SourceIp", "source.ip"),
             ("SourceHostname", "source.domain"),
@@ -447,3 +739,136 @@ impl QueryBuilder for LogRhythmBackend {
         query_str.clone()
     }
 }
+
+fn filter_group(operator: &str, filters: Vec<Value>) -> Value {
+    let mut filters: Vec<Value> = filters
+        .into_iter()
+        // items without values and empty groups filter nothing
+        .filter(|filter| {
+            !filter["values"].as_array().is_some_and(Vec::is_empty)
+                && !filter["filterItems"].as_array().is_some_and(Vec::is_empty)
+        })
+        .collect();
+    if filters.len() == 1 {
+        return filters.remove(0);
+    }
+    json!({
+        "filterGroupOperator": operator,
+        "filterMode": "Include",
+        "filterItems": filters
+    })
+}
+
+fn exclude(mut filter: Value) -> Value {
+    let mode = if filter["filterMode"] == "Exclude" {
+        "Include"
+    } else {
+        "Exclude"
+    };
+    filter["filterMode"] = json!(mode);
+    filter
+}
+
+/// A single value of a filter item, `None` for the operators needing a filter group.
+fn filter_value(operator: &RuleOperator) -> Option<Value> {
+    let (match_type, value) = match operator {
+        RuleOperator::Equals(SiemField::Array(_)) => return None,
+        RuleOperator::Equals(value) => ("Equals", value.to_string()),
+        RuleOperator::StartsWith(pattern) => ("StartsWith", pattern.to_string()),
+        RuleOperator::EndsWith(pattern) => ("EndsWith", pattern.to_string()),
+        RuleOperator::Contains(pattern) => ("Contains", pattern.to_string()),
+        RuleOperator::Matches(regex) => ("Regex", regex.as_str().to_string()),
+        RuleOperator::SameNet((ip, net)) => ("IPRange", format!("{ip}/{net}")),
+        RuleOperator::IsNull(_) => ("Null", String::new()),
+        _ => return None,
+    };
+    Some(json!({ "matchType": match_type, "value": value }))
+}
+
+fn severity_name(severity: &AlertSeverity) -> &'static str {
+    match severity {
+        AlertSeverity::INFORMATIONAL => "informational",
+        AlertSeverity::LOW => "low",
+        AlertSeverity::MEDIUM => "medium",
+        AlertSeverity::HIGH => "high",
+        AlertSeverity::CRITICAL => "critical",
+    }
+}
+
+/// AI Engine risk ratings go from 1 (low-low) to 9 (high-high).
+fn risk_rating(severity: &AlertSeverity) -> u8 {
+    match severity {
+        AlertSeverity::INFORMATIONAL => 1,
+        AlertSeverity::LOW => 3,
+        AlertSeverity::MEDIUM => 5,
+        AlertSeverity::HIGH => 7,
+        AlertSeverity::CRITICAL => 9,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::logrhythm::{LogRhythmBackend, LogRhythmOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+
+    #[test]
+    pub fn test_web_console_search() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let backend = LogRhythmBackend::new(None, LogRhythmOutput::WebConsoleSearch);
+        let search: serde_json::Value = serde_json::from_str(&backend.convert_rule(rule)).unwrap();
+        assert_eq!(search["severity"], "high");
+        assert_eq!(
+            search["logSourceTypes"][0],
+            "MS Windows Event Logging XML - Sysmon"
+        );
+        let group = &search["queryFilter"]["filterGroup"];
+        assert_eq!(group["filterGroupOperator"], "And");
+        let selection = &group["filterItems"][0];
+        assert_eq!(
+            selection["filterItems"][1]["filterType"],
+            "Parent Process Path"
+        );
+        assert_eq!(
+            selection["filterItems"][1]["values"][0],
+            serde_json::json!({ "matchType": "EndsWith", "value": "\\7zFM.exe" })
+        );
+        let filter = &group["filterItems"][1];
+        assert_eq!(filter["filterMode"], "Exclude");
+        assert_eq!(filter["filterItems"][0]["filterType"], "Command");
+        assert_eq!(filter["filterItems"][1]["values"][0]["matchType"], "Null");
+    }
+
+    #[test]
+    pub fn test_ai_engine_rule() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 10m
+    condition: selection | count() by IpAddress > 10
+level: medium
+"#,
+        )
+        .unwrap();
+        let backend = LogRhythmBackend::new(None, LogRhythmOutput::AiEngineRule);
+        let aie: serde_json::Value = serde_json::from_str(&backend.convert_rule(rule)).unwrap();
+        assert_eq!(aie["name"], "Sigma: Many Failed Logons");
+        assert_eq!(aie["riskRating"], 5);
+        let block = &aie["ruleBlocks"][0];
+        assert_eq!(
+            block["logSourceTypes"][0],
+            "MS Windows Event Logging XML - Security"
+        );
+        assert_eq!(block["primaryCriteria"]["filterType"], "Vendor Message ID");
+        assert_eq!(block["groupBy"][0], "IP Address (Origin)");
+        assert_eq!(block["threshold"]["type"], "LogCount");
+        assert_eq!(block["evaluationPeriodSeconds"], 600);
+    }
+}
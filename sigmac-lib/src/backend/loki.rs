@@ -0,0 +1,590 @@
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::sigma::{
+    ConditionAggregation, ConditionTree, SigmaRule, SigmaRuleLogSource,
+};
+use crate::prelude::rule::{RuleOperator, SiemRule};
+use crate::prelude::types::LogString;
+use crate::prelude::{AlertSeverity, SiemField, SiemIp};
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{
+    backend_option, logsource_mapping, parse_aggregation, parse_condition_tree, pascal_case,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::process::exit;
+
+/// What the Loki backend emits for a rule.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum LokiOutput {
+    /// The LogQL query.
+    #[default]
+    Query,
+    /// A ruler alerting rule group YAML.
+    Ruler,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LokiBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: LokiOutput,
+}
+
+/// A LogQL filter, negations being already pushed down to the comparisons since
+/// label filter expressions have no `not`.
+#[derive(Clone, Debug)]
+enum Filter {
+    /// A label filter expression (`Image=~"..."`).
+    Label(String),
+    /// A line filter (`|= "..."`), for conditions without a field.
+    Line(String),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+}
+
+impl LokiBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: LokiOutput) -> Self {
+        LokiBackend { mappings, output }
+    }
+
+    /// The stream selector of the logsource. The `logsource.*` mappings give either a full
+    /// selector (`{job="syslog"}`) or the value of its `job` label.
+    fn stream_selector(&self, logsource: &SigmaRuleLogSource) -> String {
+        let mapped = logsource_mapping(self.mappings.as_ref(), logsource);
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let service = logsource.service.as_deref().unwrap_or_default();
+        let category = logsource.category.as_deref().unwrap_or_default();
+        match mapped {
+            Some(selector) if selector.starts_with('{') => selector,
+            Some(job) => format!("{{job={}}}", logql_string(&job)),
+            None => {
+                let job = [product, category]
+                    .into_iter()
+                    .find(|part| !part.is_empty())
+                    .unwrap_or("sigma");
+                if service.is_empty() {
+                    format!("{{job={}}}", logql_string(job))
+                } else {
+                    format!(
+                        "{{job={}, service={}}}",
+                        logql_string(job),
+                        logql_string(service)
+                    )
+                }
+            }
+        }
+    }
+
+    /// The label of a sigma field once extracted by the `json` or `logfmt` parser, which
+    /// turns the characters labels can't hold into underscores.
+    fn label(&self, field: &str) -> String {
+        self.get_mapping(field.to_string())
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect()
+    }
+
+    fn condition_filter(&self, rule: &SigmaRule, tree: &ConditionTree, negated: bool) -> Filter {
+        match tree {
+            ConditionTree::Identifier(name) => {
+                let groups = rule
+                    .detection
+                    .search_identifiers
+                    .get(name)
+                    .map(|search| search.condition_groups())
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|group| {
+                        let filters = group
+                            .iter()
+                            .map(|condition| {
+                                self.operator_filter(&condition.field, &condition.operator, negated)
+                            })
+                            .collect();
+                        combine(filters, !negated)
+                    })
+                    .collect();
+                combine(groups, negated)
+            }
+            ConditionTree::And(nodes) | ConditionTree::Or(nodes) => {
+                let filters = nodes
+                    .iter()
+                    .map(|node| self.condition_filter(rule, node, negated))
+                    .collect();
+                // De Morgan: a negated AND is the OR of the negated nodes
+                combine(filters, matches!(tree, ConditionTree::And(_)) != negated)
+            }
+            ConditionTree::Not(node) => self.condition_filter(rule, node, !negated),
+            // expanded before walking the tree
+            ConditionTree::AnyOf(_) | ConditionTree::AllOf(_) => Filter::And(vec![]),
+        }
+    }
+
+    fn operator_filter(&self, field: &str, operator: &RuleOperator, negated: bool) -> Filter {
+        if field.is_empty() {
+            return line_filter(operator, negated);
+        }
+        let label = self.label(field);
+        let matcher = |regex: String| {
+            let operator = if negated { "!~" } else { "=~" };
+            Filter::Label(format!("{label}{operator}{}", logql_string(&regex)))
+        };
+        match operator {
+            RuleOperator::Equals(SiemField::Text(value)) => {
+                matcher(format!("(?i){}", regex::escape(value)))
+            }
+            RuleOperator::Equals(SiemField::Array(values)) => matcher(alternation(values)),
+            RuleOperator::Equals(value) => {
+                let operator = if negated { "!=" } else { "==" };
+                Filter::Label(format!("{label} {operator} {value}"))
+            }
+            RuleOperator::StartsWith(pattern) => {
+                matcher(format!("(?i){}.*", regex::escape(pattern)))
+            }
+            RuleOperator::EndsWith(pattern) => matcher(format!("(?i).*{}", regex::escape(pattern))),
+            RuleOperator::Contains(pattern) => {
+                matcher(format!("(?i).*{}.*", regex::escape(pattern)))
+            }
+            RuleOperator::Matches(regex) => matcher(unanchored(regex.as_str())),
+            RuleOperator::IsNull(_) => {
+                let operator = if negated { "!=" } else { "=" };
+                Filter::Label(format!("{label}{operator}\"\""))
+            }
+            RuleOperator::SameNet((ip, net)) => {
+                let cidr = match ip {
+                    SiemIp::V4(ip) => format!("{}/{net}", ipv4_to_str(*ip)),
+                    SiemIp::V6(ip) => format!("{}/{net}", ipv6_to_str(*ip)),
+                };
+                let operator = if negated { "!=" } else { "=" };
+                Filter::Label(format!("{label}{operator}ip(\"{cidr}\")"))
+            }
+            RuleOperator::GT(value)
+            | RuleOperator::GTE(value)
+            | RuleOperator::LT(value)
+            | RuleOperator::LTE(value) => {
+                let comparison = match (operator, negated) {
+                    (RuleOperator::GT(_), false) | (RuleOperator::LTE(_), true) => ">",
+                    (RuleOperator::GTE(_), false) | (RuleOperator::LT(_), true) => ">=",
+                    (RuleOperator::LT(_), false) | (RuleOperator::GTE(_), true) => "<",
+                    _ => "<=",
+                };
+                Filter::Label(format!("{label} {comparison} {value}"))
+            }
+            RuleOperator::Any(operators) => {
+                // Several text equalities of the same field are a single regex alternation
+                let values: Option<Vec<String>> = operators
+                    .iter()
+                    .map(|operator| match operator.as_ref() {
+                        RuleOperator::Equals(SiemField::Text(value)) => Some(value.to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                match values {
+                    Some(values) if values.len() > 1 => matcher(alternation(&values)),
+                    _ => combine(
+                        operators
+                            .iter()
+                            .map(|operator| self.operator_filter(field, operator, negated))
+                            .collect(),
+                        negated,
+                    ),
+                }
+            }
+            RuleOperator::All(operators) => {
+                let filters = operators
+                    .iter()
+                    .map(|operator| self.operator_filter(field, operator, negated))
+                    .collect();
+                combine(filters, !negated)
+            }
+            RuleOperator::Not(operator) => self.operator_filter(field, operator, !negated),
+            _ => Filter::And(vec![]),
+        }
+    }
+
+    /// The log query: stream selector, line filters, parser and label filter expression.
+    fn log_query(&self, rule: &SigmaRule) -> String {
+        let condition = match parse_condition_tree(&rule.detection.condition) {
+            Ok(condition) => condition,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let identifiers: Vec<LogString> =
+            rule.detection.search_identifiers.keys().cloned().collect();
+        let filter = self.condition_filter(rule, &condition.expand(&identifiers), false);
+        // Line filters can only be chained, the top level ones are applied before parsing
+        let conjuncts = match filter {
+            Filter::And(filters) => filters,
+            filter => vec![filter],
+        };
+        let mut lines = vec![];
+        let mut labels = vec![];
+        for conjunct in conjuncts {
+            match conjunct {
+                Filter::Line(line) => lines.push(line),
+                filter if has_line_filter(&filter) => eprintln!(
+                    "LogQL can't combine keywords with OR, they are dropped from '{}'",
+                    rule.title
+                ),
+                filter => labels.push(render_label_filter(&filter, true)),
+            }
+        }
+        let mut query = self.stream_selector(&rule.logsource);
+        for line in lines {
+            query.push_str(&format!(" {line}"));
+        }
+        let parser = backend_option(self.mappings.as_ref(), "parser").unwrap_or("json");
+        query.push_str(&format!(" | {parser}"));
+        let labels: Vec<String> = labels.into_iter().filter(|l| !l.is_empty()).collect();
+        if !labels.is_empty() {
+            query.push_str(&format!(" | {}", labels.join(" and ")));
+        }
+        query
+    }
+
+    /// The metric query of an aggregation, counting the log query over the timeframe.
+    fn metric_query(
+        &self,
+        rule: &SigmaRule,
+        aggregation: &ConditionAggregation,
+        log_query: &str,
+    ) -> String {
+        let range = rule.detection.timeframe.as_deref().unwrap_or("5m");
+        let group_by = aggregation
+            .group_by
+            .as_ref()
+            .map(|group_by| self.label(group_by));
+        let by = |labels: &[&str]| match labels.len() {
+            0 => String::new(),
+            _ => format!(" by ({}) ", labels.join(", ")),
+        };
+        let group: Vec<&str> = group_by.iter().map(String::as_str).collect();
+        let operator = match aggregation.operator.as_ref() {
+            "=" => "==",
+            operator => operator,
+        };
+        let function = aggregation.function.to_lowercase();
+        let metric = match (function.as_str(), &aggregation.field) {
+            ("count", None) => format!("sum{}(count_over_time({log_query} [{range}]))", by(&group)),
+            ("count", Some(field)) => {
+                // distinct values: one series per value, counted per group
+                let field = self.label(field);
+                let mut inner = group.clone();
+                inner.push(&field);
+                format!(
+                    "count{}(sum{}(count_over_time({log_query} [{range}])))",
+                    by(&group),
+                    by(&inner)
+                )
+            }
+            (function, Some(field)) => format!(
+                "{function}{}({function}_over_time({log_query} | unwrap {} [{range}]))",
+                by(&group),
+                self.label(field)
+            ),
+            (function, None) => format!(
+                "{function}{}(count_over_time({log_query} [{range}]))",
+                by(&group)
+            ),
+        };
+        format!("{metric} {operator} {}", aggregation.value)
+    }
+
+    fn build_logql(&self, rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let log_query = self.log_query(rule);
+        match aggregation {
+            Some(aggregation) => self.metric_query(rule, &aggregation, &log_query),
+            None => log_query,
+        }
+    }
+
+    /// The alerting rule of the ruler, whose expressions have to be metric queries: a plain
+    /// detection fires on any matching line within the timeframe.
+    fn alerting_rule(&self, sigma_rule: &SigmaRule) -> serde_json::Value {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let log_query = self.log_query(sigma_rule);
+        let expr = match aggregation {
+            Some(aggregation) => self.metric_query(sigma_rule, &aggregation, &log_query),
+            None => format!(
+                "sum(count_over_time({log_query} [{}])) > 0",
+                sigma_rule.detection.timeframe.as_deref().unwrap_or("5m")
+            ),
+        };
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let severity = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => "info",
+            AlertSeverity::LOW => "low",
+            AlertSeverity::MEDIUM => "medium",
+            AlertSeverity::HIGH => "high",
+            AlertSeverity::CRITICAL => "critical",
+        };
+        let mut annotations = json!({
+            "summary": sigma_rule.title,
+            "description": sigma_rule.description.as_deref().unwrap_or_default(),
+        });
+        if let Some(id) = &sigma_rule.id {
+            annotations["sigma_id"] = json!(id);
+        }
+        json!({
+            "alert": pascal_case(&sigma_rule.title),
+            "expr": expr,
+            "for": "0m",
+            "labels": { "severity": severity },
+            "annotations": annotations,
+        })
+    }
+
+    fn ruler_group(&self, rules: Vec<serde_json::Value>) -> String {
+        let name = backend_option(self.mappings.as_ref(), "ruler.group").unwrap_or("sigma");
+        let groups = json!({ "groups": [{ "name": name, "rules": rules }] });
+        serde_yaml::to_string(&groups).unwrap_or_default()
+    }
+}
+
+impl BackEnd for LokiBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        match self.output {
+            LokiOutput::Query => self.build_logql(&sigma_rule),
+            LokiOutput::Ruler => self.ruler_group(vec![self.alerting_rule(&sigma_rule)]),
+        }
+    }
+
+    /// The alerting rules are gathered in a single rule group.
+    fn convert_rules(&self, sigma_rules: Vec<SigmaRule>) -> String {
+        match self.output {
+            LokiOutput::Query => sigma_rules
+                .into_iter()
+                .map(|sigma_rule| self.convert_rule(sigma_rule))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            LokiOutput::Ruler => self.ruler_group(
+                sigma_rules
+                    .iter()
+                    .map(|sigma_rule| self.alerting_rule(sigma_rule))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl RequiresMappings for LokiBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        args.unwrap_or_default()
+    }
+}
+
+impl QueryBuilder for LokiBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        self.log_query(rule)
+    }
+}
+
+/// Conditions without a field search the log line.
+fn line_filter(operator: &RuleOperator, negated: bool) -> Filter {
+    let (contains, matches) = if negated { ("!=", "!~") } else { ("|=", "|~") };
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => {
+            Filter::Line(format!("{matches} {}", logql_string(&alternation(values))))
+        }
+        RuleOperator::Equals(value) => {
+            Filter::Line(format!("{contains} {}", logql_string(&value.to_string())))
+        }
+        RuleOperator::StartsWith(pattern)
+        | RuleOperator::EndsWith(pattern)
+        | RuleOperator::Contains(pattern) => {
+            Filter::Line(format!("{contains} {}", logql_string(pattern)))
+        }
+        RuleOperator::Matches(regex) => {
+            Filter::Line(format!("{matches} {}", logql_string(regex.as_str())))
+        }
+        RuleOperator::Any(operators) => {
+            // Line filters can't be OR'ed, the searched texts are a single regex alternation
+            let values: Option<Vec<String>> = operators
+                .iter()
+                .map(|operator| match operator.as_ref() {
+                    RuleOperator::Equals(SiemField::Text(value)) => Some(value.to_string()),
+                    RuleOperator::StartsWith(value)
+                    | RuleOperator::EndsWith(value)
+                    | RuleOperator::Contains(value) => Some(value.to_string()),
+                    _ => None,
+                })
+                .collect();
+            match values {
+                Some(values) if values.len() > 1 => {
+                    Filter::Line(format!("{matches} {}", logql_string(&alternation(&values))))
+                }
+                _ => combine(
+                    operators
+                        .iter()
+                        .map(|operator| line_filter(operator, negated))
+                        .collect(),
+                    negated,
+                ),
+            }
+        }
+        RuleOperator::All(operators) => combine(
+            operators
+                .iter()
+                .map(|operator| line_filter(operator, negated))
+                .collect(),
+            !negated,
+        ),
+        RuleOperator::Not(operator) => line_filter(operator, !negated),
+        _ => Filter::And(vec![]),
+    }
+}
+
+/// Combines the filters with `and` (or `or`), flattening the nested ones of the same kind
+/// and dropping the empty ones.
+fn combine(filters: Vec<Filter>, and: bool) -> Filter {
+    let mut combined = vec![];
+    for filter in filters {
+        match filter {
+            Filter::And(nested) if and => combined.extend(nested),
+            Filter::Or(nested) if !and => combined.extend(nested),
+            Filter::And(nested) | Filter::Or(nested) if nested.is_empty() => {}
+            filter => combined.push(filter),
+        }
+    }
+    match (combined.len(), and) {
+        (1, _) => combined.remove(0),
+        (_, true) => Filter::And(combined),
+        (_, false) => Filter::Or(combined),
+    }
+}
+
+fn has_line_filter(filter: &Filter) -> bool {
+    match filter {
+        Filter::Line(_) => true,
+        Filter::Label(_) => false,
+        Filter::And(filters) | Filter::Or(filters) => filters.iter().any(has_line_filter),
+    }
+}
+
+fn render_label_filter(filter: &Filter, top: bool) -> String {
+    let join = |filters: &[Filter], operator: &str| {
+        let items: Vec<String> = filters
+            .iter()
+            .map(|filter| render_label_filter(filter, false))
+            .filter(|item| !item.is_empty())
+            .collect();
+        match items.len() {
+            0 => String::new(),
+            1 => items[0].clone(),
+            _ if top && operator == "and" => items.join(" and "),
+            _ => format!("({})", items.join(&format!(" {operator} "))),
+        }
+    };
+    match filter {
+        Filter::Label(label) => label.clone(),
+        Filter::Line(_) => String::new(),
+        Filter::And(filters) => join(filters, "and"),
+        Filter::Or(filters) => join(filters, "or"),
+    }
+}
+
+/// Case insensitive regex matching any of the values.
+fn alternation<T: AsRef<str>>(values: &[T]) -> String {
+    format!(
+        "(?i)({})",
+        values
+            .iter()
+            .map(|value| regex::escape(value.as_ref()))
+            .collect::<Vec<String>>()
+            .join("|")
+    )
+}
+
+/// Label regexes match the whole value, unlike the sigma ones.
+fn unanchored(regex: &str) -> String {
+    let start = regex.strip_prefix('^');
+    let regex = start.unwrap_or(regex);
+    let end = regex.strip_suffix('$');
+    let regex = end.unwrap_or(regex);
+    format!(
+        "{}({regex}){}",
+        if start.is_some() { "" } else { ".*" },
+        if end.is_some() { "" } else { ".*" }
+    )
+}
+
+/// A LogQL string, raw between backticks unless it holds one.
+fn logql_string(value: &str) -> String {
+    if value.contains('`') {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        format!("`{value}`")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::loki::{LokiBackend, LokiOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_query() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        assert_eq!(
+            LokiBackend::default().convert_rule(rule),
+            r#"{job=`windows`} | json | (Image=~`(?i).*\\cmd\.exe` or OriginalFileName=~`(?i)Cmd\.Exe`) and ParentImage=~`(?i).*\\7zFM\.exe` and CommandLine!~`(?i).*/c.*` and CommandLine!~`(?i).*/k.*` and CommandLine!~`(?i).*/r.*` and CommandLine!="""#
+        );
+    }
+
+    #[test]
+    pub fn test_ruler() {
+        let rule = parse_sigma_rule(
+            r#"
+title: ssh brute force
+id: 5a1b2c3d-0000-4000-8000-000000000001
+logsource:
+    product: linux
+    service: sshd
+detection:
+    selection:
+        message|contains: Failed password
+        user.name:
+            - root
+            - admin
+    timeframe: 10m
+    condition: selection | count() by source.ip > 10
+level: high
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([("logsource.linux".to_string(), "syslog".to_string())]);
+        let backend = LokiBackend::new(Some(mappings), LokiOutput::Ruler);
+        let ruler: serde_yaml::Value =
+            serde_yaml::from_str(&backend.convert_rules(vec![rule])).unwrap();
+        let group = &ruler["groups"][0];
+        assert_eq!(group["name"], "sigma");
+        let alert = &group["rules"][0];
+        assert_eq!(alert["alert"], "SshBruteForce");
+        assert_eq!(alert["labels"]["severity"], "high");
+        assert_eq!(
+            alert["expr"],
+            "sum by (source_ip) (count_over_time({job=`syslog`} | json | message=~`(?i).*Failed password.*` and user_name=~`(?i)(root|admin)` [10m])) > 10"
+        );
+    }
+}
@@ -1,22 +1,32 @@
 pub mod arcsight;
 pub mod aws_opensearch;
+pub mod carbon_black;
 pub mod chronicle;
+pub mod cortex_xdr;
+pub mod data_lake;
+pub mod datadog;
 pub mod devo;
 pub mod dnif;
 pub mod elastalert;
+pub mod esql;
 pub mod graylog;
 pub mod humio_alert;
 pub mod kafka_sql;
 pub mod kibana;
 pub mod logrhythm;
+pub mod loki;
+pub mod osquery;
 pub mod qradar;
 pub mod securonix;
 pub mod sentinel;
+pub mod sentinel_one;
 pub mod snowflake;
 pub mod splunk;
 pub mod sql;
 pub mod sqlite;
 pub mod sumologic;
+pub mod velociraptor;
+pub mod wazuh;
 
 use crate::error::Error::InvalidDestination;
 use crate::sigma::components::rule::sigma::SigmaRule;
@@ -28,23 +38,70 @@ use std::fmt::{Display, Formatter};
 pub enum Backends {
     ElastAlert,
     ArcSight,
+    ArcSightEsmRule,
     Splunk,
+    SplunkSavedSearches,
+    SplunkCorrelationSearch,
+    SplunkDataModel,
     Qradar,
+    QradarRuleXml,
+    QradarBuildingBlock,
     HumioAlert,
     Kibana,
     Chronicle,
     Devo,
+    DevoAlertDefinition,
     LogRhythm,
+    LogRhythmWebConsoleSearch,
+    LogRhythmAiEngine,
     KafkaSQL,
+    KafkaSQLStream,
     AwsOpenSearch,
+    AwsOpenSearchSecurityAnalytics,
+    AwsOpenSearchMonitor,
     Dnif,
+    DnifWorkbook,
     GrayLog,
+    GrayLogEventDefinition,
+    Loki,
+    LokiRuler,
     SQL,
     SQLite,
+    PostgreSQL,
+    MySQL,
+    ClickHouse,
+    DuckDB,
     Securonix,
+    SecuronixPolicy,
     Sentinel,
+    SentinelAnalyticsRule,
+    SentinelAnalyticsRuleYaml,
+    DefenderXdr,
     Snowflake,
+    SnowflakeView,
+    SnowflakeTask,
     SumoLogic,
+    SumoLogicScheduledSearch,
+    SumoLogicCloudSiem,
+    Datadog,
+    DatadogDetectionRule,
+    CortexXdr,
+    CortexXdrBioc,
+    SentinelOne,
+    SentinelOnePowerQuery,
+    SentinelOneStarRule,
+    CarbonBlack,
+    CarbonBlackWatchlist,
+    Osquery,
+    OsqueryPack,
+    Velociraptor,
+    VelociraptorArtifact,
+    VelociraptorSigmaMapping,
+    Wazuh,
+    WazuhLists,
+    Esql,
+    BigQuery,
+    SparkSql,
 }
 
 impl Backends {
@@ -54,21 +111,70 @@ impl Backends {
             "kibana" => Ok(Backends::Kibana),
             "humio" | "humioalert" => Ok(Backends::HumioAlert),
             "arcsight" => Ok(Backends::ArcSight),
+            "arcsightesmrule" | "arcsightesm" => Ok(Backends::ArcSightEsmRule),
             "qradar" => Ok(Backends::Qradar),
+            "qradarrulexml" => Ok(Backends::QradarRuleXml),
+            "qradarbuildingblock" => Ok(Backends::QradarBuildingBlock),
             "splunk" => Ok(Backends::Splunk),
+            "splunksavedsearches" => Ok(Backends::SplunkSavedSearches),
+            "splunkcorrelationsearch" | "splunkes" => Ok(Backends::SplunkCorrelationSearch),
+            "splunkdatamodel" | "splunktstats" => Ok(Backends::SplunkDataModel),
             "chronicle" => Ok(Backends::Chronicle),
             "devo" => Ok(Backends::Devo),
+            "devoalertdefinition" => Ok(Backends::DevoAlertDefinition),
             "logrhythm" => Ok(Backends::LogRhythm),
-            "kafkasql" => Ok(Backends::KafkaSQL),
+            "logrhythmwebconsolesearch" => Ok(Backends::LogRhythmWebConsoleSearch),
+            "logrhythmaiengine" | "logrhythmaie" => Ok(Backends::LogRhythmAiEngine),
+            "kafkasql" | "ksqldb" => Ok(Backends::KafkaSQL),
+            "kafkasqlstream" => Ok(Backends::KafkaSQLStream),
             "awsopensearch" => Ok(Backends::AwsOpenSearch),
+            "awsopensearchsecurityanalytics" | "opensearchsecurityanalytics" => {
+                Ok(Backends::AwsOpenSearchSecurityAnalytics)
+            }
+            "awsopensearchmonitor" | "opensearchmonitor" => Ok(Backends::AwsOpenSearchMonitor),
             "dnif" => Ok(Backends::Dnif),
+            "dnifworkbook" => Ok(Backends::DnifWorkbook),
             "graylog" => Ok(Backends::GrayLog),
+            "graylogeventdefinition" => Ok(Backends::GrayLogEventDefinition),
+            "loki" | "logql" => Ok(Backends::Loki),
+            "lokiruler" => Ok(Backends::LokiRuler),
             "sql" => Ok(Backends::SQL),
             "sqlite" => Ok(Backends::SQLite),
+            "postgresql" | "postgres" => Ok(Backends::PostgreSQL),
+            "mysql" => Ok(Backends::MySQL),
+            "clickhouse" => Ok(Backends::ClickHouse),
+            "duckdb" => Ok(Backends::DuckDB),
             "securonix" => Ok(Backends::Securonix),
+            "securonixpolicy" => Ok(Backends::SecuronixPolicy),
             "sentinel" => Ok(Backends::Sentinel),
+            "sentinelanalyticsrule" => Ok(Backends::SentinelAnalyticsRule),
+            "sentinelanalyticsruleyaml" => Ok(Backends::SentinelAnalyticsRuleYaml),
+            "defenderxdr" | "mde" | "microsoft365defender" => Ok(Backends::DefenderXdr),
             "snowflake" => Ok(Backends::Snowflake),
+            "snowflakeview" => Ok(Backends::SnowflakeView),
+            "snowflaketask" => Ok(Backends::SnowflakeTask),
             "sumologic" => Ok(Backends::SumoLogic),
+            "sumologicscheduledsearch" => Ok(Backends::SumoLogicScheduledSearch),
+            "sumologiccloudsiem" | "sumologiccse" => Ok(Backends::SumoLogicCloudSiem),
+            "datadog" => Ok(Backends::Datadog),
+            "datadogdetectionrule" | "datadogcloudsiem" => Ok(Backends::DatadogDetectionRule),
+            "cortexxdr" | "xql" => Ok(Backends::CortexXdr),
+            "cortexxdrbioc" => Ok(Backends::CortexXdrBioc),
+            "sentinelone" | "s1ql" => Ok(Backends::SentinelOne),
+            "sentinelonepowerquery" => Ok(Backends::SentinelOnePowerQuery),
+            "sentinelonestarrule" | "sentinelonestar" => Ok(Backends::SentinelOneStarRule),
+            "carbonblack" | "cbc" => Ok(Backends::CarbonBlack),
+            "carbonblackwatchlist" => Ok(Backends::CarbonBlackWatchlist),
+            "osquery" => Ok(Backends::Osquery),
+            "osquerypack" => Ok(Backends::OsqueryPack),
+            "velociraptor" | "vql" => Ok(Backends::Velociraptor),
+            "velociraptorartifact" => Ok(Backends::VelociraptorArtifact),
+            "velociraptorsigmamapping" => Ok(Backends::VelociraptorSigmaMapping),
+            "wazuh" | "ossec" => Ok(Backends::Wazuh),
+            "wazuhlists" => Ok(Backends::WazuhLists),
+            "esql" | "elasticesql" => Ok(Backends::Esql),
+            "bigquery" => Ok(Backends::BigQuery),
+            "sparksql" | "spark" | "databricks" => Ok(Backends::SparkSql),
             _ => Err(InvalidDestination(backend.to_string())),
         };
     }
@@ -79,23 +185,70 @@ impl Backends {
         vec![
             Backends::ElastAlert.to_string(),
             Backends::ArcSight.to_string(),
+            Backends::ArcSightEsmRule.to_string(),
             Backends::Splunk.to_string(),
+            Backends::SplunkSavedSearches.to_string(),
+            Backends::SplunkCorrelationSearch.to_string(),
+            Backends::SplunkDataModel.to_string(),
             Backends::Qradar.to_string(),
+            Backends::QradarRuleXml.to_string(),
+            Backends::QradarBuildingBlock.to_string(),
             Backends::HumioAlert.to_string(),
             Backends::Kibana.to_string(),
             Backends::Chronicle.to_string(),
             Backends::Devo.to_string(),
+            Backends::DevoAlertDefinition.to_string(),
             Backends::LogRhythm.to_string(),
+            Backends::LogRhythmWebConsoleSearch.to_string(),
+            Backends::LogRhythmAiEngine.to_string(),
             Backends::KafkaSQL.to_string(),
+            Backends::KafkaSQLStream.to_string(),
             Backends::AwsOpenSearch.to_string(),
+            Backends::AwsOpenSearchSecurityAnalytics.to_string(),
+            Backends::AwsOpenSearchMonitor.to_string(),
             Backends::Dnif.to_string(),
+            Backends::DnifWorkbook.to_string(),
             Backends::GrayLog.to_string(),
+            Backends::GrayLogEventDefinition.to_string(),
+            Backends::Loki.to_string(),
+            Backends::LokiRuler.to_string(),
             Backends::SQL.to_string(),
             Backends::SQLite.to_string(),
+            Backends::PostgreSQL.to_string(),
+            Backends::MySQL.to_string(),
+            Backends::ClickHouse.to_string(),
+            Backends::DuckDB.to_string(),
             Backends::Securonix.to_string(),
+            Backends::SecuronixPolicy.to_string(),
             Backends::Sentinel.to_string(),
+            Backends::SentinelAnalyticsRule.to_string(),
+            Backends::SentinelAnalyticsRuleYaml.to_string(),
+            Backends::DefenderXdr.to_string(),
             Backends::Snowflake.to_string(),
+            Backends::SnowflakeView.to_string(),
+            Backends::SnowflakeTask.to_string(),
             Backends::SumoLogic.to_string(),
+            Backends::SumoLogicScheduledSearch.to_string(),
+            Backends::SumoLogicCloudSiem.to_string(),
+            Backends::Datadog.to_string(),
+            Backends::DatadogDetectionRule.to_string(),
+            Backends::CortexXdr.to_string(),
+            Backends::CortexXdrBioc.to_string(),
+            Backends::SentinelOne.to_string(),
+            Backends::SentinelOnePowerQuery.to_string(),
+            Backends::SentinelOneStarRule.to_string(),
+            Backends::CarbonBlack.to_string(),
+            Backends::CarbonBlackWatchlist.to_string(),
+            Backends::Osquery.to_string(),
+            Backends::OsqueryPack.to_string(),
+            Backends::Velociraptor.to_string(),
+            Backends::VelociraptorArtifact.to_string(),
+            Backends::VelociraptorSigmaMapping.to_string(),
+            Backends::Wazuh.to_string(),
+            Backends::WazuhLists.to_string(),
+            Backends::Esql.to_string(),
+            Backends::BigQuery.to_string(),
+            Backends::SparkSql.to_string(),
         ]
     }
 }
@@ -105,23 +258,72 @@ impl Display for Backends {
         match self {
             Backends::ElastAlert => write!(f, "ElastAlert"),
             Backends::ArcSight => write!(f, "ArcSight"),
+            Backends::ArcSightEsmRule => write!(f, "ArcSightEsmRule"),
             Backends::Splunk => write!(f, "Splunk"),
+            Backends::SplunkSavedSearches => write!(f, "SplunkSavedSearches"),
+            Backends::SplunkCorrelationSearch => write!(f, "SplunkCorrelationSearch"),
+            Backends::SplunkDataModel => write!(f, "SplunkDataModel"),
             Backends::Qradar => write!(f, "Qradar"),
+            Backends::QradarRuleXml => write!(f, "QradarRuleXml"),
+            Backends::QradarBuildingBlock => write!(f, "QradarBuildingBlock"),
             Backends::HumioAlert => write!(f, "HumioAlert"),
             Backends::Kibana => write!(f, "Kibana"),
             Backends::Chronicle => write!(f, "Chronicle"),
             Backends::Devo => write!(f, "Devo"),
+            Backends::DevoAlertDefinition => write!(f, "DevoAlertDefinition"),
             Backends::LogRhythm => write!(f, "LogRhythm"),
+            Backends::LogRhythmWebConsoleSearch => write!(f, "LogRhythmWebConsoleSearch"),
+            Backends::LogRhythmAiEngine => write!(f, "LogRhythmAiEngine"),
             Backends::KafkaSQL => write!(f, "KafkaSQL"),
+            Backends::KafkaSQLStream => write!(f, "KafkaSQLStream"),
             Backends::AwsOpenSearch => write!(f, "AwsOpenSearch"),
+            Backends::AwsOpenSearchSecurityAnalytics => {
+                write!(f, "AwsOpenSearchSecurityAnalytics")
+            }
+            Backends::AwsOpenSearchMonitor => write!(f, "AwsOpenSearchMonitor"),
             Backends::Dnif => write!(f, "DNIF"),
+            Backends::DnifWorkbook => write!(f, "DNIFWorkbook"),
             Backends::GrayLog => write!(f, "GrayLog"),
+            Backends::GrayLogEventDefinition => write!(f, "GrayLogEventDefinition"),
+            Backends::Loki => write!(f, "Loki"),
+            Backends::LokiRuler => write!(f, "LokiRuler"),
             Backends::SQL => write!(f, "SQL"),
             Backends::SQLite => write!(f, "SQLite"),
+            Backends::PostgreSQL => write!(f, "PostgreSQL"),
+            Backends::MySQL => write!(f, "MySQL"),
+            Backends::ClickHouse => write!(f, "ClickHouse"),
+            Backends::DuckDB => write!(f, "DuckDB"),
             Backends::Securonix => write!(f, "Securonix"),
+            Backends::SecuronixPolicy => write!(f, "SecuronixPolicy"),
             Backends::Sentinel => write!(f, "Sentinel"),
+            Backends::SentinelAnalyticsRule => write!(f, "SentinelAnalyticsRule"),
+            Backends::SentinelAnalyticsRuleYaml => write!(f, "SentinelAnalyticsRuleYaml"),
+            Backends::DefenderXdr => write!(f, "DefenderXdr"),
             Backends::Snowflake => write!(f, "Snowflake"),
+            Backends::SnowflakeView => write!(f, "SnowflakeView"),
+            Backends::SnowflakeTask => write!(f, "SnowflakeTask"),
             Backends::SumoLogic => write!(f, "SumoLogic"),
+            Backends::SumoLogicScheduledSearch => write!(f, "SumoLogicScheduledSearch"),
+            Backends::SumoLogicCloudSiem => write!(f, "SumoLogicCloudSiem"),
+            Backends::Datadog => write!(f, "Datadog"),
+            Backends::DatadogDetectionRule => write!(f, "DatadogDetectionRule"),
+            Backends::CortexXdr => write!(f, "CortexXDR"),
+            Backends::CortexXdrBioc => write!(f, "CortexXDRBioc"),
+            Backends::SentinelOne => write!(f, "SentinelOne"),
+            Backends::SentinelOnePowerQuery => write!(f, "SentinelOnePowerQuery"),
+            Backends::SentinelOneStarRule => write!(f, "SentinelOneStarRule"),
+            Backends::CarbonBlack => write!(f, "CarbonBlack"),
+            Backends::CarbonBlackWatchlist => write!(f, "CarbonBlackWatchlist"),
+            Backends::Osquery => write!(f, "Osquery"),
+            Backends::OsqueryPack => write!(f, "OsqueryPack"),
+            Backends::Velociraptor => write!(f, "Velociraptor"),
+            Backends::VelociraptorArtifact => write!(f, "VelociraptorArtifact"),
+            Backends::VelociraptorSigmaMapping => write!(f, "VelociraptorSigmaMapping"),
+            Backends::Wazuh => write!(f, "Wazuh"),
+            Backends::WazuhLists => write!(f, "WazuhLists"),
+            Backends::Esql => write!(f, "ESQL"),
+            Backends::BigQuery => write!(f, "BigQuery"),
+            Backends::SparkSql => write!(f, "SparkSQL"),
         }
     }
 }
@@ -173,6 +375,16 @@ impl Display for Backends {
 ///
 pub trait BackEnd: QueryBuilder {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String;
+
+    /// Converts a set of rules into a single output. By default the converted rules are separated
+    /// by an empty line, formats holding the rules in a single document must override it.
+    fn convert_rules(&self, sigma_rules: Vec<SigmaRule>) -> String {
+        sigma_rules
+            .into_iter()
+            .map(|sigma_rule| self.convert_rule(sigma_rule))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
 }
 
 pub trait QueryBuilder {
@@ -0,0 +1,353 @@
+use crate::backend::sql::SqlDialect;
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::RuleOperator;
+use crate::sigma::components::rule::sigma::{SigmaRule, SigmaRuleLogSource};
+use crate::utils::{backend_option, logsource_mapping, parse_aggregation, snake_case};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::process::exit;
+
+/// What the osquery backend emits for a rule.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum OsqueryOutput {
+    /// The hunting query.
+    #[default]
+    Query,
+    /// A query pack JSON, holding the queries of all the converted rules.
+    Pack,
+}
+
+/// Where a sigma field is found in an osquery table.
+#[derive(Clone, Copy, Debug)]
+enum Column<'a> {
+    /// A column of the table.
+    Direct(&'a str),
+    /// A column of the `processes` table, reached from the pid held by a column of the table.
+    Process(&'a str, &'a str),
+}
+
+/// The columns of the osquery tables the logsource categories are mapped to.
+fn table_columns(table: &str) -> &'static [(&'static str, Column<'static>)] {
+    match table {
+        "processes" | "process_events" => &[
+            ("Image", Column::Direct("path")),
+            ("CommandLine", Column::Direct("cmdline")),
+            ("CurrentDirectory", Column::Direct("cwd")),
+            ("ProcessId", Column::Direct("pid")),
+            ("ParentProcessId", Column::Direct("parent")),
+            ("ParentImage", Column::Process("parent", "path")),
+            ("ParentCommandLine", Column::Process("parent", "cmdline")),
+        ],
+        "file_events" => &[
+            ("TargetFilename", Column::Direct("target_path")),
+            ("md5", Column::Direct("md5")),
+            ("sha1", Column::Direct("sha1")),
+            ("sha256", Column::Direct("sha256")),
+        ],
+        "process_open_sockets" => &[
+            ("DestinationIp", Column::Direct("remote_address")),
+            ("DestinationPort", Column::Direct("remote_port")),
+            ("SourceIp", Column::Direct("local_address")),
+            ("SourcePort", Column::Direct("local_port")),
+            ("ProcessId", Column::Direct("pid")),
+            ("Image", Column::Process("pid", "path")),
+            ("CommandLine", Column::Process("pid", "cmdline")),
+        ],
+        "registry" => &[
+            ("TargetObject", Column::Direct("path")),
+            ("Details", Column::Direct("data")),
+        ],
+        _ => &[],
+    }
+}
+
+const TABLES: [&str; 5] = [
+    "processes",
+    "process_events",
+    "file_events",
+    "process_open_sockets",
+    "registry",
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct OsqueryBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: OsqueryOutput,
+}
+
+impl OsqueryBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: OsqueryOutput) -> Self {
+        OsqueryBackend { mappings, output }
+    }
+
+    /// The table of the logsource, which the `logsource.*` mappings can override. Process
+    /// creations are searched in the running `processes`, or in the audited `process_events`
+    /// with an `osquery.evented=true` option.
+    fn table(&self, logsource: &SigmaRuleLogSource) -> String {
+        let mapped = logsource_mapping(self.mappings.as_ref(), logsource);
+        let category = logsource.category.as_deref().unwrap_or_default();
+        if let Some(table) = mapped {
+            return table;
+        }
+        let evented = backend_option(self.mappings.as_ref(), "osquery.evented")
+            .is_some_and(|evented| evented == "true");
+        match category {
+            "process_creation" if evented => "process_events",
+            "file_event" | "file_change" | "file_delete" | "file_rename" => "file_events",
+            "network_connection" => "process_open_sockets",
+            "registry_event" | "registry_add" | "registry_set" | "registry_delete" => "registry",
+            _ => SqlDialect::OSQUERY.table,
+        }
+        .to_string()
+    }
+
+    /// Where a sigma field is found in the table, the field mappings taking precedence
+    /// over the known columns.
+    fn column(&self, table: &str, field: &str) -> Option<Column<'_>> {
+        if let Some(mapping) = self
+            .mappings
+            .as_ref()
+            .and_then(|mappings| mappings.get(field))
+        {
+            return Some(Column::Direct(mapping));
+        }
+        table_columns(table)
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, column)| *column)
+    }
+
+    /// Warns about the fields of the rule which the table doesn't have, the query failing
+    /// on their columns.
+    fn check_fields(&self, rule: &SigmaRule, table: &str) {
+        let fields: BTreeSet<String> = rule
+            .detection
+            .search_identifiers
+            .values()
+            .flat_map(|search| search.condition_groups())
+            .flatten()
+            .map(|condition| condition.field.to_string())
+            .collect();
+        for field in fields {
+            if field.is_empty() {
+                eprintln!(
+                    "osquery has no full text search, the keywords of '{}' are dropped",
+                    rule.title
+                );
+            } else if self.column(table, &field).is_some() {
+                continue;
+            } else if TABLES
+                .iter()
+                .any(|other| self.column(other, &field).is_some())
+            {
+                eprintln!(
+                    "'{}': the field '{field}' isn't a column of the osquery {table} table",
+                    rule.title
+                );
+            } else {
+                eprintln!(
+                    "'{}': the field '{field}' doesn't exist in any osquery table",
+                    rule.title
+                );
+            }
+        }
+    }
+
+    fn build_predicate(&self, table: &str, field: &str, operator: &RuleOperator) -> String {
+        let dialect = SqlDialect::OSQUERY;
+        if field.is_empty() {
+            return String::new();
+        }
+        let predicate = |column: &str| match operator {
+            // osquery reports the missing values as empty strings
+            RuleOperator::IsNull(_) => format!("({column} IS NULL OR {column} = '')"),
+            _ => dialect.build_predicate(&dialect.quote_identifier(column), operator),
+        };
+        match self.column(table, field) {
+            Some(Column::Direct(column)) => predicate(column),
+            Some(Column::Process(key, column)) => {
+                format!(
+                    "{key} IN (SELECT pid FROM processes WHERE {})",
+                    predicate(column)
+                )
+            }
+            None => predicate(field),
+        }
+    }
+
+    fn build_select(&self, rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let table = self.table(&rule.logsource);
+        let filter = self.build_query(rule);
+        match aggregation {
+            Some(aggregation) => {
+                SqlDialect::OSQUERY.build_aggregation(&aggregation, &table, &filter, &|field| {
+                    match self.column(&table, field) {
+                        Some(Column::Direct(column)) | Some(Column::Process(column, _)) => {
+                            SqlDialect::OSQUERY.quote_identifier(column)
+                        }
+                        None => SqlDialect::OSQUERY.quote_identifier(field),
+                    }
+                })
+            }
+            None => format!("SELECT * FROM {table} WHERE {filter}"),
+        }
+    }
+
+    /// The pack entry of the rule, named after its title.
+    fn build_pack_query(&self, rule: &SigmaRule) -> Value {
+        let interval = backend_option(self.mappings.as_ref(), "pack.interval")
+            .and_then(|interval| interval.parse::<u64>().ok())
+            .unwrap_or(3600);
+        let mut query = json!({
+            "query": format!("{};", self.build_select(rule)),
+            "interval": interval,
+            "description": rule.description.as_deref().unwrap_or(&rule.title),
+        });
+        let platform = match rule.logsource.product.as_deref() {
+            Some("windows") => Some("windows"),
+            Some("linux") => Some("linux"),
+            Some("macos") => Some("darwin"),
+            _ => None,
+        };
+        if let Some(platform) = platform {
+            query["platform"] = json!(platform);
+        }
+        query
+    }
+}
+
+impl BackEnd for OsqueryBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        match self.output {
+            OsqueryOutput::Query => self.build_select(&sigma_rule),
+            OsqueryOutput::Pack => self.convert_rules(vec![sigma_rule]),
+        }
+    }
+
+    /// The pack holds the queries of all the rules, as scheduled by the osquery daemon.
+    fn convert_rules(&self, sigma_rules: Vec<SigmaRule>) -> String {
+        match self.output {
+            OsqueryOutput::Query => sigma_rules
+                .into_iter()
+                .map(|sigma_rule| self.convert_rule(sigma_rule))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            OsqueryOutput::Pack => {
+                let mut queries = Map::new();
+                for rule in &sigma_rules {
+                    let name = format!("sigma_{}", snake_case(&rule.title));
+                    // rules sharing a title get numbered
+                    let name = (1..)
+                        .map(|n| match n {
+                            1 => name.clone(),
+                            n => format!("{name}_{n}"),
+                        })
+                        .find(|name| !queries.contains_key(name))
+                        .unwrap_or(name);
+                    queries.insert(name, self.build_pack_query(rule));
+                }
+                serde_json::to_string_pretty(&json!({ "queries": queries })).unwrap_or_default()
+            }
+        }
+    }
+}
+
+impl RequiresMappings for OsqueryBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        args.unwrap_or_default()
+    }
+}
+
+impl QueryBuilder for OsqueryBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        let table = self.table(&rule.logsource);
+        self.check_fields(rule, &table);
+        SqlDialect::OSQUERY.render_where(rule, &|field, operator| {
+            self.build_predicate(&table, field, operator)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::osquery::{OsqueryBackend, OsqueryOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_query() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let mappings = HashMap::from([(
+            "OriginalFileName".to_string(),
+            "original_filename".to_string(),
+        )]);
+        assert_eq!(
+            OsqueryBackend::new(Some(mappings), OsqueryOutput::Query).convert_rule(rule),
+            r#"SELECT * FROM processes WHERE (((path LIKE '%\\cmd.exe' ESCAPE '\' OR original_filename LIKE 'Cmd.Exe' ESCAPE '\') AND parent IN (SELECT pid FROM processes WHERE path LIKE '%\\7zFM.exe' ESCAPE '\')) AND NOT ((cmdline LIKE '%/c%' ESCAPE '\' OR cmdline LIKE '%/k%' ESCAPE '\' OR cmdline LIKE '%/r%' ESCAPE '\') OR (cmdline IS NULL OR cmdline = '')))"#
+        );
+    }
+
+    #[test]
+    pub fn test_pack() {
+        let rules = [
+            r#"
+title: Shell Connecting Out
+description: A shell with a network connection
+logsource:
+    product: linux
+    category: network_connection
+detection:
+    selection:
+        Image|endswith: /bash
+        DestinationIp|cidr: 10.0.0.0/8
+    condition: selection
+"#,
+            r#"
+title: Shell Connecting Out
+logsource:
+    product: macos
+    category: file_event
+detection:
+    selection:
+        TargetFilename|startswith: /tmp/
+    condition: selection
+"#,
+        ]
+        .iter()
+        .map(|rule| parse_sigma_rule(rule).unwrap())
+        .collect();
+        let pack: serde_json::Value = serde_json::from_str(
+            &OsqueryBackend::new(None, OsqueryOutput::Pack).convert_rules(rules),
+        )
+        .unwrap();
+        assert_eq!(
+            pack["queries"]["sigma_shell_connecting_out"]["query"],
+            r#"SELECT * FROM process_open_sockets WHERE (remote_address LIKE '10.%' AND pid IN (SELECT pid FROM processes WHERE path LIKE '%/bash' ESCAPE '\'));"#
+        );
+        assert_eq!(
+            pack["queries"]["sigma_shell_connecting_out"]["platform"],
+            "linux"
+        );
+        assert_eq!(
+            pack["queries"]["sigma_shell_connecting_out_2"]["query"],
+            r#"SELECT * FROM file_events WHERE target_path LIKE '/tmp/%' ESCAPE '\';"#
+        );
+        assert_eq!(
+            pack["queries"]["sigma_shell_connecting_out_2"]["platform"],
+            "darwin"
+        );
+    }
+}
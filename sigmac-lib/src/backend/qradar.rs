@@ -1,27 +1,184 @@
 use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
-use crate::prelude::SiemField;
-use crate::sigma::components::rule::sigma::SigmaRule;
+use crate::prelude::rule::sigma::{SigmaRule, SigmaRuleLogSource};
+use crate::prelude::{AlertSeverity, SiemField, SiemIp};
 use crate::sigma::components::rule::RuleOperator;
 use crate::sigma::components::rule::SiemRule;
-use crate::utils::parse_condition;
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{
+    join_predicates, logsource_mapping, parse_aggregation, render_condition, stable_uuid,
+    timeframe_seconds,
+};
+use serde_json::json;
 use std::collections::HashMap;
 use std::process::exit;
 
+/// Field every unmapped sigma field gets searched in.
+const PAYLOAD: &str = "UTF8(payload)";
+
+/// The artifacts the QRadar backend is able to produce.
+#[derive(Clone, Debug, Default)]
+pub enum QradarOutput {
+    /// The bare AQL search.
+    #[default]
+    Query,
+    /// A custom rule XML testing the AQL filter of the rule, to be imported with the
+    /// content management tool.
+    RuleXml,
+    /// A building block JSON holding the AQL filter of the rule, for the rules API.
+    BuildingBlock,
+}
+
+/// Converts Sigma rules into QRadar AQL. The log source type is looked up in the field mappings
+/// under the `logsource.<product>.<service>`, `logsource.<product>` and `logsource.<category>`
+/// keys before falling back to the default QRadar DSM names.
 #[derive(Clone, Debug)]
 pub struct QradarBackend {
     mappings: Option<HashMap<String, String>>,
+    output: QradarOutput,
 }
 
 impl QradarBackend {
-    pub fn new(mappings: Option<HashMap<String, String>>) -> Self {
-        QradarBackend { mappings }
+    pub fn new(mappings: Option<HashMap<String, String>>, output: QradarOutput) -> Self {
+        QradarBackend { mappings, output }
+    }
+
+    /// The QRadar log source type name of the sigma logsource, if any.
+    fn log_source_type(&self, logsource: &SigmaRuleLogSource) -> Option<String> {
+        if let Some(name) = logsource_mapping(self.mappings.as_ref(), logsource) {
+            return Some(name);
+        }
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let service = logsource.service.as_deref().unwrap_or_default();
+        let name = match (product, service) {
+            ("windows", _) => "Microsoft Windows Security Event Log",
+            ("linux", _) => "Linux OS",
+            ("macos", _) => "Mac OS X",
+            ("aws", _) => "Amazon AWS CloudTrail",
+            ("azure", "signinlogs") | ("azure", "auditlogs") => "Microsoft Azure Active Directory",
+            ("azure", _) => "Microsoft Azure Platform",
+            ("m365", _) => "Microsoft 365 Defender",
+            ("gcp", _) => "Google Cloud Platform Audit",
+            ("okta", _) => "Okta",
+            ("zeek", _) => "Zeek",
+            ("apache", _) => "Apache HTTP Server",
+            _ => return None,
+        };
+        Some(name.to_string())
+    }
+
+    /// Flow rules are searched in the flows database, everything else in the events one.
+    fn is_flow(&self, logsource: &SigmaRuleLogSource) -> bool {
+        matches!(logsource.product.as_deref(), Some("qflow") | Some("ipfix"))
+            || logsource.service.as_deref() == Some("netflow")
+            || logsource.category.as_deref() == Some("flow")
+    }
+
+    /// The AQL filter of the detection condition, without the database and log source selection.
+    fn build_filter(&self, rule: &SigmaRule) -> String {
+        render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            build_predicate(&quote_field(&self.get_mapping(field.to_string())), operator)
+        })
+    }
+
+    /// The filter of the rule restricted to its log source type, as tested by the rule exports.
+    fn build_rule_filter(&self, rule: &SigmaRule) -> String {
+        let filter = self.build_filter(rule);
+        match self.log_source_type(&rule.logsource) {
+            Some(name) if !self.is_flow(&rule.logsource) => format!(
+                "LOGSOURCETYPENAME(devicetype)='{}' AND {filter}",
+                escape_string(&name)
+            ),
+            _ => filter,
+        }
+    }
+
+    /// The rule exports test single events, so the aggregations cannot be converted.
+    fn check_aggregation(&self, sigma_rule: &SigmaRule) {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        if aggregation.is_some() {
+            eprintln!(
+                "QRadar rule filters test single events, the aggregation of '{}' is dropped",
+                sigma_rule.title
+            );
+        }
+    }
+
+    fn build_rule_xml(&self, sigma_rule: &SigmaRule) -> String {
+        self.check_aggregation(sigma_rule);
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        // rules without a sigma id get one derived from their title
+        let id = match &sigma_rule.id {
+            Some(id) => id.to_string(),
+            None => stable_uuid(&sigma_rule.title).to_string(),
+        };
+        let rule_type = if self.is_flow(&sigma_rule.logsource) {
+            "FLOW"
+        } else {
+            "EVENT"
+        };
+        let severity = severity(&siem_rule.alert.severity);
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<rule owner="admin" scope="LOCAL" type="{rule_type}" roleDefinition="false" buildingBlock="false" enabled="true" id="{}">
+    <name>{}</name>
+    <notes>{}</notes>
+    <testDefinitions>
+        <test requiredCapabilities="EVENTVIEWER|FLOWVIEWER" group="Common Property Tests" uid="0" name="com.q1labs.semsources.cre.tests.AQLFilterTest" id="1">
+            <text>when the {} matches this AQL filter query</text>
+            <parameter id="1">
+                <initialText>this AQL filter query</initialText>
+                <selectionLabel>AQL filter</selectionLabel>
+                <userSelection>{}</userSelection>
+            </parameter>
+        </test>
+    </testDefinitions>
+    <actions forceOffenseCreation="true" includeDetectedEventsByDefault="true" offenseMapping="0" severity="{severity}" credibility="{severity}" relevance="{severity}"/>
+    <responses referenceTable="false" referenceMap="false" referenceMapOfMap="false" referenceMapOfSet="false" reference="false">
+        <newevent name="{}" description="{}" severity="{severity}" credibility="{severity}" relevance="{severity}" lowLevelCategory="18001" qid="0" forceOffenseCreation="true" offenseMapping="0" contributeOffenseName="true"/>
+    </responses>
+</rule>"#,
+            escape_xml(&id),
+            escape_xml(&sigma_rule.title),
+            escape_xml(sigma_rule.description.as_deref().unwrap_or_default()),
+            rule_type.to_lowercase(),
+            escape_xml(&self.build_rule_filter(sigma_rule)),
+            escape_xml(&sigma_rule.title),
+            escape_xml(sigma_rule.description.as_deref().unwrap_or_default()),
+        )
+    }
+
+    fn build_building_block(&self, sigma_rule: &SigmaRule) -> String {
+        self.check_aggregation(sigma_rule);
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let building_block = json!({
+            "name": format!("BB: {}", sigma_rule.title),
+            "type": if self.is_flow(&sigma_rule.logsource) { "FLOW" } else { "EVENT" },
+            "enabled": true,
+            "owner": "admin",
+            "origin": "USER",
+            "notes": sigma_rule.description.as_deref().unwrap_or_default(),
+            "aql_filter": self.build_rule_filter(sigma_rule),
+            "severity": severity(&siem_rule.alert.severity),
+            "sigma_id": sigma_rule.id.as_deref().unwrap_or_default(),
+            "tags": sigma_rule.tags.clone().unwrap_or_default(),
+        });
+        serde_json::to_string_pretty(&building_block).unwrap_or_default()
     }
 }
 
 impl BackEnd for QradarBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        self.build_query(&sigma_rule).clone()
+        match self.output {
+            QradarOutput::Query => self.build_query(&sigma_rule),
+            QradarOutput::RuleXml => self.build_rule_xml(&sigma_rule),
+            QradarOutput::BuildingBlock => self.build_building_block(&sigma_rule),
+        }
     }
 }
 
@@ -31,331 +188,179 @@ impl RequiresMappings for QradarBackend {
     }
 
     fn get_default_field_name(&self, _args: Option<String>) -> String {
-        "UTF8(payload)".to_string()
+        PAYLOAD.to_string()
     }
 }
 
 impl QueryBuilder for QradarBackend {
     fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut database = "events";
-        if let (Some(product), Some(service), Some(category)) = (
-            rule.logsource.product.clone(),
-            rule.logsource.service.clone(),
-            rule.logsource.category.clone(),
-        ) {
-            if product.to_string().eq(&String::from("qflow"))
-                || product.to_string().eq(&String::from("ipfix"))
-                || service.to_string().eq(&String::from("netflow"))
-                || category.to_string().eq(&String::from("flow"))
-            {
-                database = "flows";
-            }
-        }
-        let mut query_str = format!("SELECT UTF8(payload) FROM {} WHERE LOGSOURCETYPENAME(devicetype)='Microsoft Windows Security Event Log' AND ", database);
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
+        let database = if self.is_flow(&rule.logsource) {
+            "flows"
+        } else {
+            "events"
+        };
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
             Err(e) => {
                 eprintln!("{:?}", e);
                 exit(1);
             }
         };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().into_iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            for condition in &sub_rule.conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let regex;
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace("\\", "\\\\");
-                        regex = format!("{}%", pattern);
-                        query_str.push_str(self.get_mapping(cond_name).as_str());
-                        query_str.push_str(format!(" ILIKE '{}'", regex).as_str());
-                        // Map the condition name
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace("\\", "\\\\");
-                        regex = format!("%{}", pattern);
-                        query_str.push_str(self.get_mapping(cond_name).as_str());
-                        query_str.push_str(format!(" ILIKE '{}'", regex).as_str());
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace("\\", "\\\\");
-                        regex = format!("%{}%", pattern);
-                        query_str.push_str(self.get_mapping(cond_name).as_str());
-                        query_str.push_str(format!(" ILIKE '{} '", regex).as_str())
-                    }
-                    RuleOperator::Equals(field) => {
-                        match field {
-                            SiemField::Array(_) => {}
-                            _ => {
-                                // query_str.push('(');
-                                query_str.push_str(self.get_mapping(cond_name).as_str());
-                                query_str.push_str(format!("='{}'", field.to_string()).as_str());
-                                // query_str.push(')');
-                            }
-                        }
-                    }
-                    RuleOperator::Any(cond_list) => {
-                        let cond_name = self.get_mapping(cond_name);
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{} ILIKE '%{}%'", cond_name, val));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{} ILIKE '{}%'", cond_name, pattern));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("{} ILIKE '%{}'", cond_name, pattern));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("{} ILIKE %{}%", cond_name, pattern));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" OR ");
-                        query_str.push_str(format!("({})", joined_sub_queries).as_str());
-                    }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
-            }
-            let mut condition_query = String::new();
-            condition_query.push('(');
-            condition_query.push_str(conditions_list.join(" AND ").as_str());
-            condition_query.push(')');
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
-        }
+        let filter = self.build_rule_filter(rule);
+        let aggregation = match aggregation {
+            Some(aggregation) => aggregation,
+            None => return format!("SELECT UTF8(payload) FROM {database} WHERE {filter}"),
+        };
+        let column = |field: &str| quote_field(&self.get_mapping(field.to_string()));
+        // count(field) counts the distinct values of the field
+        let value = match (
+            aggregation.function.to_uppercase().as_str(),
+            &aggregation.field,
+        ) {
+            ("COUNT", Some(field)) => format!("UNIQUECOUNT({})", column(field)),
+            (function, Some(field)) => format!("{function}({})", column(field)),
+            (function, None) => format!("{function}(*)"),
+        };
+        let operator = match aggregation.operator.as_ref() {
+            "==" => "=",
+            operator => operator,
+        };
+        let (select, group_by) = match &aggregation.group_by {
+            Some(group_by) => (
+                format!("{}, ", column(group_by)),
+                format!(" GROUP BY {}", column(group_by)),
+            ),
+            None => (String::new(), String::new()),
+        };
+        let last = match &rule.detection.timeframe {
+            Some(timeframe) => format!(
+                " LAST {} MINUTES",
+                (timeframe_seconds(timeframe) / 60).max(1)
+            ),
+            None => String::new(),
+        };
+        format!(
+            "SELECT {select}{value} AS event_count FROM {database} WHERE {filter}{group_by} HAVING {value} {operator} {}{last}",
+            aggregation.value
+        )
+    }
+}
 
-        // Check the conditions of this subrule
-        for condition in conditions.clone() {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace("*", "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" OR ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace("*", "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" AND ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" NOT ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace("*", "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" OR ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace("*", "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" AND ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" AND ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace("*", "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace("*", "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace("*", "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace("*", "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" OR ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace("*", "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace("*", "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace("*", "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace("*", "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
+/// Custom properties holding spaces or other special characters have to be double quoted.
+fn quote_field(field: &str) -> String {
+    let is_plain = field
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    if is_plain || field.contains('(') || field.starts_with('"') {
+        field.to_string()
+    } else {
+        format!("\"{}\"", field.replace('"', "\\\""))
+    }
+}
+
+fn build_predicate(field: &str, operator: &RuleOperator) -> String {
+    let like = |pattern: String| format!("{field} ILIKE '{pattern}'");
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => join_predicates(
+            values
+                .iter()
+                .map(|value| {
+                    build_predicate(field, &RuleOperator::Equals(SiemField::Text(value.clone())))
+                })
+                .collect(),
+            "OR",
+        ),
+        // the payload holds the whole event, the value is only a part of it
+        RuleOperator::Equals(value) if field == PAYLOAD => {
+            like(format!("%{}%", escape_like(&value.to_string())))
+        }
+        RuleOperator::StartsWith(pattern) | RuleOperator::EndsWith(pattern) if field == PAYLOAD => {
+            like(format!("%{}%", escape_like(pattern)))
+        }
+        RuleOperator::Equals(SiemField::Text(value)) => like(escape_like(value)),
+        RuleOperator::Equals(value) => format!("{field}={value}"),
+        RuleOperator::StartsWith(pattern) => like(format!("{}%", escape_like(pattern))),
+        RuleOperator::EndsWith(pattern) => like(format!("%{}", escape_like(pattern))),
+        RuleOperator::Contains(pattern) => like(format!("%{}%", escape_like(pattern))),
+        RuleOperator::Matches(regex) => {
+            format!("{field} MATCHES '{}'", escape_string(regex.as_str()))
+        }
+        RuleOperator::SameNet((ip, net)) => {
+            let ip = match ip {
+                SiemIp::V4(ip) => ipv4_to_str(*ip),
+                SiemIp::V6(ip) => ipv6_to_str(*ip),
+            };
+            format!("INCIDR('{ip}/{net}', {field})")
+        }
+        RuleOperator::IsNull(_) if field == PAYLOAD => String::new(),
+        RuleOperator::IsNull(_) => format!("{field} IS NULL"),
+        RuleOperator::Any(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
             }
         }
-        query_str.clone()
+        _ => String::new(),
+    }
+}
+
+fn escape_string(value: &str) -> String {
+    value.replace('\'', "''")
+}
+
+/// Escapes a literal value for an ILIKE pattern, `%` and `_` being its wildcards.
+fn escape_like(value: &str) -> String {
+    escape_string(
+        &value
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_"),
+    )
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// QRadar magnitudes go from 0 to 10.
+fn severity(severity: &AlertSeverity) -> u8 {
+    match severity {
+        AlertSeverity::INFORMATIONAL => 1,
+        AlertSeverity::LOW => 3,
+        AlertSeverity::MEDIUM => 5,
+        AlertSeverity::HIGH => 7,
+        AlertSeverity::CRITICAL => 10,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::backend::qradar::QradarBackend;
+    use crate::backend::qradar::{QradarBackend, QradarOutput};
+    use crate::backend::BackEnd;
     use crate::backend::QueryBuilder;
     use crate::parse_sigma_rule;
+    use std::collections::HashMap;
     use std::env::current_dir;
 
     #[test]
@@ -377,9 +382,82 @@ mod tests {
       OR *%%8450*))) OR ((SubcategoryGuid:"{0CCE9217-69AE-11D9-BED3-505054503030}"
       AND winlog.event_data.AuditPolicyChanges:*%%8448*))))"###;
         let rule = parse_sigma_rule(_7zip_rule_path.as_str()).unwrap();
-        let backend = QradarBackend::new(None);
+        let backend = QradarBackend::new(None, QradarOutput::Query);
         let query = backend.build_query(&rule);
         println!("{}", query);
         assert_eq!(query.as_str(), expected_query_str);
     }
+
+    #[test]
+    pub fn test_filter() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let mappings = HashMap::from([
+            ("Image".to_string(), "Process Path".to_string()),
+            ("CommandLine".to_string(), "Command".to_string()),
+            (
+                "logsource.windows".to_string(),
+                "Microsoft Windows Sysmon".to_string(),
+            ),
+        ]);
+        let backend = QradarBackend::new(Some(mappings), QradarOutput::Query);
+        assert_eq!(
+            backend.build_query(&rule),
+            r#"SELECT UTF8(payload) FROM events WHERE LOGSOURCETYPENAME(devicetype)='Microsoft Windows Sysmon' AND ((("Process Path" ILIKE '%\\cmd.exe' OR UTF8(payload) ILIKE '%Cmd.Exe%') AND UTF8(payload) ILIKE '%\\7zFM.exe%') AND NOT ((Command ILIKE '%/c%' OR Command ILIKE '%/k%' OR Command ILIKE '%/r%') OR Command IS NULL))"#
+        );
+    }
+
+    #[test]
+    pub fn test_rule_xml() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Outbound Transfer
+logsource:
+    product: linux
+detection:
+    selection:
+        destinationip|cidr: 10.0.0.0/8
+        username|startswith: "adm_"
+    condition: selection
+level: medium
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([
+            ("destinationip".to_string(), "destinationip".to_string()),
+            ("username".to_string(), "username".to_string()),
+        ]);
+        let xml = QradarBackend::new(Some(mappings), QradarOutput::RuleXml).convert_rule(rule);
+        assert!(xml.contains("<name>Outbound Transfer</name>"));
+        assert!(xml.contains("<userSelection>LOGSOURCETYPENAME(devicetype)=&apos;Linux OS&apos; AND (INCIDR(&apos;10.0.0.0/8&apos;, destinationip) AND username ILIKE &apos;adm\\_%&apos;)</userSelection>"));
+        assert!(xml.contains("severity=\"5\""));
+        assert!(xml.contains(" id=\"bbb7f613-b6f7-5f5c-91b8-a0469e27cf4c\">"));
+    }
+
+    #[test]
+    pub fn test_aggregation() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Password Spraying
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 1h
+    condition: selection | count(TargetUserName) by IpAddress > 20
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([
+            ("EventID".to_string(), "qid".to_string()),
+            ("TargetUserName".to_string(), "username".to_string()),
+            ("IpAddress".to_string(), "sourceip".to_string()),
+        ]);
+        assert_eq!(
+            QradarBackend::new(Some(mappings), QradarOutput::Query).convert_rule(rule),
+            "SELECT sourceip, UNIQUECOUNT(username) AS event_count FROM events WHERE LOGSOURCETYPENAME(devicetype)='Microsoft Windows Security Event Log' AND qid=4625 GROUP BY sourceip HAVING UNIQUECOUNT(username) > 20 LAST 60 MINUTES"
+        );
+    }
 }
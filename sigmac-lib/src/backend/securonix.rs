@@ -1,21 +1,168 @@
 use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
-use crate::prelude::rule::RuleCondition;
-use crate::prelude::types::LogString;
-use crate::prelude::SiemField;
+use crate::prelude::rule::sigma::{ConditionAggregation, SigmaRuleLogSource};
+use crate::prelude::{AlertSeverity, SiemField};
 use crate::sigma::components::rule::sigma::SigmaRule;
 use crate::sigma::components::rule::RuleOperator;
 use crate::sigma::components::rule::SiemRule;
-use crate::utils::parse_condition;
+use crate::utils::{
+    escape_string, join_predicates, logsource_mapping, parse_aggregation, render_condition,
+};
+use serde_json::json;
 use std::collections::HashMap;
 use std::process::exit;
 
+/// The artifacts the Securonix backend is able to produce.
 #[derive(Clone, Debug, Default)]
-pub struct SecuronixBackend {}
+pub enum SecuronixOutput {
+    /// The Spotter query.
+    #[default]
+    Query,
+    /// A policy JSON holding the Spotter query and the violation metadata.
+    Policy,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SecuronixBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: SecuronixOutput,
+}
+
+impl SecuronixBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: SecuronixOutput) -> Self {
+        SecuronixBackend { mappings, output }
+    }
+
+    /// The Spotter condition selecting the events of the logsource: the resource group given by
+    /// the `logsource.*` mappings, or else the functionality of the product.
+    fn datasource(&self, logsource: &SigmaRuleLogSource) -> Option<String> {
+        if let Some(name) = logsource_mapping(self.mappings.as_ref(), logsource) {
+            return Some(format!("resourcegroupname = \"{}\"", escape_string(&name)));
+        }
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let category = logsource.category.as_deref().unwrap_or_default();
+        let functionality = match (product, category) {
+            ("windows", _) => "Microsoft Windows",
+            ("linux", _) | ("macos", _) => "Unix / Linux / AIX",
+            ("aws", _) | ("azure", _) | ("gcp", _) | ("m365", _) => "Cloud Services / Applications",
+            (_, "proxy") => "Web Proxy",
+            (_, "firewall") => "Next Generation Firewall",
+            (_, "dns") => "DNS / DHCP",
+            _ => return None,
+        };
+        Some(format!("rg_functionality = \"{functionality}\""))
+    }
+
+    fn build_policy(&self, sigma_rule: &SigmaRule) -> String {
+        if self.aggregation(sigma_rule).is_some() {
+            eprintln!(
+                "Securonix policies match single events, the aggregation of '{}' is dropped",
+                sigma_rule.title
+            );
+        }
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let tactics: Vec<&str> = siem_rule
+            .mitre
+            .tactics
+            .iter()
+            .map(|tactic| tactic.name())
+            .collect();
+        let techniques: Vec<String> = siem_rule
+            .mitre
+            .techniques
+            .iter()
+            .map(|technique| technique.to_string())
+            .collect();
+        // Threat indicators name what the violation is evidence of, ATT&CK giving the most
+        // specific one.
+        let threat_indicator = techniques
+            .first()
+            .cloned()
+            .or_else(|| tactics.first().map(|tactic| tactic.to_string()))
+            .unwrap_or_else(|| sigma_rule.title.to_string());
+        let (criticality, risk_level) = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => ("Low", 1),
+            AlertSeverity::LOW => ("Low", 3),
+            AlertSeverity::MEDIUM => ("Medium", 5),
+            AlertSeverity::HIGH => ("High", 7),
+            AlertSeverity::CRITICAL => ("Critical", 9),
+        };
+        let policy = json!({
+            "policyName": format!("Sigma - {}", sigma_rule.title),
+            "violationName": sigma_rule.title,
+            "description": sigma_rule.description.as_deref().unwrap_or_default(),
+            "criticality": criticality,
+            "riskLevel": risk_level,
+            "threatIndicator": threat_indicator,
+            "mitreTactics": tactics,
+            "mitreTechniques": techniques,
+            "category": "ALERT",
+            "violator": "Activityaccount",
+            "query": self.convert_query(sigma_rule),
+            "enabled": true,
+            "sigmaId": sigma_rule.id.as_deref().unwrap_or_default()
+        });
+        serde_json::to_string_pretty(&policy).unwrap_or_default()
+    }
+
+    fn aggregation(&self, sigma_rule: &SigmaRule) -> Option<ConditionAggregation> {
+        match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        }
+    }
+
+    /// The Spotter query followed by the aggregation of the rule. Spotter counts the events
+    /// of each group with `STATS`, so only the event counts by a field can be converted.
+    fn convert_aggregated_query(&self, sigma_rule: &SigmaRule) -> String {
+        let query = self.convert_query(sigma_rule);
+        let aggregation = match self.aggregation(sigma_rule) {
+            Some(aggregation) => aggregation,
+            None => return query,
+        };
+        match (
+            aggregation.function.as_ref(),
+            &aggregation.field,
+            &aggregation.group_by,
+        ) {
+            ("count", None, Some(group_by)) => {
+                let operator = match aggregation.operator.as_ref() {
+                    "==" => "=",
+                    operator => operator,
+                };
+                format!(
+                    "{query} | STATS {} | WHERE count {operator} {}",
+                    self.get_mapping(group_by.to_string()),
+                    aggregation.value
+                )
+            }
+            _ => {
+                eprintln!(
+                    "Spotter only counts the events by a field, the aggregation of '{}' is dropped",
+                    sigma_rule.title
+                );
+                query
+            }
+        }
+    }
+
+    /// The full Spotter query, searching the archive of the logsource datasource.
+    fn convert_query(&self, sigma_rule: &SigmaRule) -> String {
+        let mut predicates = vec!["index = archive".to_string()];
+        predicates.extend(self.datasource(&sigma_rule.logsource));
+        let query = self.build_query(sigma_rule);
+        if !query.is_empty() {
+            predicates.push(query);
+        }
+        predicates.join(" AND ")
+    }
+}
 
 impl RequiresMappings for SecuronixBackend {
     fn get_field_map(&self) -> Option<HashMap<String, String>> {
-        None
+        self.mappings.clone()
     }
 
     fn get_default_field_name(&self, args: Option<String>) -> String {
@@ -161,353 +308,164 @@ impl RequiresMappings for SecuronixBackend {
 
 impl BackEnd for SecuronixBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        let query = self.build_query(&sigma_rule);
-        format!("index = archive AND {query}")
+        match self.output {
+            SecuronixOutput::Query => self.convert_aggregated_query(&sigma_rule),
+            SecuronixOutput::Policy => self.build_policy(&sigma_rule),
+        }
     }
 }
 
 impl QueryBuilder for SecuronixBackend {
     fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
-            Err(e) => {
-                eprintln!("{:?}", e);
-                exit(1);
-            }
-        };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            if let Some(product) = &rule.logsource.product {
-                if product.to_string().eq("windows") {
-                    let product_condition = RuleCondition {
-                        field: LogString::Owned("rg_functionality".to_string()),
-                        operator: RuleOperator::Equals(SiemField::Text(LogString::Owned(
-                            "Microsoft Windows".to_string(),
-                        ))),
-                    };
-                    subrule_conditions.push(product_condition);
-                }
-            };
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let mapped_cond_name = self.get_mapping(cond_name);
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!("{mapped_cond_name} STARTS WITH \"{pattern}\"").as_str(),
-                        );
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!("{mapped_cond_name} ENDS WITH \"{pattern}\"").as_str(),
-                        );
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!("{mapped_cond_name} CONTAINS \"{pattern}\"").as_str(),
-                        );
-                    }
-                    RuleOperator::Equals(field) => match field {
-                        SiemField::Array(elements) => {
-                            let joined_query = elements
-                                .iter()
-                                .map(|val| format!("{mapped_cond_name} = \"{val}\""))
-                                .collect::<Vec<_>>()
-                                .join(" OR ");
-                            query_str.push_str(format!("({joined_query})").as_str())
-                        }
-                        _ => {
-                            query_str
-                                .push_str(format!("{mapped_cond_name} = \"{field}\"").as_str());
-                        }
-                    },
-                    RuleOperator::Any(cond_list) => {
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{mapped_cond_name} = \"{val}\""));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{mapped_cond_name} STARTS WITH \"{pattern}\""
-                                    ));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{mapped_cond_name} ENDS WITH \"{pattern}\""
-                                    ));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries
-                                        .push(format!("{mapped_cond_name} CONTAINS \"{pattern}\""));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" OR ");
-                        query_str.push_str(format!("({})", joined_sub_queries).as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{mapped_cond_name} = \"{val}\""));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{mapped_cond_name} STARTS WITH \"{pattern}\""
-                                    ));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{mapped_cond_name} ENDS WITH \"{pattern}\""
-                                    ));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries
-                                        .push(format!("{mapped_cond_name} CONTAINS \"{pattern}\""));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" AND ");
-                        query_str.push_str(format!("({joined_sub_queries})").as_str());
+        render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            build_predicate(&self.get_mapping(field.to_string()), operator)
+        })
+    }
+}
+
+fn build_predicate(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => format!(
+            "{field} IN ({})",
+            values
+                .iter()
+                .map(|value| format!("\"{}\"", escape_string(value)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        RuleOperator::Equals(value) => {
+            format!("{field} = \"{}\"", escape_string(&value.to_string()))
+        }
+        RuleOperator::StartsWith(pattern) => {
+            format!("{field} STARTS WITH \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::EndsWith(pattern) => {
+            format!("{field} ENDS WITH \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::Contains(pattern) => {
+            format!("{field} CONTAINS \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::Matches(regex) => {
+            format!("{field} REGEX \"{}\"", escape_string(regex.as_str()))
+        }
+        RuleOperator::IsNull(_) => format!("{field} NULL"),
+        RuleOperator::Any(operators) => {
+            // Several equalities of the same field are an IN list
+            let values: Option<Vec<String>> = operators
+                .iter()
+                .map(|operator| match operator.as_ref() {
+                    RuleOperator::Equals(SiemField::Array(_)) => None,
+                    RuleOperator::Equals(value) => {
+                        Some(format!("\"{}\"", escape_string(&value.to_string())))
                     }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
+                    _ => None,
+                })
+                .collect();
+            match values {
+                Some(values) if values.len() > 1 => format!("{field} IN ({})", values.join(", ")),
+                _ => join_predicates(
+                    operators
+                        .iter()
+                        .map(|operator| build_predicate(field, operator))
+                        .collect(),
+                    "OR",
+                ),
             }
-            let mut condition_query = String::new();
-            condition_query.push_str(format!("({})", conditions_list.join(" AND ")).as_str());
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
         }
-
-        // Check the conditions of this subrule
-        for condition in conditions {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" OR ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" AND ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" NOT ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" or ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" AND ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" AND ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" or ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" or ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" and ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" not ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" or ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" and ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(field, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => match operator.as_ref() {
+            RuleOperator::IsNull(_) => format!("{field} NOT NULL"),
+            operator => {
+                let predicate = build_predicate(field, operator);
+                if predicate.is_empty() {
+                    predicate
+                } else {
+                    format!("NOT ({predicate})")
                 }
             }
-        }
-        query_str.clone()
+        },
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::securonix::{SecuronixBackend, SecuronixOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_spotter_query() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        assert_eq!(
+            SecuronixBackend::default().convert_rule(rule),
+            r#"index = archive AND rg_functionality = "Microsoft Windows" AND (((@destinationprocessname ENDS WITH "\\cmd.exe" OR @filename = "Cmd.Exe") AND @sourceprocessname ENDS WITH "\\7zFM.exe") AND NOT ((@resourcecustomfield1 CONTAINS "/c" OR @resourcecustomfield1 CONTAINS "/k" OR @resourcecustomfield1 CONTAINS "/r") OR @resourcecustomfield1 NULL))"#
+        );
+    }
+
+    #[test]
+    pub fn test_spotter_stats() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    condition: selection | count() by IpAddress > 10
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([("IpAddress".to_string(), "@ipaddress".to_string())]);
+        let backend = SecuronixBackend::new(Some(mappings), SecuronixOutput::Query);
+        assert!(backend
+            .convert_rule(rule)
+            .ends_with(" | STATS @ipaddress | WHERE count > 10"));
+    }
+
+    #[test]
+    pub fn test_policy() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Suspicious Shell Spawn
+tags:
+    - attack.execution
+    - attack.t1059.004
+logsource:
+    product: linux
+    category: process_creation
+detection:
+    selection:
+        Image:
+            - /bin/sh
+            - /bin/bash
+        CommandLine|re: curl .+\| ?sh
+    filter:
+        ParentImage: null
+    condition: selection and not filter
+level: high
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([("logsource.linux".to_string(), "Linux Auditd".to_string())]);
+        let backend = SecuronixBackend::new(Some(mappings), SecuronixOutput::Policy);
+        let policy: serde_json::Value = serde_json::from_str(&backend.convert_rule(rule)).unwrap();
+        assert_eq!(policy["violationName"], "Suspicious Shell Spawn");
+        assert_eq!(policy["criticality"], "High");
+        assert_eq!(policy["threatIndicator"], "T1059.004");
+        assert_eq!(
+            policy["query"],
+            r#"index = archive AND resourcegroupname = "Linux Auditd" AND ((@resourcecustomfield1 REGEX "curl .+\\| ?sh" AND @destinationprocessname IN ("/bin/sh", "/bin/bash")) AND NOT @sourceprocessname NULL)"#
+        );
     }
 }
@@ -1,19 +1,354 @@
 use crate::backend::{BackEnd, QueryBuilder};
 use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
-use crate::prelude::SiemField;
-use crate::sigma::components::rule::sigma::SigmaRule;
+use crate::prelude::{AlertSeverity, SiemField};
+use crate::sigma::components::rule::sigma::{SigmaRule, SigmaRuleLogSource};
 use crate::sigma::components::rule::RuleOperator;
 use crate::sigma::components::rule::SiemRule;
-use crate::utils::parse_condition;
+use crate::utils::{parse_condition, stable_uuid, timeframe_seconds};
+use serde_json::{json, Value};
 use std::process::exit;
+use uuid::Uuid;
 
+/// The tables the query is run against.
 #[derive(Clone, Debug, Default)]
-pub struct SentinelBackend {}
+pub enum SentinelSchema {
+    /// The Windows Security Events table of Log Analytics.
+    #[default]
+    SecurityEvent,
+    /// The Microsoft Defender XDR advanced hunting tables (DeviceProcessEvents, DeviceNetworkEvents...),
+    /// chosen from the logsource. Sysmon fields are renamed to their Defender XDR counterparts.
+    DefenderXdr,
+}
+
+/// The artifacts the Sentinel backend is able to produce.
+#[derive(Clone, Debug, Default)]
+pub enum SentinelOutput {
+    /// The bare KQL query.
+    #[default]
+    Query,
+    /// A scheduled analytics rule wrapped in an ARM template.
+    ArmTemplate,
+    /// A scheduled analytics rule in the YAML format of the Azure Sentinel repository.
+    Yaml,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SentinelBackend {
+    schema: SentinelSchema,
+    output: SentinelOutput,
+}
+
+impl SentinelBackend {
+    pub fn new(schema: SentinelSchema, output: SentinelOutput) -> Self {
+        SentinelBackend { schema, output }
+    }
+
+    /// Builds the KQL query, prefixed with the table it runs against.
+    fn build_table_query(&self, sigma_rule: &SigmaRule) -> String {
+        let query = self.build_query(sigma_rule);
+        match self.schema {
+            SentinelSchema::SecurityEvent => format!("SecurityEvent | where {query}"),
+            SentinelSchema::DefenderXdr => {
+                let table = defender_table(&sigma_rule.logsource);
+                match defender_action_type(&sigma_rule.logsource) {
+                    Some(action_type) => {
+                        format!("{table} | where ActionType == \"{action_type}\" | where {query}")
+                    }
+                    None => format!("{table} | where {query}"),
+                }
+            }
+        }
+    }
+
+    /// Maps a Sysmon field name to the column holding it in the table the rule runs against.
+    fn get_field_name(&self, logsource: &SigmaRuleLogSource, field: String) -> String {
+        match self.schema {
+            SentinelSchema::SecurityEvent => field,
+            SentinelSchema::DefenderXdr => defender_field_name(defender_table(logsource), &field)
+                .map(|v| v.to_string())
+                .unwrap_or(field),
+        }
+    }
+
+    /// The columns of the query results that identify the entities involved in the alert.
+    fn entity_mappings(
+        &self,
+        logsource: &SigmaRuleLogSource,
+    ) -> Vec<(&'static str, &'static str, &'static str)> {
+        match self.schema {
+            SentinelSchema::SecurityEvent => vec![
+                ("Host", "FullName", "Computer"),
+                ("Account", "FullName", "Account"),
+            ],
+            SentinelSchema::DefenderXdr => {
+                let mut mappings = vec![("Host", "HostName", "DeviceName")];
+                match defender_table(logsource) {
+                    "DeviceProcessEvents" => {
+                        mappings.push(("Account", "Name", "AccountName"));
+                        mappings.push(("Process", "CommandLine", "ProcessCommandLine"));
+                    }
+                    "DeviceNetworkEvents" => {
+                        mappings.push(("Account", "Name", "InitiatingProcessAccountName"));
+                        mappings.push(("IP", "Address", "RemoteIP"));
+                    }
+                    "DeviceFileEvents" | "DeviceImageLoadEvents" => {
+                        mappings.push(("Account", "Name", "InitiatingProcessAccountName"));
+                        mappings.push(("File", "Name", "FileName"));
+                    }
+                    "DeviceRegistryEvents" => {
+                        mappings.push(("Account", "Name", "InitiatingProcessAccountName"));
+                        mappings.push(("RegistryKey", "Key", "RegistryKey"));
+                    }
+                    _ => mappings.push(("Account", "Name", "InitiatingProcessAccountName")),
+                }
+                mappings
+            }
+        }
+    }
+
+    /// Builds the properties shared by both analytics rule formats.
+    fn build_analytics_rule(&self, sigma_rule: &SigmaRule) -> AnalyticsRule {
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let mut tactics = vec![];
+        for tactic in &siem_rule.mitre.tactics {
+            // Sentinel expects the tactic names in pascal case. Ex: "CommandAndControl"
+            let tactic: String = tactic
+                .name()
+                .split(' ')
+                .map(|word| word[..1].to_uppercase() + &word[1..])
+                .collect();
+            if !tactics.contains(&tactic) {
+                tactics.push(tactic);
+            }
+        }
+        let mut techniques = vec![];
+        for technique in &siem_rule.mitre.techniques {
+            let technique = technique.parent_id();
+            if !techniques.contains(&technique) {
+                techniques.push(technique);
+            }
+        }
+        AnalyticsRule {
+            id: rule_guid(sigma_rule),
+            name: sigma_rule.title.to_string(),
+            description: sigma_rule
+                .description
+                .as_ref()
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            severity: match siem_rule.alert.severity {
+                AlertSeverity::INFORMATIONAL => "Informational",
+                AlertSeverity::LOW => "Low",
+                AlertSeverity::MEDIUM => "Medium",
+                AlertSeverity::HIGH | AlertSeverity::CRITICAL => "High",
+            },
+            query: self.build_table_query(sigma_rule),
+            tactics,
+            techniques,
+            entity_mappings: self.entity_mappings(&sigma_rule.logsource),
+        }
+    }
+
+    fn build_arm_template(&self, sigma_rule: &SigmaRule) -> String {
+        let rule = self.build_analytics_rule(sigma_rule);
+        let (period, _) = query_schedule(sigma_rule);
+        let entity_mappings: Vec<Value> = rule
+            .entity_mappings
+            .iter()
+            .map(|(entity, identifier, column)| {
+                json!({
+                    "entityType": entity,
+                    "fieldMappings": [{"identifier": identifier, "columnName": column}]
+                })
+            })
+            .collect();
+        let template = json!({
+            "$schema": "https://schema.management.azure.com/schemas/2019-04-01/deploymentTemplate.json#",
+            "contentVersion": "1.0.0.0",
+            "parameters": {
+                "workspace": {"type": "String"}
+            },
+            "resources": [{
+                "id": format!("[concat(resourceId('Microsoft.OperationalInsights/workspaces/providers', parameters('workspace'), 'Microsoft.SecurityInsights'),'/alertRules/{}')]", rule.id),
+                "name": format!("[concat(parameters('workspace'),'/Microsoft.SecurityInsights/{}')]", rule.id),
+                "type": "Microsoft.OperationalInsights/workspaces/providers/alertRules",
+                "kind": "Scheduled",
+                "apiVersion": "2022-11-01",
+                "properties": {
+                    "displayName": rule.name,
+                    "description": rule.description,
+                    "severity": rule.severity,
+                    "enabled": true,
+                    "query": rule.query,
+                    "queryFrequency": period,
+                    "queryPeriod": period,
+                    "triggerOperator": "GreaterThan",
+                    "triggerThreshold": 0,
+                    "suppressionDuration": "PT5H",
+                    "suppressionEnabled": false,
+                    "tactics": rule.tactics,
+                    "techniques": rule.techniques,
+                    "entityMappings": entity_mappings
+                }
+            }]
+        });
+        serde_json::to_string_pretty(&template).unwrap()
+    }
+
+    fn build_yaml(&self, sigma_rule: &SigmaRule) -> String {
+        let rule = self.build_analytics_rule(sigma_rule);
+        let (_, period) = query_schedule(sigma_rule);
+        let entity_mappings: Vec<Value> = rule
+            .entity_mappings
+            .iter()
+            .map(|(entity, identifier, column)| {
+                json!({
+                    "entityType": entity,
+                    "fieldMappings": [{"identifier": identifier, "columnName": column}]
+                })
+            })
+            .collect();
+        let yaml = json!({
+            "id": rule.id,
+            "name": rule.name,
+            "description": rule.description,
+            "severity": rule.severity,
+            "requiredDataConnectors": [],
+            "queryFrequency": period,
+            "queryPeriod": period,
+            "triggerOperator": "gt",
+            "triggerThreshold": 0,
+            "tactics": rule.tactics,
+            "relevantTechniques": rule.techniques,
+            "query": rule.query,
+            "entityMappings": entity_mappings,
+            "version": "1.0.0",
+            "kind": "Scheduled"
+        });
+        serde_yaml::to_string(&yaml).unwrap()
+    }
+}
+
+struct AnalyticsRule {
+    id: String,
+    name: String,
+    description: String,
+    severity: &'static str,
+    query: String,
+    tactics: Vec<String>,
+    techniques: Vec<String>,
+    entity_mappings: Vec<(&'static str, &'static str, &'static str)>,
+}
+
+/// How often the analytics rule runs and the period of time it looks back on, from the rule
+/// timeframe (5 minutes by default) within the 5 minutes to 14 days Sentinel accepts. Given as
+/// an ISO 8601 duration and in the short notation of the YAML rules (Ex: `PT1H` and `1h`).
+fn query_schedule(sigma_rule: &SigmaRule) -> (String, String) {
+    let seconds = timeframe_seconds(sigma_rule.detection.timeframe.as_deref().unwrap_or("5m"))
+        .clamp(300, 14 * 86_400);
+    if seconds.is_multiple_of(86_400) {
+        let days = seconds / 86_400;
+        (format!("P{days}D"), format!("{days}d"))
+    } else if seconds.is_multiple_of(3_600) {
+        let hours = seconds / 3_600;
+        (format!("PT{hours}H"), format!("{hours}h"))
+    } else {
+        let minutes = seconds.div_ceil(60);
+        (format!("PT{minutes}M"), format!("{minutes}m"))
+    }
+}
+
+/// Analytics rules are identified by a GUID. Sigma ids are used as is when they are valid UUIDs,
+/// otherwise one is derived from the id (or the title) so converting the same rule twice updates
+/// the same analytics rule.
+fn rule_guid(sigma_rule: &SigmaRule) -> String {
+    if let Some(id) = sigma_rule
+        .id
+        .as_ref()
+        .and_then(|id| Uuid::parse_str(id).ok())
+    {
+        return id.to_string();
+    }
+    stable_uuid(sigma_rule.id.as_deref().unwrap_or(&sigma_rule.title)).to_string()
+}
+
+/// Routes the logsource to one of the Defender XDR advanced hunting tables.
+fn defender_table(logsource: &SigmaRuleLogSource) -> &'static str {
+    match logsource.category.as_deref().unwrap_or_default() {
+        "process_creation" => "DeviceProcessEvents",
+        "network_connection" | "firewall" => "DeviceNetworkEvents",
+        "registry_event" | "registry_add" | "registry_delete" | "registry_set"
+        | "registry_rename" => "DeviceRegistryEvents",
+        "file_event" | "file_change" | "file_delete" | "file_rename" | "file_access"
+        | "create_stream_hash" => "DeviceFileEvents",
+        "image_load" | "driver_load" => "DeviceImageLoadEvents",
+        _ => "DeviceEvents",
+    }
+}
+
+/// DeviceEvents mixes many kinds of events, the logsource narrows it down to a single ActionType.
+fn defender_action_type(logsource: &SigmaRuleLogSource) -> Option<&'static str> {
+    match logsource.category.as_deref().unwrap_or_default() {
+        "dns_query" => Some("DnsQueryResponse"),
+        "create_remote_thread" => Some("CreateRemoteThreadApiCall"),
+        "pipe_created" => Some("NamedPipeEvent"),
+        "process_access" => Some("OpenProcessApiCall"),
+        _ => None,
+    }
+}
+
+/// Sysmon to Defender XDR field mappings, the same Sysmon field lives in different columns
+/// depending on the table.
+fn defender_field_name(table: &str, field: &str) -> Option<&'static str> {
+    let mapping = match (table, field) {
+        ("DeviceProcessEvents", "Image") => "FolderPath",
+        ("DeviceProcessEvents", "OriginalFileName") => "ProcessVersionInfoOriginalFileName",
+        ("DeviceProcessEvents", "CommandLine") => "ProcessCommandLine",
+        ("DeviceProcessEvents", "User") => "AccountName",
+        ("DeviceProcessEvents", "IntegrityLevel") => "ProcessIntegrityLevel",
+        ("DeviceProcessEvents", "Product") => "ProcessVersionInfoProductName",
+        ("DeviceProcessEvents", "Company") => "ProcessVersionInfoCompanyName",
+        ("DeviceProcessEvents", "Description") => "ProcessVersionInfoFileDescription",
+        ("DeviceProcessEvents", "FileVersion") => "ProcessVersionInfoProductVersion",
+        ("DeviceProcessEvents", "LogonId") => "LogonId",
+        ("DeviceProcessEvents", "ParentUser") => "InitiatingProcessAccountName",
+        ("DeviceProcessEvents", "ParentImage") => "InitiatingProcessFolderPath",
+        ("DeviceProcessEvents", "ParentCommandLine") => "InitiatingProcessCommandLine",
+        ("DeviceProcessEvents", "ParentProcessId") => "InitiatingProcessId",
+        ("DeviceNetworkEvents", "DestinationIp") => "RemoteIP",
+        ("DeviceNetworkEvents", "DestinationPort") => "RemotePort",
+        ("DeviceNetworkEvents", "DestinationHostname") => "RemoteUrl",
+        ("DeviceNetworkEvents", "SourceIp") => "LocalIP",
+        ("DeviceNetworkEvents", "SourcePort") => "LocalPort",
+        ("DeviceNetworkEvents", "Protocol") => "Protocol",
+        ("DeviceRegistryEvents", "TargetObject") => "RegistryKey",
+        ("DeviceRegistryEvents", "Details") => "RegistryValueData",
+        ("DeviceRegistryEvents", "EventType") => "ActionType",
+        ("DeviceFileEvents", "TargetFilename") => "FolderPath",
+        ("DeviceImageLoadEvents", "ImageLoaded") => "FolderPath",
+        ("DeviceEvents", "QueryName") => "RemoteUrl",
+        ("DeviceEvents", "TargetImage") => "FolderPath",
+        ("DeviceEvents", "PipeName") => "AdditionalFields",
+        (_, "Image") | (_, "SourceImage") => "InitiatingProcessFolderPath",
+        (_, "CommandLine") => "InitiatingProcessCommandLine",
+        (_, "User") => "InitiatingProcessAccountName",
+        (_, "ProcessId") => "InitiatingProcessId",
+        (_, "Computer") | (_, "ComputerName") => "DeviceName",
+        (_, "md5") | (_, "MD5") => "MD5",
+        (_, "sha1") | (_, "SHA1") => "SHA1",
+        (_, "sha256") | (_, "SHA256") => "SHA256",
+        _ => return None,
+    };
+    Some(mapping)
+}
 
 impl BackEnd for SentinelBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        let query = self.build_query(&sigma_rule);
-        format!("SecurityEvent | where {query}")
+        match self.output {
+            SentinelOutput::Query => self.build_table_query(&sigma_rule),
+            SentinelOutput::ArmTemplate => self.build_arm_template(&sigma_rule),
+            SentinelOutput::Yaml => self.build_yaml(&sigma_rule),
+        }
     }
 }
 
@@ -38,7 +373,7 @@ impl QueryBuilder for SentinelBackend {
                 let mut query_str = String::new();
                 let (cond_name, cond_oper) =
                     (condition.field.to_string(), condition.operator.clone());
-                let mapped_cond_name = cond_name;
+                let mapped_cond_name = self.get_field_name(&rule.logsource, cond_name);
                 match cond_oper.clone() {
                     RuleOperator::StartsWith(mut pattern) => {
                         pattern = pattern.replace('\\', "\\\\");
@@ -367,3 +702,66 @@ impl QueryBuilder for SentinelBackend {
         query_str.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::sentinel::{SentinelBackend, SentinelOutput, SentinelSchema};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+
+    #[test]
+    pub fn test_defender_xdr_query() {
+        let rule = include_str!("../sigma/components/rule/7zip_sigma_rule.yml");
+        let sigma_rule = parse_sigma_rule(rule).unwrap();
+        let backend = SentinelBackend::new(SentinelSchema::DefenderXdr, SentinelOutput::Query);
+        let query = backend.convert_rule(sigma_rule);
+        assert!(query.starts_with("DeviceProcessEvents | where "));
+        assert!(query.contains("InitiatingProcessFolderPath endswith @'\\\\7zFM.exe'"));
+        assert!(query.contains("ProcessVersionInfoOriginalFileName =~ @'Cmd.Exe'"));
+    }
+
+    #[test]
+    pub fn test_analytics_rule() {
+        let rule = include_str!("../sigma/components/rule/c2_sigma_rule.yml");
+        let sigma_rule = parse_sigma_rule(rule).unwrap();
+        let backend =
+            SentinelBackend::new(SentinelSchema::SecurityEvent, SentinelOutput::ArmTemplate);
+        let template: serde_json::Value =
+            serde_json::from_str(&backend.convert_rule(sigma_rule)).unwrap();
+        let properties = &template["resources"][0]["properties"];
+        assert_eq!(properties["severity"], "High");
+        assert_eq!(properties["tactics"][0], "CommandAndControl");
+        assert_eq!(properties["techniques"][0], "T1041");
+        assert_eq!(properties["queryFrequency"], "PT5M");
+        assert_eq!(
+            template["resources"][0]["name"],
+            "[concat(parameters('workspace'),'/Microsoft.SecurityInsights/881834a4-6659-4773-821e-1c151789d873')]"
+        );
+    }
+
+    #[test]
+    pub fn test_analytics_rule_yaml() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+id: sigma-local-0042
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 1h
+    condition: selection
+level: medium
+"#,
+        )
+        .unwrap();
+        let backend = SentinelBackend::new(SentinelSchema::SecurityEvent, SentinelOutput::Yaml);
+        let yaml: serde_json::Value = serde_yaml::from_str(&backend.convert_rule(rule)).unwrap();
+        // derived from the sigma id, which isn't a UUID
+        assert_eq!(yaml["id"], "163a9c58-d0ca-52cd-866e-5878f58c07a4");
+        assert_eq!(yaml["queryFrequency"], "1h");
+        assert_eq!(yaml["queryPeriod"], "1h");
+    }
+}
@@ -0,0 +1,579 @@
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::sigma::{SigmaRule, SigmaRuleLogSource};
+use crate::prelude::rule::{RuleOperator, SiemRule};
+use crate::prelude::{AlertSeverity, SiemField, SiemIp};
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{escape_string, join_predicates, parse_aggregation, render_condition};
+use serde_json::json;
+use std::collections::HashMap;
+use std::process::exit;
+
+/// What the SentinelOne backend emits for a rule.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SentinelOneOutput {
+    /// The Deep Visibility (S1QL 1.0) query.
+    #[default]
+    Query,
+    /// The PowerQuery of the Singularity Data Lake.
+    PowerQuery,
+    /// A STAR custom detection rule JSON, holding the S1QL query.
+    StarRule,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SentinelOneBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: SentinelOneOutput,
+}
+
+impl SentinelOneBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: SentinelOneOutput) -> Self {
+        SentinelOneBackend { mappings, output }
+    }
+
+    fn power_query(&self) -> bool {
+        self.output == SentinelOneOutput::PowerQuery
+    }
+
+    /// The SentinelOne field of a sigma field, in the syntax of the output. The process
+    /// fields are the ones of the created process (`TgtProc*`) in process creation events,
+    /// and the ones of the process acting (`SrcProc*`) in the other events.
+    fn field(&self, field: &str, category: &str) -> String {
+        if let Some(mapped) = self
+            .mappings
+            .as_ref()
+            .and_then(|mappings| mappings.get(field))
+        {
+            return mapped.to_string();
+        }
+        let actor = match (category, field) {
+            ("process_creation", _) => None,
+            (_, "Image") => Some("SrcProcImagePath"),
+            (_, "CommandLine") => Some("SrcProcCmdLine"),
+            (_, "ProcessId") => Some("SrcProcPID"),
+            (_, "User") => Some("SrcProcUser"),
+            (_, "ParentImage") => Some("SrcProcParentImagePath"),
+            _ => None,
+        };
+        let field = match actor {
+            Some(actor) => actor.to_string(),
+            None => self.get_default_field_name(Some(field.to_string())),
+        };
+        if self.power_query() {
+            power_query_field(&field)
+        } else {
+            field
+        }
+    }
+
+    /// The conditions on the endpoint OS and event type of the logsource.
+    fn source_conditions(&self, logsource: &SigmaRuleLogSource) -> Vec<String> {
+        let (os_field, type_field, in_list) = if self.power_query() {
+            ("endpoint.os", "event.type", "in")
+        } else {
+            ("EndpointOS", "EventType", "In")
+        };
+        let mut conditions = vec![];
+        if let Some(os @ ("windows" | "linux" | "macos")) = logsource.product.as_deref() {
+            conditions.push(format!("{os_field} = \"{os}\""));
+        }
+        let event_types: &[&str] = match logsource.category.as_deref().unwrap_or_default() {
+            "process_creation" => &["Process Creation"],
+            "network_connection" => &["IP Connect"],
+            "file_event" => &["File Creation"],
+            "file_change" => &["File Modification"],
+            "file_delete" => &["File Deletion"],
+            "file_rename" => &["File Rename"],
+            "registry_add" => &["Registry Key Create"],
+            "registry_set" => &["Registry Value Modified"],
+            "registry_delete" => &["Registry Key Delete", "Registry Value Delete"],
+            "registry_event" => &[
+                "Registry Key Create",
+                "Registry Key Delete",
+                "Registry Value Create",
+                "Registry Value Modified",
+                "Registry Value Delete",
+            ],
+            "image_load" => &["Module Load"],
+            "driver_load" => &["Driver Load"],
+            "dns_query" => &["DNS Resolved", "DNS Unresolved"],
+            _ => &[],
+        };
+        match event_types {
+            [] => {}
+            [event_type] => conditions.push(format!("{type_field} = \"{event_type}\"")),
+            event_types => conditions.push(format!(
+                "{type_field} {in_list} ({})",
+                event_types
+                    .iter()
+                    .map(|event_type| format!("\"{event_type}\""))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )),
+        }
+        conditions
+    }
+
+    fn build_search(&self, rule: &SigmaRule) -> String {
+        let mut conditions = self.source_conditions(&rule.logsource);
+        conditions.push(self.build_query(rule));
+        let and = if self.power_query() { " and " } else { " AND " };
+        conditions
+            .into_iter()
+            .filter(|condition| !condition.is_empty())
+            .collect::<Vec<String>>()
+            .join(and)
+    }
+
+    fn build_power_query(&self, rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let mut query = self.build_search(rule);
+        if let Some(aggregation) = aggregation {
+            let category = rule.logsource.category.as_deref().unwrap_or_default();
+            let function = match (aggregation.function.as_ref(), &aggregation.field) {
+                ("count", Some(field)) => {
+                    format!("estimate_distinct({})", self.field(field, category))
+                }
+                ("count", None) => "count()".to_string(),
+                (function, Some(field)) => format!("{function}({})", self.field(field, category)),
+                (function, None) => format!("{function}()"),
+            };
+            query.push_str(&format!("\n| group event_count = {function}"));
+            if let Some(group_by) = &aggregation.group_by {
+                query.push_str(&format!(" by {}", self.field(group_by, category)));
+            }
+            let operator = match aggregation.operator.as_ref() {
+                "=" => "==",
+                operator => operator,
+            };
+            query.push_str(&format!(
+                "\n| filter event_count {operator} {}",
+                aggregation.value
+            ));
+        }
+        query
+    }
+
+    fn build_star_rule(&self, sigma_rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        if aggregation.is_some() {
+            eprintln!(
+                "STAR rules match single events, the aggregation of '{}' is dropped",
+                sigma_rule.title
+            );
+        }
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let severity = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL | AlertSeverity::LOW => "Low",
+            AlertSeverity::MEDIUM => "Medium",
+            AlertSeverity::HIGH => "High",
+            AlertSeverity::CRITICAL => "Critical",
+        };
+        let star_rule = json!({
+            "data": {
+                "name": sigma_rule.title,
+                "description": sigma_rule.description.as_deref().unwrap_or_default(),
+                "severity": severity,
+                "status": "Active",
+                "queryType": "events",
+                "queryLang": "1.0",
+                "s1ql": self.build_search(sigma_rule),
+                "expirationMode": "Permanent",
+                "treatAsThreat": "UNDEFINED",
+                "networkQuarantine": false,
+            }
+        });
+        serde_json::to_string_pretty(&star_rule).unwrap_or_default()
+    }
+}
+
+impl BackEnd for SentinelOneBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        match self.output {
+            SentinelOneOutput::Query => {
+                let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+                    Ok(aggregation) => aggregation,
+                    Err(e) => {
+                        eprintln!("{:?}", e);
+                        exit(1);
+                    }
+                };
+                if aggregation.is_some() {
+                    eprintln!(
+                        "Deep Visibility queries can't aggregate, use PowerQuery for '{}'",
+                        sigma_rule.title
+                    );
+                }
+                self.build_search(&sigma_rule)
+            }
+            SentinelOneOutput::PowerQuery => self.build_power_query(&sigma_rule),
+            SentinelOneOutput::StarRule => self.build_star_rule(&sigma_rule),
+        }
+    }
+}
+
+impl RequiresMappings for SentinelOneBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        let mappings = HashMap::from([
+            ("ProcessId", "TgtProcPID"),
+            ("Image", "TgtProcImagePath"),
+            ("OriginalFileName", "TgtProcName"),
+            ("Description", "TgtProcDisplayName"),
+            ("Product", "TgtProcDisplayName"),
+            ("Company", "TgtProcPublisher"),
+            ("CommandLine", "TgtProcCmdLine"),
+            ("User", "TgtProcUser"),
+            ("IntegrityLevel", "TgtProcIntegrityLevel"),
+            ("md5", "TgtProcImageMd5"),
+            ("sha1", "TgtProcImageSha1"),
+            ("sha256", "TgtProcImageSha256"),
+            ("ParentProcessId", "SrcProcPID"),
+            ("ParentImage", "SrcProcImagePath"),
+            ("ParentCommandLine", "SrcProcCmdLine"),
+            ("ParentProcessName", "SrcProcName"),
+            ("ParentUser", "SrcProcUser"),
+            ("ProcessName", "SrcProcName"),
+            ("TargetFilename", "TgtFilePath"),
+            ("SourceIp", "SrcIP"),
+            ("SourcePort", "SrcPort"),
+            ("DestinationIp", "DstIP"),
+            ("DestinationPort", "DstPort"),
+            ("DestinationHostname", "Url"),
+            ("TargetObject", "RegistryKeyPath"),
+            ("Details", "RegistryValue"),
+            ("ImageLoaded", "ModulePath"),
+            ("QueryName", "DnsRequest"),
+            ("Computer", "EndpointName"),
+            ("ComputerName", "EndpointName"),
+        ]);
+        let field = args.unwrap_or_default();
+        match mappings.get(field.as_str()) {
+            Some(mapping) => mapping.to_string(),
+            _ => field,
+        }
+    }
+}
+
+impl QueryBuilder for SentinelOneBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        let power_query = self.power_query();
+        let keywords = if power_query {
+            ("and", "or", "not")
+        } else {
+            ("AND", "OR", "NOT")
+        };
+        let category = rule.logsource.category.as_deref().unwrap_or_default();
+        render_condition(rule, keywords, &|field, operator| {
+            let field = self.field(field, category);
+            if power_query {
+                power_query_predicate(&field, operator)
+            } else {
+                s1ql_predicate(&field, operator)
+            }
+        })
+    }
+}
+
+/// Deep Visibility predicate, the text comparisons being case insensitive.
+fn s1ql_predicate(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => {
+            format!("{field} In AnyCase ({})", quoted_list(values))
+        }
+        RuleOperator::Equals(SiemField::Text(value)) => {
+            format!("{field} In AnyCase (\"{}\")", escape_string(value))
+        }
+        RuleOperator::Equals(value) => format!("{field} = {value}"),
+        RuleOperator::StartsWith(pattern) => {
+            format!("{field} StartsWithCIS \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::EndsWith(pattern) => {
+            format!("{field} EndsWithCIS \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::Contains(pattern) => {
+            format!("{field} ContainsCIS \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::Matches(regex) => {
+            format!("{field} RegExp \"{}\"", escape_string(regex.as_str()))
+        }
+        RuleOperator::GT(value) => format!("{field} > {value}"),
+        RuleOperator::GTE(value) => format!("{field} >= {value}"),
+        RuleOperator::LT(value) => format!("{field} < {value}"),
+        RuleOperator::LTE(value) => format!("{field} <= {value}"),
+        RuleOperator::SameNet((SiemIp::V4(ip), net)) if net % 8 == 0 => {
+            // networks ending on an octet boundary are a prefix of their addresses
+            let address = ipv4_to_str(*ip);
+            let prefix: Vec<&str> = address.split('.').take((*net / 8) as usize).collect();
+            match prefix.len() {
+                0 => format!("{field} IS NOT EMPTY"),
+                4 => format!("{field} = \"{address}\""),
+                _ => format!("{field} StartsWith \"{}.\"", prefix.join(".")),
+            }
+        }
+        RuleOperator::SameNet((ip, net)) => {
+            eprintln!(
+                "Deep Visibility has no network test, the condition on '{field}' matching '{}/{net}' is dropped",
+                ip_to_str(ip)
+            );
+            String::new()
+        }
+        RuleOperator::IsNull(_) => format!("{field} IS EMPTY"),
+        RuleOperator::Any(operators) => match text_values(operators) {
+            Some(values) => format!("{field} In AnyCase ({})", quoted_list(&values)),
+            None => join_predicates(
+                operators
+                    .iter()
+                    .map(|operator| s1ql_predicate(field, operator))
+                    .collect(),
+                "OR",
+            ),
+        },
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| s1ql_predicate(field, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = s1ql_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// PowerQuery predicate, the text comparisons being case insensitive.
+fn power_query_predicate(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => {
+            format!("{field} in:anycase({})", quoted_list(values))
+        }
+        RuleOperator::Equals(SiemField::Text(value)) => {
+            format!("{field} in:anycase(\"{}\")", escape_string(value))
+        }
+        RuleOperator::Equals(value) => format!("{field} = {value}"),
+        RuleOperator::StartsWith(pattern) => format!(
+            "{field} matches \"(?i)^{}\"",
+            escape_string(&regex::escape(pattern))
+        ),
+        RuleOperator::EndsWith(pattern) => format!(
+            "{field} matches \"(?i){}$\"",
+            escape_string(&regex::escape(pattern))
+        ),
+        RuleOperator::Contains(pattern) => {
+            format!("{field} contains:anycase \"{}\"", escape_string(pattern))
+        }
+        RuleOperator::Matches(regex) => {
+            format!("{field} matches \"{}\"", escape_string(regex.as_str()))
+        }
+        RuleOperator::GT(value) => format!("{field} > {value}"),
+        RuleOperator::GTE(value) => format!("{field} >= {value}"),
+        RuleOperator::LT(value) => format!("{field} < {value}"),
+        RuleOperator::LTE(value) => format!("{field} <= {value}"),
+        RuleOperator::SameNet((ip, net)) => {
+            format!("net_incidr({field}, \"{}/{net}\")", ip_to_str(ip))
+        }
+        RuleOperator::IsNull(_) => format!("!({field} = *)"),
+        RuleOperator::Any(operators) => match text_values(operators) {
+            Some(values) => format!("{field} in:anycase({})", quoted_list(&values)),
+            None => join_predicates(
+                operators
+                    .iter()
+                    .map(|operator| power_query_predicate(field, operator))
+                    .collect(),
+                "or",
+            ),
+        },
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| power_query_predicate(field, operator))
+                .collect(),
+            "and",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = power_query_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("not {predicate}")
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// The text form of an IPv4 or IPv6 address.
+fn ip_to_str(ip: &SiemIp) -> String {
+    match ip {
+        SiemIp::V4(ip) => ipv4_to_str(*ip),
+        SiemIp::V6(ip) => ipv6_to_str(*ip),
+    }
+}
+
+/// The values of several text equalities, which are a single list membership.
+fn text_values(operators: &[Box<RuleOperator>]) -> Option<Vec<String>> {
+    let values: Option<Vec<String>> = operators
+        .iter()
+        .map(|operator| match operator.as_ref() {
+            RuleOperator::Equals(SiemField::Text(value)) => Some(value.to_string()),
+            _ => None,
+        })
+        .collect();
+    values.filter(|values| values.len() > 1)
+}
+
+/// The PowerQuery name of a Deep Visibility field.
+fn power_query_field(field: &str) -> String {
+    let field = match field {
+        "EventType" => "event.type",
+        "EndpointOS" => "endpoint.os",
+        "EndpointName" => "endpoint.name",
+        "TgtProcPID" => "tgt.process.pid",
+        "TgtProcImagePath" => "tgt.process.image.path",
+        "TgtProcName" => "tgt.process.name",
+        "TgtProcDisplayName" => "tgt.process.displayName",
+        "TgtProcPublisher" => "tgt.process.publisher",
+        "TgtProcCmdLine" => "tgt.process.cmdline",
+        "TgtProcUser" => "tgt.process.user",
+        "TgtProcIntegrityLevel" => "tgt.process.integrityLevel",
+        "TgtProcImageMd5" => "tgt.process.image.md5",
+        "TgtProcImageSha1" => "tgt.process.image.sha1",
+        "TgtProcImageSha256" => "tgt.process.image.sha256",
+        "SrcProcPID" => "src.process.pid",
+        "SrcProcImagePath" => "src.process.image.path",
+        "SrcProcName" => "src.process.name",
+        "SrcProcCmdLine" => "src.process.cmdline",
+        "SrcProcUser" => "src.process.user",
+        "SrcProcParentImagePath" => "src.process.parent.image.path",
+        "TgtFilePath" => "tgt.file.path",
+        "SrcIP" => "src.ip.address",
+        "SrcPort" => "src.port.number",
+        "DstIP" => "dst.ip.address",
+        "DstPort" => "dst.port.number",
+        "Url" => "url.address",
+        "RegistryKeyPath" => "registry.keyPath",
+        "RegistryValue" => "registry.value",
+        "ModulePath" => "module.path",
+        "DnsRequest" => "event.dns.request",
+        field => field,
+    };
+    field.to_string()
+}
+
+fn quoted_list<T: AsRef<str>>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|value| format!("\"{}\"", escape_string(value.as_ref())))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::sentinel_one::{
+        power_query_predicate, s1ql_predicate, SentinelOneBackend, SentinelOneOutput,
+    };
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+    use crate::prelude::rule::RuleOperator;
+    use crate::prelude::{SiemField, SiemIp};
+
+    const RULE: &str = r#"
+title: Suspicious Shell From Office
+description: Office spawning a shell
+logsource:
+    product: windows
+    category: process_creation
+detection:
+    selection:
+        ParentImage|endswith: \winword.exe
+        Image|endswith:
+            - \cmd.exe
+            - \powershell.exe
+    filter:
+        CommandLine|contains: ScanTool
+    condition: selection and not filter
+level: high
+"#;
+
+    #[test]
+    pub fn test_deep_visibility() {
+        let rule = parse_sigma_rule(RULE).unwrap();
+        assert_eq!(
+            SentinelOneBackend::default().convert_rule(rule),
+            r#"EndpointOS = "windows" AND EventType = "Process Creation" AND (((TgtProcImagePath EndsWithCIS "\\cmd.exe" OR TgtProcImagePath EndsWithCIS "\\powershell.exe") AND SrcProcImagePath EndsWithCIS "\\winword.exe") AND NOT TgtProcCmdLine ContainsCIS "ScanTool")"#
+        );
+    }
+
+    #[test]
+    pub fn test_power_query_and_star_rule() {
+        let rule = parse_sigma_rule(RULE).unwrap();
+        assert_eq!(
+            SentinelOneBackend::new(None, SentinelOneOutput::PowerQuery).convert_rule(rule.clone()),
+            r#"endpoint.os = "windows" and event.type = "Process Creation" and (((tgt.process.image.path matches "(?i)\\\\cmd\\.exe$" or tgt.process.image.path matches "(?i)\\\\powershell\\.exe$") and src.process.image.path matches "(?i)\\\\winword\\.exe$") and not tgt.process.cmdline contains:anycase "ScanTool")"#
+        );
+        let backend = SentinelOneBackend::new(None, SentinelOneOutput::StarRule);
+        let star_rule: serde_json::Value =
+            serde_json::from_str(&backend.convert_rule(rule)).unwrap();
+        assert_eq!(star_rule["data"]["severity"], "High");
+        assert!(star_rule["data"]["s1ql"]
+            .as_str()
+            .unwrap()
+            .starts_with(r#"EndpointOS = "windows" AND EventType = "Process Creation" AND"#));
+    }
+
+    #[test]
+    pub fn test_ranges_and_networks() {
+        let operators = [
+            RuleOperator::GTE(SiemField::U32(1024)),
+            RuleOperator::LTE(SiemField::U32(49151)),
+            RuleOperator::SameNet((SiemIp::V4(0x0a140000), 16)),
+        ];
+        let s1ql: Vec<String> = operators
+            .iter()
+            .map(|operator| s1ql_predicate("DstPort", operator))
+            .collect();
+        assert_eq!(
+            s1ql,
+            [
+                "DstPort >= 1024",
+                "DstPort <= 49151",
+                r#"DstPort StartsWith "10.20.""#
+            ]
+        );
+        let power_query: Vec<String> = operators
+            .iter()
+            .map(|operator| power_query_predicate("dst.port.number", operator))
+            .collect();
+        assert_eq!(
+            power_query,
+            [
+                "dst.port.number >= 1024",
+                "dst.port.number <= 49151",
+                r#"net_incidr(dst.port.number, "10.20.0.0/16")"#
+            ]
+        );
+    }
+}
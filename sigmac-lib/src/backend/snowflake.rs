@@ -1,19 +1,224 @@
+use crate::backend::sql::SqlDialect;
 use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
+use crate::prelude::rule::RuleOperator;
 use crate::prelude::SiemField;
-use crate::sigma::components::rule::sigma::SigmaRule;
-use crate::sigma::components::rule::RuleOperator;
-use crate::sigma::components::rule::SiemRule;
-use crate::utils::parse_condition;
+use crate::sigma::components::rule::sigma::{SigmaRule, SigmaRuleLogSource};
+use crate::utils::{
+    backend_option, join_predicates, logsource_mapping, parse_aggregation, parse_timeframe,
+    timeframe_seconds, upper_snake_case,
+};
 use std::collections::HashMap;
 use std::process::exit;
 
+/// What the Snowflake backend emits for a rule.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SnowflakeOutput {
+    /// The detection query.
+    #[default]
+    Query,
+    /// A `CREATE VIEW` over the detection query.
+    View,
+    /// A scheduled `TASK` inserting the detections into an alerts table.
+    Task,
+}
+
 #[derive(Clone, Debug, Default)]
-pub struct SnowflakeBackend {}
+pub struct SnowflakeBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: SnowflakeOutput,
+}
+
+impl SnowflakeBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: SnowflakeOutput) -> Self {
+        SnowflakeBackend { mappings, output }
+    }
+
+    /// The table holding the events of the logsource, which the `logsource.*` mappings
+    /// can override.
+    fn table(&self, logsource: &SigmaRuleLogSource) -> String {
+        logsource_mapping(self.mappings.as_ref(), logsource)
+            .unwrap_or_else(|| SqlDialect::SNOWFLAKE.table.to_string())
+    }
+
+    /// The SQL expression of a sigma field. With a `variant.root` option the fields are
+    /// paths in that VARIANT column, cast to `cast` (Ex: `raw:winlog:event_data:Image::string`).
+    fn column(&self, field: &str, cast: &str) -> String {
+        let field = self.get_mapping(field.to_string());
+        match backend_option(self.mappings.as_ref(), "variant.root") {
+            Some(root) => {
+                let path: Vec<String> = field.split('.').map(variant_key).collect();
+                format!("{root}:{}::{cast}", path.join(":"))
+            }
+            None => SqlDialect::SNOWFLAKE.quote_identifier(&field),
+        }
+    }
+
+    /// Whether the field holds an array, the `array.fields` option listing the sigma fields
+    /// of the ARRAY columns (comma separated).
+    fn is_array_field(&self, field: &str) -> bool {
+        backend_option(self.mappings.as_ref(), "array.fields")
+            .is_some_and(|fields| fields.split(',').any(|name| name.trim() == field))
+    }
+
+    fn build_predicate(&self, field: &str, operator: &RuleOperator) -> String {
+        let dialect = SqlDialect::SNOWFLAKE;
+        match operator {
+            RuleOperator::Equals(SiemField::Array(values)) if self.is_array_field(field) => {
+                join_predicates(
+                    values
+                        .iter()
+                        .map(|value| {
+                            self.array_contains(
+                                field,
+                                &format!("'{}'", dialect.escape_string(value)),
+                            )
+                        })
+                        .collect(),
+                    "OR",
+                )
+            }
+            RuleOperator::Equals(SiemField::Text(value)) if self.is_array_field(field) => {
+                self.array_contains(field, &format!("'{}'", dialect.escape_string(value)))
+            }
+            RuleOperator::Equals(value) if self.is_array_field(field) => {
+                self.array_contains(field, &value.to_string())
+            }
+            RuleOperator::Equals(SiemField::Array(values)) => self.in_list(field, values),
+            RuleOperator::Equals(SiemField::Text(_)) => {
+                dialect.build_predicate(&self.column(field, "string"), operator)
+            }
+            RuleOperator::Equals(_) => {
+                dialect.build_predicate(&self.column(field, "number"), operator)
+            }
+            RuleOperator::Any(operators) => {
+                // Several text equalities of the same field are a membership in their list
+                let values: Option<Vec<String>> = operators
+                    .iter()
+                    .map(|operator| match operator.as_ref() {
+                        RuleOperator::Equals(SiemField::Text(value)) => Some(value.to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                match values {
+                    Some(values) if values.len() > 1 && !self.is_array_field(field) => {
+                        self.in_list(field, &values)
+                    }
+                    _ => join_predicates(
+                        operators
+                            .iter()
+                            .map(|operator| self.build_predicate(field, operator))
+                            .collect(),
+                        "OR",
+                    ),
+                }
+            }
+            RuleOperator::All(operators) => join_predicates(
+                operators
+                    .iter()
+                    .map(|operator| self.build_predicate(field, operator))
+                    .collect(),
+                "AND",
+            ),
+            RuleOperator::Not(operator) => {
+                let predicate = self.build_predicate(field, operator);
+                if predicate.is_empty() {
+                    predicate
+                } else {
+                    format!("NOT {predicate}")
+                }
+            }
+            _ => dialect.build_predicate(&self.column(field, "string"), operator),
+        }
+    }
+
+    /// Membership of a value, a SQL literal, in the array of an array field.
+    fn array_contains(&self, field: &str, literal: &str) -> String {
+        format!(
+            "ARRAY_CONTAINS({literal}::variant, {})",
+            self.column(field, "array")
+        )
+    }
+
+    /// Case insensitive membership of the field in a list of values.
+    fn in_list<T: AsRef<str>>(&self, field: &str, values: &[T]) -> String {
+        let dialect = SqlDialect::SNOWFLAKE;
+        format!(
+            "LOWER({}) IN ({})",
+            self.column(field, "string"),
+            values
+                .iter()
+                .map(|value| format!(
+                    "'{}'",
+                    dialect.escape_string(&value.as_ref().to_lowercase())
+                ))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+
+    /// The predicate keeping the events of the rule timeframe, so the scheduled runs only
+    /// look at the recent events. The `timestamp.column` option names the event time column.
+    fn time_window(&self, rule: &SigmaRule) -> String {
+        let timestamp = match backend_option(self.mappings.as_ref(), "timestamp.column") {
+            Some(column) => column.to_string(),
+            None => SqlDialect::SNOWFLAKE.quote_identifier("EVENT_TIME"),
+        };
+        let timeframe = rule.detection.timeframe.as_deref().unwrap_or("5m");
+        // tasks run at most every minute, so shorter windows would miss events
+        let (amount, unit) = match parse_timeframe(timeframe) {
+            (amount, 's') if amount < 60 => (1, "MINUTE"),
+            (amount, 's') => (amount, "SECOND"),
+            (amount, 'h') => (amount, "HOUR"),
+            (amount, 'd') => (amount, "DAY"),
+            (amount, _) => (amount, "MINUTE"),
+        };
+        format!("{timestamp} >= DATEADD({unit}, -{amount}, CURRENT_TIMESTAMP())")
+    }
+
+    /// The detection query, restricted to the rule timeframe when `windowed`.
+    fn build_select(&self, rule: &SigmaRule, windowed: bool) -> String {
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let table = self.table(&rule.logsource);
+        let mut filter = self.build_query(rule);
+        if windowed {
+            filter = format!("{} AND ({filter})", self.time_window(rule));
+        }
+        match aggregation {
+            Some(aggregation) => {
+                SqlDialect::SNOWFLAKE.build_aggregation(&aggregation, &table, &filter, &|field| {
+                    self.column(field, "string")
+                })
+            }
+            None => format!("SELECT * FROM {table} WHERE {filter}"),
+        }
+    }
+
+    /// A task running the query on the rule timeframe, the detections being inserted in the
+    /// `task.alerts_table` table by the `task.warehouse` warehouse.
+    fn build_task(&self, rule: &SigmaRule, name: &str, select: &str) -> String {
+        let dialect = SqlDialect::SNOWFLAKE;
+        let warehouse =
+            backend_option(self.mappings.as_ref(), "task.warehouse").unwrap_or("COMPUTE_WH");
+        let alerts_table =
+            backend_option(self.mappings.as_ref(), "task.alerts_table").unwrap_or("SIGMA_ALERTS");
+        format!(
+            "CREATE OR REPLACE TASK {name}\n  WAREHOUSE = {warehouse}\n  SCHEDULE = '{}'\nAS\n  INSERT INTO {alerts_table} (rule_id, rule_title, detected_at, event)\n  SELECT '{}', '{}', CURRENT_TIMESTAMP(), OBJECT_CONSTRUCT(*)\n  FROM ({select});",
+            schedule(rule.detection.timeframe.as_deref().unwrap_or("5m")),
+            dialect.escape_string(rule.id.as_deref().unwrap_or_default()),
+            dialect.escape_string(&rule.title),
+        )
+    }
+}
 
 impl RequiresMappings for SnowflakeBackend {
     fn get_field_map(&self) -> Option<HashMap<String, String>> {
-        None
+        self.mappings.clone()
     }
 
     fn get_default_field_name(&self, args: Option<String>) -> String {
@@ -99,333 +304,152 @@ impl RequiresMappings for SnowflakeBackend {
 
 impl BackEnd for SnowflakeBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        let query = self.build_query(&sigma_rule);
-        format!("SELECT * FROM windows WHERE {query}")
+        let select = self.build_select(&sigma_rule, self.output != SnowflakeOutput::Query);
+        let name = format!("SIGMA_{}", upper_snake_case(&sigma_rule.title));
+        match self.output {
+            SnowflakeOutput::Query => select,
+            SnowflakeOutput::View => format!("CREATE OR REPLACE VIEW {name} AS {select};"),
+            SnowflakeOutput::Task => self.build_task(&sigma_rule, &name, &select),
+        }
     }
 }
 
 impl QueryBuilder for SnowflakeBackend {
     fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
-            Err(e) => {
-                eprintln!("{:?}", e);
-                exit(1);
-            }
-        };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let mapped_cond_name = self.get_mapping(cond_name);
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str
-                            .push_str(format!("{mapped_cond_name} ILIKE '{pattern}%'").as_str());
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str
-                            .push_str(format!("{mapped_cond_name} ILIKE '%{pattern}'").as_str());
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str
-                            .push_str(format!("{mapped_cond_name} ILIKE '%{pattern}%'").as_str());
-                    }
-                    RuleOperator::Equals(field) => match field {
-                        SiemField::Array(elements) => {
-                            let joined_query = elements
-                                .iter()
-                                .map(|val| format!("{mapped_cond_name} = {val}"))
-                                .collect::<Vec<_>>()
-                                .join(" or ");
-                            query_str.push_str(format!("({joined_query})").as_str())
-                        }
-                        SiemField::Text(val) => {
-                            query_str.push_str(format!("{mapped_cond_name} = '{val}'").as_str());
-                        }
-                        _ => {
-                            query_str.push_str(format!("{mapped_cond_name} = {field}").as_str());
-                        }
-                    },
-                    RuleOperator::Any(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{cond_name} = {val}"));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{cond_name} ILIKE '{pattern}%'"));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("{cond_name} ILIKE '%{pattern}'"));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("{cond_name} ILIKE '%{pattern}%'"));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" OR ");
-                        query_str.push_str(format!("({})", joined_sub_queries).as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("{cond_name} = {val}"));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("{cond_name} ILIKE '{pattern}%'"));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("{cond_name} ILIKE '%{pattern}'"));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("{cond_name} ILIKE '%{pattern}%'"));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" AND ");
-                        query_str.push_str(joined_sub_queries.as_str());
-                    }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
-            }
-            let mut condition_query = String::new();
-            condition_query.push_str(format!("({})", conditions_list.join(" AND ")).as_str());
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
-        }
+        SqlDialect::SNOWFLAKE.render_where(rule, &|field, operator| {
+            self.build_predicate(field, operator)
+        })
+    }
+}
 
-        // Check the conditions of this subrule
-        for condition in conditions {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" OR ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" AND ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" NOT ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" or ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" AND ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" AND ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" or ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" or ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" and ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" not ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" or ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" and ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        query_str.clone()
+/// Key of a VARIANT path, double quoted unless it's a plain word.
+fn variant_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        key.to_string()
+    } else {
+        format!("\"{}\"", key.replace('"', "\"\""))
+    }
+}
+
+/// Converts a sigma timeframe (`30s`, `5m`, `1h`, `2d`) into a task schedule, tasks running
+/// at most every minute.
+fn schedule(timeframe: &str) -> String {
+    let minutes = timeframe_seconds(timeframe) / 60;
+    format!("{} MINUTE", minutes.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::snowflake::{SnowflakeBackend, SnowflakeOutput};
+    use crate::backend::{BackEnd, QueryBuilder};
+    use crate::parse_sigma_rule;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_variant_paths() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Suspicious Shell
+logsource:
+    product: windows
+    category: process_creation
+detection:
+    selection:
+        EventID: 1
+        Image|endswith: cmd_x.exe
+        OriginalFileName:
+            - Cmd.Exe
+            - PowerShell.EXE
+    filter:
+        CommandLine|re: (?i)/c [a-z]+
+    condition: selection and not filter
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([("option.variant.root".to_string(), "raw".to_string())]);
+        let backend = SnowflakeBackend::new(Some(mappings), SnowflakeOutput::Query);
+        let query = backend.build_query(&rule);
+        assert!(query.contains("raw:winlog:event_id::number = 1"));
+        assert!(query.contains(r"raw:process:executable::string ILIKE '%cmd\\_x.exe' ESCAPE '\\'"));
+        assert!(query.contains(
+            "LOWER(raw:winlog:event_data:OriginalFileName::string) IN ('cmd.exe', 'powershell.exe')"
+        ));
+        assert!(query.contains("NOT REGEXP_LIKE(raw:winlog:event_data:CommandLine::string, "));
+    }
+
+    #[test]
+    pub fn test_array_fields() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Tagged Hosts
+logsource:
+    product: windows
+detection:
+    selection:
+        Tags:
+            - Domain Controller
+            - Exchange
+        Roles: Web
+    condition: selection
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([
+            ("option.array.fields".to_string(), "Tags, Roles".to_string()),
+            ("Tags".to_string(), "host.tags".to_string()),
+        ]);
+        let query = SnowflakeBackend::new(Some(mappings.clone()), SnowflakeOutput::Query)
+            .build_query(&rule);
+        assert!(query.contains(
+            "(ARRAY_CONTAINS('Domain Controller'::variant, host.tags) OR ARRAY_CONTAINS('Exchange'::variant, host.tags))"
+        ));
+        assert!(query.contains("ARRAY_CONTAINS('Web'::variant, winlog.event_data.Roles)"));
+        let mut mappings = mappings;
+        mappings.insert("option.variant.root".to_string(), "raw".to_string());
+        let query =
+            SnowflakeBackend::new(Some(mappings), SnowflakeOutput::Query).build_query(&rule);
+        assert!(query.contains("ARRAY_CONTAINS('Exchange'::variant, raw:host:tags::array)"));
+    }
+
+    #[test]
+    pub fn test_task() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+id: 5a1b2c3d-0000-4000-8000-000000000001
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 10m
+    condition: selection | count() by IpAddress > 10
+"#,
+        )
+        .unwrap();
+        let mappings = HashMap::from([(
+            "logsource.windows.security".to_string(),
+            "security_events".to_string(),
+        )]);
+        let backend = SnowflakeBackend::new(Some(mappings.clone()), SnowflakeOutput::View);
+        assert_eq!(
+            backend.convert_rule(rule.clone()),
+            "CREATE OR REPLACE VIEW SIGMA_MANY_FAILED_LOGONS AS SELECT IpAddress, COUNT(*) AS event_count FROM security_events WHERE EVENT_TIME >= DATEADD(MINUTE, -10, CURRENT_TIMESTAMP()) AND (winlog.event_id = 4625) GROUP BY IpAddress HAVING COUNT(*) > 10;"
+        );
+        let mappings = HashMap::from([
+            (
+                "logsource.windows.security".to_string(),
+                "security_events".to_string(),
+            ),
+            (
+                "option.timestamp.column".to_string(),
+                "raw:\"@timestamp\"::timestamp_ntz".to_string(),
+            ),
+        ]);
+        let task = SnowflakeBackend::new(Some(mappings), SnowflakeOutput::Task).convert_rule(rule);
+        assert!(task.starts_with("CREATE OR REPLACE TASK SIGMA_MANY_FAILED_LOGONS\n  WAREHOUSE = COMPUTE_WH\n  SCHEDULE = '10 MINUTE'\n"));
+        assert!(task.contains(r#"WHERE raw:"@timestamp"::timestamp_ntz >= DATEADD(MINUTE, -10, CURRENT_TIMESTAMP()) AND (winlog.event_id = 4625) GROUP BY"#));
+        assert!(task.contains("SELECT '5a1b2c3d-0000-4000-8000-000000000001', 'Many Failed Logons', CURRENT_TIMESTAMP(), OBJECT_CONSTRUCT(*)"));
     }
 }
@@ -1,24 +1,522 @@
 use crate::backend::{BackEnd, QueryBuilder};
-use crate::prelude::rule::sigma::SigmaRule;
 use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
-use crate::prelude::SiemField;
+use crate::prelude::rule::sigma::{SigmaRule, SigmaRuleLogSource};
+use crate::prelude::{AlertSeverity, SiemField, SiemIp};
 use crate::sigma::components::rule::RuleOperator;
 use crate::sigma::components::rule::SiemRule;
-use crate::utils::parse_condition;
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{
+    escape_string, join_predicates, parse_aggregation, parse_condition, render_condition,
+    timeframe_seconds,
+};
+use serde_json::json;
 use std::process::exit;
 
+/// The artifacts the Splunk backend is able to produce.
 #[derive(Clone, Debug, Default)]
-pub struct SplunkBackend {}
+pub enum SplunkOutput {
+    /// The bare SPL search.
+    #[default]
+    Query,
+    /// A savedsearches.conf stanza scheduling the search as an alert.
+    SavedSearches,
+    /// A savedsearches.conf stanza of a Splunk ES correlation search raising notable and risk events.
+    CorrelationSearch,
+    /// A tstats search over the Endpoint and Network_Traffic CIM data models.
+    DataModel,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SplunkBackend {
+    output: SplunkOutput,
+    /// The comma separated alert actions of the saved searches (Ex: `email, webhook`).
+    actions: Option<String>,
+}
+
+impl SplunkBackend {
+    pub fn new(output: SplunkOutput, actions: Option<String>) -> Self {
+        SplunkBackend { output, actions }
+    }
+
+    /// The part of the search selecting the events of the logsource. Data model searches select
+    /// the dataset in the tstats command instead.
+    fn search_prefix(&self, logsource: &SigmaRuleLogSource) -> String {
+        if let SplunkOutput::DataModel = self.output {
+            return String::new();
+        }
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let service = logsource.service.as_deref().unwrap_or_default();
+        match (product, service) {
+            ("windows", "sysmon") => {
+                "index=* source=\"XmlWinEventLog:Microsoft-Windows-Sysmon/Operational\" AND "
+                    .to_string()
+            }
+            ("windows", "") => "index=* source=\"WinEventLog:*\" AND ".to_string(),
+            ("windows", service) => {
+                let channel = match service {
+                    "security" => "Security",
+                    "system" => "System",
+                    "application" => "Application",
+                    "powershell" => "Microsoft-Windows-PowerShell/Operational",
+                    "powershell-classic" => "Windows PowerShell",
+                    _ => "*",
+                };
+                format!("index=* source=\"WinEventLog:{channel}\" AND ")
+            }
+            _ => "index=* ".to_string(),
+        }
+    }
+
+    /// Sigma fields are kept as is, except for data model searches where they are renamed to
+    /// the fields of the CIM dataset.
+    fn get_field_name(&self, logsource: &SigmaRuleLogSource, field: String) -> String {
+        if let SplunkOutput::DataModel = self.output {
+            let (_, node) = data_model(logsource);
+            return match data_model_field(node, &field) {
+                Some(mapping) => format!("{node}.{mapping}"),
+                None => {
+                    eprintln!(
+                        "The {node} dataset has no CIM field for '{field}', its condition won't match"
+                    );
+                    format!("{node}.{field}")
+                }
+            };
+        }
+        field
+    }
+
+    /// Renders the detection condition as a search, the fields being renamed for the output.
+    fn build_search(&self, rule: &SigmaRule) -> String {
+        let search = render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            let field = if field.is_empty() {
+                String::new()
+            } else {
+                self.get_field_name(&rule.logsource, field.to_string())
+            };
+            build_predicate(&rule.title, &field, operator)
+        });
+        format!("{}{search}", self.search_prefix(&rule.logsource))
+    }
+
+    /// The statistic computed by the aggregation, `count(field)` counting the distinct values.
+    fn aggregation_function(
+        &self,
+        rule: &SigmaRule,
+        function: &str,
+        field: Option<&str>,
+    ) -> String {
+        match (function, field) {
+            ("count", Some(field)) => {
+                format!(
+                    "dc({})",
+                    self.get_field_name(&rule.logsource, field.to_string())
+                )
+            }
+            (function, Some(field)) => format!(
+                "{function}({})",
+                self.get_field_name(&rule.logsource, field.to_string())
+            ),
+            (_, None) => "count".to_string(),
+        }
+    }
+
+    /// The search of the rule followed by its aggregation, the matching events being counted
+    /// by `stats` and filtered on the threshold.
+    fn build_aggregated_search(&self, rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let search = self.build_search(rule);
+        match aggregation {
+            Some(aggregation) => {
+                let function = self.aggregation_function(
+                    rule,
+                    &aggregation.function,
+                    aggregation.field.as_deref(),
+                );
+                let by = match &aggregation.group_by {
+                    Some(group_by) => {
+                        format!(
+                            " by {}",
+                            self.get_field_name(&rule.logsource, group_by.to_string())
+                        )
+                    }
+                    None => String::new(),
+                };
+                format!(
+                    "{search} | stats {function} as count{by} | where count {} {}",
+                    aggregation.operator, aggregation.value
+                )
+            }
+            None => search,
+        }
+    }
+
+    fn build_data_model_search(&self, sigma_rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&sigma_rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let (model, node) = data_model(&sigma_rule.logsource);
+        if let Some(aggregation) = aggregation {
+            let function = self.aggregation_function(
+                sigma_rule,
+                &aggregation.function,
+                aggregation.field.as_deref(),
+            );
+            let by = match &aggregation.group_by {
+                Some(group_by) => format!(
+                    " by {}",
+                    self.get_field_name(&sigma_rule.logsource, group_by.to_string())
+                ),
+                None => String::new(),
+            };
+            return format!(
+                "| tstats summariesonly=true {function} as count min(_time) as firstTime max(_time) as lastTime from datamodel={model}.{node} where {}{by} | where count {} {}",
+                self.build_search(sigma_rule),
+                aggregation.operator,
+                aggregation.value
+            );
+        }
+        let by_fields = match node {
+            "Processes" => vec![
+                "dest",
+                "user",
+                "parent_process_name",
+                "process_name",
+                "process",
+            ],
+            "Filesystem" => vec!["dest", "user", "file_path", "file_name"],
+            "Registry" => vec!["dest", "user", "registry_path", "registry_value_data"],
+            _ => vec!["src", "dest", "dest_port", "transport", "app"],
+        };
+        format!(
+            "| tstats summariesonly=true count min(_time) as firstTime max(_time) as lastTime from datamodel={model}.{node} where {} by {}",
+            self.build_search(sigma_rule),
+            by_fields
+                .iter()
+                .map(|field| format!("{node}.{field}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    }
+
+    /// Builds the savedsearches.conf settings scheduling the search over the last 10 minutes of
+    /// events (or the rule timeframe), alerting as soon as one result matches.
+    fn saved_search_settings(&self, sigma_rule: &SigmaRule) -> Vec<(String, String)> {
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let severity = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => "2",
+            AlertSeverity::LOW => "3",
+            AlertSeverity::MEDIUM => "4",
+            AlertSeverity::HIGH => "5",
+            AlertSeverity::CRITICAL => "6",
+        };
+        let description = sigma_rule
+            .description
+            .as_ref()
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+        let (cron_schedule, earliest_time) = search_schedule(sigma_rule);
+        vec![
+            (
+                "search".to_string(),
+                self.build_aggregated_search(sigma_rule),
+            ),
+            ("description".to_string(), description),
+            ("cron_schedule".to_string(), cron_schedule),
+            ("dispatch.earliest_time".to_string(), earliest_time),
+            ("dispatch.latest_time".to_string(), "now".to_string()),
+            ("enableSched".to_string(), "1".to_string()),
+            ("counttype".to_string(), "number of events".to_string()),
+            ("relation".to_string(), "greater than".to_string()),
+            ("quantity".to_string(), "0".to_string()),
+            ("alert.severity".to_string(), severity.to_string()),
+            ("alert.track".to_string(), "1".to_string()),
+            ("alert.suppress".to_string(), "0".to_string()),
+            ("alert.digest_mode".to_string(), "1".to_string()),
+        ]
+    }
+
+    /// The configured alert actions, the triggered alerts being listed by Splunk otherwise.
+    fn build_saved_search(&self, sigma_rule: &SigmaRule) -> String {
+        let mut settings = self.saved_search_settings(sigma_rule);
+        if let Some(actions) = &self.actions {
+            settings.push(("actions".to_string(), actions.to_string()));
+        }
+        format_stanza(sigma_rule, settings)
+    }
+
+    fn build_correlation_search(&self, sigma_rule: &SigmaRule) -> String {
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let (severity, risk_score) = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => ("informational", 10),
+            AlertSeverity::LOW => ("low", 25),
+            AlertSeverity::MEDIUM => ("medium", 50),
+            AlertSeverity::HIGH => ("high", 75),
+            AlertSeverity::CRITICAL => ("critical", 100),
+        };
+        let (security_domain, risk_object, risk_object_type) =
+            match data_model(&sigma_rule.logsource) {
+                (_, "All_Traffic") => ("network", "src", "system"),
+                _ => ("endpoint", "dest", "system"),
+            };
+        let techniques: Vec<String> = siem_rule
+            .mitre
+            .techniques
+            .iter()
+            .map(|technique| technique.to_string())
+            .collect();
+        let annotations = json!({ "mitre_attack": techniques });
+        let risk = json!([{
+            "risk_object_field": risk_object,
+            "risk_object_type": risk_object_type,
+            "risk_score": risk_score
+        }]);
+        let description = sigma_rule
+            .description
+            .as_ref()
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+        let mut settings = self.saved_search_settings(sigma_rule);
+        settings.extend(vec![
+            (
+                "actions".to_string(),
+                match &self.actions {
+                    Some(actions) => format!("notable, risk, {actions}"),
+                    None => "notable, risk".to_string(),
+                },
+            ),
+            (
+                "action.correlationsearch.enabled".to_string(),
+                "1".to_string(),
+            ),
+            (
+                "action.correlationsearch.label".to_string(),
+                sigma_rule.title.to_string(),
+            ),
+            (
+                "action.correlationsearch.annotations".to_string(),
+                annotations.to_string(),
+            ),
+            ("action.notable".to_string(), "1".to_string()),
+            (
+                "action.notable.param.rule_title".to_string(),
+                sigma_rule.title.to_string(),
+            ),
+            (
+                "action.notable.param.rule_description".to_string(),
+                description,
+            ),
+            (
+                "action.notable.param.security_domain".to_string(),
+                security_domain.to_string(),
+            ),
+            (
+                "action.notable.param.severity".to_string(),
+                severity.to_string(),
+            ),
+            ("action.risk".to_string(), "1".to_string()),
+            ("action.risk.param._risk".to_string(), risk.to_string()),
+            (
+                "action.risk.param._risk_score".to_string(),
+                risk_score.to_string(),
+            ),
+            (
+                "action.risk.param._risk_object".to_string(),
+                risk_object.to_string(),
+            ),
+            (
+                "action.risk.param._risk_object_type".to_string(),
+                risk_object_type.to_string(),
+            ),
+        ]);
+        format_stanza(sigma_rule, settings)
+    }
+}
+
+/// Renders the condition of a single field as a search term, the keywords being searched in
+/// the raw events.
+fn build_predicate(title: &str, field: &str, operator: &RuleOperator) -> String {
+    let term = |value: String| {
+        if field.is_empty() {
+            format!("\"{}\"", escape_string(&value))
+        } else {
+            format!("{field}=\"{}\"", escape_string(&value))
+        }
+    };
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => join_predicates(
+            values.iter().map(|value| term(value.to_string())).collect(),
+            "OR",
+        ),
+        RuleOperator::Equals(SiemField::Text(value)) => term(value.to_string()),
+        RuleOperator::Equals(value) if field.is_empty() => term(value.to_string()),
+        RuleOperator::Equals(value) => format!("{field}={value}"),
+        RuleOperator::StartsWith(pattern) => term(format!("{pattern}*")),
+        RuleOperator::EndsWith(pattern) => term(format!("*{pattern}")),
+        RuleOperator::Contains(pattern) => term(format!("*{pattern}*")),
+        RuleOperator::GT(value) => format!("{field}>{value}"),
+        RuleOperator::GTE(value) => format!("{field}>={value}"),
+        RuleOperator::LT(value) => format!("{field}<{value}"),
+        RuleOperator::LTE(value) => format!("{field}<={value}"),
+        // Searches match the fields against CIDR networks
+        RuleOperator::SameNet((ip, net)) => match ip {
+            SiemIp::V4(ip) => term(format!("{}/{net}", ipv4_to_str(*ip))),
+            SiemIp::V6(ip) => term(format!("{}/{net}", ipv6_to_str(*ip))),
+        },
+        RuleOperator::IsNull(_) => format!("NOT {field}=*"),
+        RuleOperator::Matches(_) => {
+            eprintln!("'{title}': searches do not match regular expressions, the condition on {field} is dropped");
+            String::new()
+        }
+        RuleOperator::Any(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(title, field, operator))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(title, field, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(title, field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// Writes the settings into a savedsearches.conf stanza named after the rule.
+fn format_stanza(sigma_rule: &SigmaRule, settings: Vec<(String, String)>) -> String {
+    let mut stanza = vec![format!(
+        "[SIGMA - {}]",
+        sigma_rule.title.replace(['[', ']'], "")
+    )];
+    for (key, value) in settings {
+        // Values are kept on a single line, as new lines would need to be escaped
+        let value = value
+            .lines()
+            .map(|line| line.trim())
+            .collect::<Vec<_>>()
+            .join(" ");
+        stanza.push(format!("{key} = {value}").trim_end().to_string());
+    }
+    stanza.join("\n")
+}
+
+/// The CIM data model and dataset holding the events of the logsource.
+fn data_model(logsource: &SigmaRuleLogSource) -> (&'static str, &'static str) {
+    match logsource.category.as_deref().unwrap_or_default() {
+        "file_event" | "file_change" | "file_delete" | "file_rename" | "file_access" => {
+            ("Endpoint", "Filesystem")
+        }
+        "registry_event" | "registry_add" | "registry_delete" | "registry_set"
+        | "registry_rename" => ("Endpoint", "Registry"),
+        "network_connection" | "firewall" => ("Network_Traffic", "All_Traffic"),
+        _ => ("Endpoint", "Processes"),
+    }
+}
+
+/// The cron schedule and earliest time of the search, run once per timeframe over the events
+/// of the timeframe. Timeframes cron can't divide are searched a bit more often, so that no
+/// events are missed.
+fn search_schedule(sigma_rule: &SigmaRule) -> (String, String) {
+    let minutes = timeframe_seconds(sigma_rule.detection.timeframe.as_deref().unwrap_or("10m"))
+        .div_ceil(60)
+        .max(1);
+    // the largest step of the cron field, up to `max`, dividing its range
+    let step = |max: u64, range: u64| {
+        (1..=max)
+            .rev()
+            .find(|step| range.is_multiple_of(*step))
+            .unwrap_or(1)
+    };
+    let every = |step: u64| match step {
+        1 => "*".to_string(),
+        step => format!("*/{step}"),
+    };
+    let cron_schedule = if minutes < 60 {
+        format!("{} * * * *", every(step(minutes, 60)))
+    } else if minutes < 1_440 {
+        format!("0 {} * * *", every(step(minutes / 60, 24)))
+    } else {
+        format!("0 0 {} * *", every(minutes / 1_440))
+    };
+    let earliest_time = if minutes.is_multiple_of(1_440) {
+        format!("-{}d", minutes / 1_440)
+    } else if minutes.is_multiple_of(60) {
+        format!("-{}h", minutes / 60)
+    } else {
+        format!("-{minutes}m")
+    };
+    (cron_schedule, earliest_time)
+}
+
+/// Sysmon to CIM field mappings for each of the supported data model datasets.
+fn data_model_field(node: &str, field: &str) -> Option<&'static str> {
+    let mapping = match (node, field) {
+        ("Processes", "Image") => "process_path",
+        ("Processes", "CommandLine") => "process",
+        ("Processes", "OriginalFileName") => "original_file_name",
+        ("Processes", "ParentImage") => "parent_process_path",
+        ("Processes", "ParentCommandLine") => "parent_process",
+        ("Processes", "ProcessId") => "process_id",
+        ("Processes", "ParentProcessId") => "parent_process_id",
+        ("Processes", "IntegrityLevel") => "process_integrity_level",
+        ("Processes", "CurrentDirectory") => "process_current_directory",
+        ("Processes", "Hashes") => "process_hash",
+        ("Filesystem", "TargetFilename") => "file_path",
+        ("Filesystem", "Image") => "process_path",
+        ("Registry", "TargetObject") => "registry_path",
+        ("Registry", "Details") => "registry_value_data",
+        ("Registry", "EventType") => "action",
+        ("Registry", "Image") => "process_path",
+        ("All_Traffic", "DestinationIp") => "dest_ip",
+        ("All_Traffic", "DestinationPort") => "dest_port",
+        ("All_Traffic", "DestinationHostname") => "dest",
+        ("All_Traffic", "SourceIp") => "src_ip",
+        ("All_Traffic", "SourcePort") => "src_port",
+        ("All_Traffic", "SourceHostname") => "src",
+        ("All_Traffic", "Protocol") => "transport",
+        ("All_Traffic", "Image") => "app",
+        (_, "User") => "user",
+        (_, "Computer") | (_, "ComputerName") => "dest",
+        _ => return None,
+    };
+    Some(mapping)
+}
 
 impl BackEnd for SplunkBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        self.build_query(&sigma_rule).clone()
+        match self.output {
+            SplunkOutput::Query => self.build_query(&sigma_rule),
+            SplunkOutput::SavedSearches => self.build_saved_search(&sigma_rule),
+            SplunkOutput::CorrelationSearch => self.build_correlation_search(&sigma_rule),
+            SplunkOutput::DataModel => self.build_data_model_search(&sigma_rule),
+        }
     }
 }
 
 impl QueryBuilder for SplunkBackend {
     fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = format!("index=* source=\"WinEventLog:*\" AND ");
+        let mut query_str = self.search_prefix(&rule.logsource);
         let siem_rule: SiemRule = rule.clone().into();
         let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
             Ok(conds) => conds,
@@ -35,6 +533,7 @@ impl QueryBuilder for SplunkBackend {
                 let mut query_str = String::new();
                 let (cond_name, cond_oper) =
                     (condition.field.to_string(), condition.operator.clone());
+                let cond_name = self.get_field_name(&rule.logsource, cond_name);
                 let regex;
                 match cond_oper.clone() {
                     RuleOperator::StartsWith(mut pattern) => {
@@ -320,8 +819,8 @@ impl QueryBuilder for SplunkBackend {
 
 #[cfg(test)]
 mod tests {
-    use crate::backend::splunk::SplunkBackend;
-    use crate::backend::QueryBuilder;
+    use crate::backend::splunk::{SplunkBackend, SplunkOutput};
+    use crate::backend::{BackEnd, QueryBuilder};
     use crate::parse_sigma_rule;
     use std::env::current_dir;
 
@@ -340,4 +839,58 @@ mod tests {
         println!("{}", query);
         assert_eq!(query.as_str(), expected_query_str);
     }
+
+    #[test]
+    pub fn test_saved_searches() {
+        let rules = vec![
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap(),
+            parse_sigma_rule(include_str!("../sigma/components/rule/c2_sigma_rule.yml")).unwrap(),
+        ];
+        let backend = SplunkBackend::new(SplunkOutput::SavedSearches, None);
+        let conf = backend.convert_rules(rules);
+        assert!(conf.starts_with(
+            "[SIGMA - Suspicious 7zip Subprocess]\nsearch = index=* source=\"WinEventLog:*\" AND "
+        ));
+        assert!(conf.contains("\n\n[SIGMA - Equation Group C2 Communication]\n"));
+        assert!(conf.contains("\nalert.severity = 5\n"));
+    }
+
+    #[test]
+    pub fn test_data_model() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let backend = SplunkBackend::new(SplunkOutput::DataModel, None);
+        let query = backend.convert_rule(rule);
+        assert!(query.starts_with("| tstats summariesonly=true count min(_time) as firstTime max(_time) as lastTime from datamodel=Endpoint.Processes where (((Processes.process_path=\"*\\\\cmd.exe\""));
+    }
+
+    #[test]
+    pub fn test_correlation_search_aggregation() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Many Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    filter:
+        TargetUserName|endswith: $
+    timeframe: 1h
+    condition: (selection and not filter) | count(TargetUserName) by IpAddress > 10
+level: high
+"#,
+        )
+        .unwrap();
+        let backend =
+            SplunkBackend::new(SplunkOutput::CorrelationSearch, Some("email".to_string()));
+        let conf = backend.convert_rule(rule.clone());
+        assert!(conf.contains("\nsearch = index=* source=\"WinEventLog:Security\" AND (EventID=4625 AND NOT TargetUserName=\"*$\") | stats dc(TargetUserName) as count by IpAddress | where count > 10\n"));
+        assert!(conf.contains("\ncron_schedule = 0 * * * *\n"));
+        assert!(conf.contains("\ndispatch.earliest_time = -1h\n"));
+        assert!(conf.contains("\nactions = notable, risk, email\n"));
+        let conf = SplunkBackend::new(SplunkOutput::SavedSearches, None).convert_rule(rule);
+        assert!(!conf.contains("actions ="));
+    }
 }
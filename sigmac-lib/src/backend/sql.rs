@@ -1,381 +1,574 @@
 use crate::backend::{BackEnd, QueryBuilder};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
-use crate::prelude::SiemField;
-use crate::sigma::components::rule::sigma::SigmaRule;
+use crate::prelude::rule::sigma::{ConditionAggregation, SigmaRule};
+use crate::prelude::{SiemField, SiemIp};
 use crate::sigma::components::rule::RuleOperator;
-use crate::sigma::components::rule::SiemRule;
-use crate::utils::parse_condition;
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{join_predicates, parse_aggregation, render_condition};
 use std::process::exit;
 
-#[derive(Clone, Debug, Default)]
-pub struct SQLBackend {}
+/// How a dialect compares text without minding the case.
+#[derive(Clone, Copy, Debug)]
+pub enum CaseInsensitiveMatch {
+    /// `field ILIKE 'value'`
+    Ilike,
+    /// `LOWER(field) LIKE 'value'`, the function name being given.
+    LowerLike(&'static str),
+    /// `field LIKE 'value'`, LIKE being already case insensitive (Ex: osquery).
+    Like,
+}
 
-impl BackEnd for SQLBackend {
-    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        let query = self.build_query(&sigma_rule);
-        format!("SELECT * FROM eventlog WHERE {query}")
-    }
+/// Describes the syntax differences between the SQL flavours, so a single renderer
+/// can build the queries of all the SQL based backends.
+#[derive(Clone, Copy, Debug)]
+pub struct SqlDialect {
+    /// Character quoting the identifiers which aren't plain words.
+    pub identifier_quote: char,
+    /// Dotted field names are paths (Ex: Snowflake VARIANT columns) and are kept unquoted.
+    pub dotted_paths: bool,
+    /// Unquoted identifiers get lowercased, so the mixed case ones have to be quoted.
+    pub quote_mixed_case: bool,
+    pub case_insensitive: CaseInsensitiveMatch,
+    /// Whether the LIKE expressions need an explicit `ESCAPE '\'` clause.
+    pub like_escape: bool,
+    /// Whether a backslash is an escape character in string literals.
+    pub backslash_escapes: bool,
+    /// How a single quote is written inside a string literal.
+    pub quote_escape: &'static str,
+    /// Regex test, `{field}` and `{pattern}` being replaced.
+    pub regex: &'static str,
+    /// Network test, `{field}`, `{cidr}`, `{network}` (address), `{prefix}` (length), `{start}`
+    /// and `{end}` (IPv4 range as integers) being replaced. Dialects without one match the IPv4
+    /// networks on octet boundaries with LIKE.
+    pub cidr: Option<&'static str>,
+    /// Table the events are selected from.
+    pub table: &'static str,
 }
 
-impl QueryBuilder for SQLBackend {
-    fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
-            Err(e) => {
-                eprintln!("{:?}", e);
-                exit(1);
-            }
+impl SqlDialect {
+    pub const SQL: SqlDialect = SqlDialect {
+        identifier_quote: '"',
+        dotted_paths: false,
+        quote_mixed_case: false,
+        case_insensitive: CaseInsensitiveMatch::LowerLike("LOWER"),
+        like_escape: true,
+        backslash_escapes: false,
+        quote_escape: "''",
+        regex: "{field} REGEXP '{pattern}'",
+        cidr: None,
+        table: "eventlog",
+    };
+    pub const SQLITE: SqlDialect = SqlDialect {
+        identifier_quote: '"',
+        dotted_paths: false,
+        quote_mixed_case: false,
+        case_insensitive: CaseInsensitiveMatch::LowerLike("LOWER"),
+        like_escape: true,
+        backslash_escapes: false,
+        quote_escape: "''",
+        regex: "{field} REGEXP '{pattern}'",
+        cidr: None,
+        table: "eventlog",
+    };
+    pub const POSTGRESQL: SqlDialect = SqlDialect {
+        identifier_quote: '"',
+        dotted_paths: false,
+        quote_mixed_case: true,
+        case_insensitive: CaseInsensitiveMatch::Ilike,
+        like_escape: true,
+        backslash_escapes: false,
+        quote_escape: "''",
+        regex: "{field} ~ '{pattern}'",
+        cidr: Some("{field}::inet <<= '{cidr}'::inet"),
+        table: "eventlog",
+    };
+    pub const MYSQL: SqlDialect = SqlDialect {
+        identifier_quote: '`',
+        dotted_paths: false,
+        quote_mixed_case: false,
+        case_insensitive: CaseInsensitiveMatch::LowerLike("LOWER"),
+        like_escape: true,
+        backslash_escapes: true,
+        quote_escape: "''",
+        regex: "{field} REGEXP '{pattern}'",
+        cidr: Some("INET_ATON({field}) BETWEEN {start} AND {end}"),
+        table: "eventlog",
+    };
+    pub const CLICKHOUSE: SqlDialect = SqlDialect {
+        identifier_quote: '`',
+        dotted_paths: false,
+        quote_mixed_case: false,
+        case_insensitive: CaseInsensitiveMatch::Ilike,
+        like_escape: false,
+        backslash_escapes: true,
+        quote_escape: "''",
+        regex: "match({field}, '{pattern}')",
+        cidr: Some("isIPAddressInRange(toString({field}), '{cidr}')"),
+        table: "eventlog",
+    };
+    pub const DUCKDB: SqlDialect = SqlDialect {
+        identifier_quote: '"',
+        dotted_paths: false,
+        quote_mixed_case: false,
+        case_insensitive: CaseInsensitiveMatch::Ilike,
+        like_escape: true,
+        backslash_escapes: false,
+        quote_escape: "''",
+        regex: "regexp_matches({field}, '{pattern}')",
+        cidr: Some("{field}::INET <<= '{cidr}'::INET"),
+        table: "eventlog",
+    };
+    pub const SNOWFLAKE: SqlDialect = SqlDialect {
+        identifier_quote: '"',
+        dotted_paths: true,
+        quote_mixed_case: false,
+        case_insensitive: CaseInsensitiveMatch::Ilike,
+        like_escape: true,
+        backslash_escapes: true,
+        quote_escape: "''",
+        // REGEXP_LIKE matches the whole value
+        regex: "REGEXP_LIKE({field}, '.*({pattern}).*')",
+        cidr: Some("PARSE_IP({field}, 'INET'):ipv4 BETWEEN {start} AND {end}"),
+        table: "windows",
+    };
+    pub const OSQUERY: SqlDialect = SqlDialect {
+        identifier_quote: '"',
+        dotted_paths: false,
+        quote_mixed_case: false,
+        case_insensitive: CaseInsensitiveMatch::Like,
+        like_escape: true,
+        backslash_escapes: false,
+        quote_escape: "''",
+        regex: "{field} REGEXP '{pattern}'",
+        cidr: None,
+        table: "processes",
+    };
+    pub const BIGQUERY: SqlDialect = SqlDialect {
+        identifier_quote: '`',
+        dotted_paths: false,
+        quote_mixed_case: false,
+        // LIKE and = are case sensitive
+        case_insensitive: CaseInsensitiveMatch::LowerLike("LOWER"),
+        // LIKE always escapes with a backslash and has no ESCAPE clause
+        like_escape: false,
+        backslash_escapes: true,
+        quote_escape: "\\'",
+        regex: "REGEXP_CONTAINS({field}, '{pattern}')",
+        cidr: Some(
+            "NET.IP_TRUNC(NET.SAFE_IP_FROM_STRING({field}), {prefix}) = NET.IP_FROM_STRING('{network}')",
+        ),
+        table: "events",
+    };
+    pub const SPARK: SqlDialect = SqlDialect {
+        identifier_quote: '`',
+        dotted_paths: false,
+        quote_mixed_case: false,
+        // LIKE and = are case sensitive, ILIKE only exists since Spark 3.3
+        case_insensitive: CaseInsensitiveMatch::LowerLike("lower"),
+        like_escape: false,
+        backslash_escapes: true,
+        quote_escape: "\\'",
+        regex: "{field} rlike '{pattern}'",
+        cidr: None,
+        table: "events",
+    };
+    pub const KSQL: SqlDialect = SqlDialect {
+        identifier_quote: '`',
+        dotted_paths: false,
+        quote_mixed_case: false,
+        case_insensitive: CaseInsensitiveMatch::LowerLike("LCASE"),
+        like_escape: true,
+        backslash_escapes: false,
+        quote_escape: "''",
+        regex: "REGEXP_EXTRACT('{pattern}', {field}) IS NOT NULL",
+        cidr: None,
+        table: "EVENTS",
+    };
+
+    /// Quotes the identifier unless it's a plain word (or a path in the dialects having them).
+    pub fn quote_identifier(&self, field: &str) -> String {
+        let needs_quotes = field.is_empty()
+            || field.starts_with(|c: char| c.is_ascii_digit())
+            || (self.quote_mixed_case && field.chars().any(|c| c.is_ascii_uppercase()))
+            || !field
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || (self.dotted_paths && c == '.'));
+        if needs_quotes {
+            let quote = self.identifier_quote;
+            format!(
+                "{quote}{}{quote}",
+                field.replace(quote, &format!("{quote}{quote}"))
+            )
+        } else {
+            field.to_string()
+        }
+    }
+
+    /// The body of a single quoted string literal.
+    pub fn escape_string(&self, value: &str) -> String {
+        let value = if self.backslash_escapes {
+            value.replace('\\', "\\\\")
+        } else {
+            value.to_string()
         };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let mapped_cond_name = cond_name;
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!("{mapped_cond_name} = '{mapped_cond_name}' LIKE '{pattern}%'")
-                                .as_str(),
-                        );
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!("{mapped_cond_name} = '{mapped_cond_name}' LIKE '%{pattern}'")
-                                .as_str(),
-                        );
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\");
-                        query_str.push_str(
-                            format!(
-                                "{mapped_cond_name} = {mapped_cond_name} CONTAINS \"{pattern}\""
-                            )
-                            .as_str(),
-                        );
-                    }
-                    RuleOperator::Equals(field) => match field {
-                        SiemField::Array(elements) => {
-                            let joined_query = elements
-                                .iter()
-                                .map(|val| format!("{mapped_cond_name} LIKE \"{val}\" ESCAPE '\\'"))
-                                .collect::<Vec<_>>()
-                                .join(" OR ");
-                            query_str.push_str(format!("({joined_query})").as_str())
-                        }
-                        _ => {
-                            query_str.push_str(
-                                format!("{mapped_cond_name} LIKE \"{field}\" ESCAPE '\\'").as_str(),
-                            );
-                        }
-                    },
-                    RuleOperator::Any(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        let mut eq_sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    eq_sub_queries
-                                        .push(format!("{cond_name} LIKE \"{val}\" ESCAPE '\\'"));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{cond_name} = '{cond_name}' LIKE '{pattern}%'"
-                                    ));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{cond_name} = '{cond_name}' LIKE '%{pattern}'"
-                                    ));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{cond_name} = {cond_name} CONTAINS \"{pattern}\""
-                                    ));
-                                }
-                                _ => {}
-                            }
-                        }
-                        if !eq_sub_queries.is_empty() {
-                            sub_queries.push(eq_sub_queries.join(" OR "));
-                        }
-                        let joined_sub_queries = sub_queries.join(", ");
-                        query_str.push_str(format!("({})", joined_sub_queries).as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let cond_name = mapped_cond_name.clone();
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries
-                                        .push(format!("{cond_name} LIKE \"{val}\" ESCAPE '\\'"));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{cond_name} = '{cond_name}' LIKE '{pattern}%'"
-                                    ));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{cond_name} = '{cond_name}' LIKE '%{pattern}'"
-                                    ));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!(
-                                        "{cond_name} = {cond_name} CONTAINS \"{pattern}\""
-                                    ));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" AND ");
-                        query_str.push_str(format!("({joined_sub_queries})").as_str());
-                    }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
+        value.replace('\'', self.quote_escape)
+    }
+
+    /// Escapes the LIKE wildcards of a literal value, before it gets its `%` anchors.
+    pub fn escape_like(&self, value: &str) -> String {
+        value
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    }
+
+    /// Case insensitive LIKE of a pattern already holding its escaped wildcards.
+    pub fn like(&self, field: &str, pattern: &str) -> String {
+        let escape = match (self.like_escape, self.backslash_escapes) {
+            (false, _) => "",
+            (true, false) => " ESCAPE '\\'",
+            (true, true) => " ESCAPE '\\\\'",
+        };
+        match self.case_insensitive {
+            CaseInsensitiveMatch::Ilike => {
+                format!("{field} ILIKE '{}'{escape}", self.escape_string(pattern))
+            }
+            CaseInsensitiveMatch::LowerLike(lower) => format!(
+                "{lower}({field}) LIKE '{}'{escape}",
+                self.escape_string(&pattern.to_lowercase())
+            ),
+            CaseInsensitiveMatch::Like => {
+                format!("{field} LIKE '{}'{escape}", self.escape_string(pattern))
+            }
+        }
+    }
+
+    /// Case insensitive equality of a text value.
+    pub fn equals(&self, field: &str, value: &str) -> String {
+        match self.case_insensitive {
+            CaseInsensitiveMatch::Ilike | CaseInsensitiveMatch::Like => {
+                self.like(field, &self.escape_like(value))
             }
-            let mut condition_query = String::new();
-            condition_query.push_str(format!("({})", conditions_list.join(" AND ")).as_str());
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
+            CaseInsensitiveMatch::LowerLike(lower) => format!(
+                "{lower}({field}) = '{}'",
+                self.escape_string(&value.to_lowercase())
+            ),
         }
+    }
 
-        // Check the conditions of this subrule
-        for condition in conditions {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let pattern = pattern.replace(' ', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" OR ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let pattern = pattern.replace(' ', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" AND ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" NOT ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let pattern = pattern.replace(' ', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" or ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let pattern = pattern.replace(' ', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" AND ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" AND ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let pattern = pattern.replace(' ', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let pattern = pattern.replace(' ', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let pattern = pattern.replace(' ', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let pattern = pattern.replace(' ', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
+    pub fn regex(&self, field: &str, pattern: &str) -> String {
+        self.regex
+            .replace("{pattern}", &self.escape_string(pattern))
+            .replace("{field}", field)
+    }
+
+    pub fn cidr(&self, field: &str, ip: &SiemIp, net: u8) -> String {
+        match (self.cidr, ip) {
+            (Some(template), SiemIp::V4(ip)) => {
+                let net = net.min(32);
+                let mask = u32::MAX.checked_shl(32 - net as u32).unwrap_or(0);
+                template
+                    .replace("{cidr}", &format!("{}/{net}", ipv4_to_str(*ip)))
+                    .replace("{network}", &ipv4_to_str(ip & mask))
+                    .replace("{prefix}", &net.to_string())
+                    .replace("{start}", &(ip & mask).to_string())
+                    .replace("{end}", &(ip | !mask).to_string())
+                    .replace("{field}", field)
+            }
+            (Some(template), SiemIp::V6(ip))
+                if template.contains("{cidr}") || template.contains("{network}") =>
+            {
+                let net = net.min(128);
+                let mask = u128::MAX.checked_shl(128 - net as u32).unwrap_or(0);
+                template
+                    .replace("{cidr}", &format!("{}/{net}", ipv6_to_str(*ip)))
+                    .replace("{network}", &ipv6_to_str(ip & mask))
+                    .replace("{prefix}", &net.to_string())
+                    .replace("{field}", field)
+            }
+            (_, SiemIp::V4(ip)) => {
+                // Without network functions the addresses are matched on their prefix, the
+                // networks not ending on an octet boundary being split into the networks of
+                // the next boundary they cover (Ex: 172.16.0.0/12 into 172.16. to 172.31.).
+                let net = net.min(32) as u32;
+                if net == 0 {
+                    return format!("{field} IS NOT NULL");
                 }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" or ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let pattern = pattern.replace(' ', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" or ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let pattern = pattern.replace(' ', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" and ").as_str());
+                let octets = net.div_ceil(8);
+                let start = ip & (u32::MAX << (32 - net));
+                let step = 1u64 << (32 - octets * 8);
+                join_predicates(
+                    (0..1u64 << (octets * 8 - net))
+                        .map(|n| {
+                            let address = ipv4_to_str((u64::from(start) + n * step) as u32);
+                            if octets == 4 {
+                                format!("{field} = '{address}'")
                             } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" not ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let pattern = pattern.replace(' ', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" or ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let pattern = pattern.replace(' ', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push_str(
-                                            format!("({})", matches.join(" and ")).as_str(),
-                                        );
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
+                                let prefix: Vec<&str> =
+                                    address.split('.').take(octets as usize).collect();
+                                format!("{field} LIKE '{}.%'", prefix.join("."))
                             }
-                        }
-                    }
+                        })
+                        .collect(),
+                    "OR",
+                )
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Renders the condition of a single field, `field` being already mapped and quoted.
+    pub fn build_predicate(&self, field: &str, operator: &RuleOperator) -> String {
+        match operator {
+            RuleOperator::Equals(SiemField::Array(values)) => join_predicates(
+                values
+                    .iter()
+                    .map(|value| self.equals(field, value))
+                    .collect(),
+                "OR",
+            ),
+            RuleOperator::Equals(SiemField::Text(value)) => self.equals(field, value),
+            RuleOperator::Equals(value) => format!("{field} = {value}"),
+            RuleOperator::StartsWith(pattern) => {
+                self.like(field, &format!("{}%", self.escape_like(pattern)))
+            }
+            RuleOperator::EndsWith(pattern) => {
+                self.like(field, &format!("%{}", self.escape_like(pattern)))
+            }
+            RuleOperator::Contains(pattern) => {
+                self.like(field, &format!("%{}%", self.escape_like(pattern)))
+            }
+            RuleOperator::Matches(regex) => self.regex(field, regex.as_str()),
+            RuleOperator::SameNet((ip, net)) => self.cidr(field, ip, *net),
+            RuleOperator::IsNull(_) => format!("{field} IS NULL"),
+            RuleOperator::Any(operators) => join_predicates(
+                operators
+                    .iter()
+                    .map(|operator| self.build_predicate(field, operator))
+                    .collect(),
+                "OR",
+            ),
+            RuleOperator::All(operators) => join_predicates(
+                operators
+                    .iter()
+                    .map(|operator| self.build_predicate(field, operator))
+                    .collect(),
+                "AND",
+            ),
+            RuleOperator::Not(operator) => {
+                let predicate = self.build_predicate(field, operator);
+                if predicate.is_empty() {
+                    predicate
+                } else {
+                    format!("NOT {predicate}")
                 }
             }
+            _ => String::new(),
         }
-        query_str.clone()
+    }
+
+    /// The WHERE expression of the detection condition, `map_field` giving the column of
+    /// a sigma field.
+    pub fn build_where(&self, rule: &SigmaRule, map_field: &dyn Fn(&str) -> String) -> String {
+        self.render_where(rule, &|field, operator| {
+            self.build_predicate(&self.quote_identifier(&map_field(field)), operator)
+        })
+    }
+
+    /// The WHERE expression of the detection condition, `predicate` rendering the condition
+    /// of a single sigma field. Backends with their own column syntax render them here.
+    pub fn render_where(
+        &self,
+        rule: &SigmaRule,
+        predicate: &dyn Fn(&str, &RuleOperator) -> String,
+    ) -> String {
+        render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            if self.drops_ipv6_network(operator) {
+                eprintln!(
+                    "The SQL dialect has no IPv6 network test, the condition of '{}' on '{field}' is dropped",
+                    rule.title
+                );
+            }
+            predicate(field, operator)
+        })
+    }
+
+    /// Whether the condition holds an IPv6 network the dialect can't test.
+    fn drops_ipv6_network(&self, operator: &RuleOperator) -> bool {
+        match operator {
+            RuleOperator::SameNet((SiemIp::V6(_), _)) => !matches!(
+                self.cidr,
+                Some(template) if template.contains("{cidr}") || template.contains("{network}")
+            ),
+            RuleOperator::Any(operators) | RuleOperator::All(operators) => operators
+                .iter()
+                .any(|operator| self.drops_ipv6_network(operator)),
+            RuleOperator::Not(operator) => self.drops_ipv6_network(operator),
+            _ => false,
+        }
+    }
+
+    /// The SELECT statement of the rule, grouping the events when its condition
+    /// holds an aggregation.
+    pub fn build_select(
+        &self,
+        rule: &SigmaRule,
+        table: &str,
+        map_field: &dyn Fn(&str) -> String,
+    ) -> String {
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let filter = self.build_where(rule, map_field);
+        match aggregation {
+            Some(aggregation) => self.build_aggregation(&aggregation, table, &filter, &|field| {
+                self.quote_identifier(&map_field(field))
+            }),
+            None => format!("SELECT * FROM {table} WHERE {filter}"),
+        }
+    }
+
+    /// The grouping of the events matching `filter`, `column` giving the SQL expression of
+    /// a sigma field.
+    pub fn build_aggregation(
+        &self,
+        aggregation: &ConditionAggregation,
+        table: &str,
+        filter: &str,
+        column: &dyn Fn(&str) -> String,
+    ) -> String {
+        let function = aggregation.function.to_uppercase();
+        let value = match (function.as_str(), &aggregation.field) {
+            ("COUNT", Some(field)) => {
+                format!("COUNT(DISTINCT {})", column(field))
+            }
+            (_, Some(field)) => format!("{function}({})", column(field)),
+            _ => format!("{function}(*)"),
+        };
+        let operator = match aggregation.operator.as_ref() {
+            "==" => "=",
+            operator => operator,
+        };
+        match &aggregation.group_by {
+            Some(group_by) => {
+                let group_by = column(group_by);
+                format!(
+                    "SELECT {group_by}, {value} AS event_count FROM {table} WHERE {filter} GROUP BY {group_by} HAVING {value} {operator} {}",
+                    aggregation.value
+                )
+            }
+            None => format!(
+                "SELECT {value} AS event_count FROM {table} WHERE {filter} HAVING {value} {operator} {}",
+                aggregation.value
+            ),
+        }
+    }
+}
+
+impl Default for SqlDialect {
+    fn default() -> Self {
+        SqlDialect::SQL
+    }
+}
+
+/// Converts Sigma rules into SQL, the flavour being given by its dialect.
+#[derive(Clone, Debug, Default)]
+pub struct SQLBackend {
+    dialect: SqlDialect,
+}
+
+impl SQLBackend {
+    pub fn new(dialect: SqlDialect) -> Self {
+        SQLBackend { dialect }
+    }
+}
+
+impl BackEnd for SQLBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        self.dialect
+            .build_select(&sigma_rule, self.dialect.table, &|field| field.to_string())
+    }
+}
+
+impl QueryBuilder for SQLBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        self.dialect.build_where(rule, &|field| field.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::sql::{SQLBackend, SqlDialect};
+    use crate::backend::{BackEnd, QueryBuilder};
+    use crate::parse_sigma_rule;
+    use crate::prelude::SiemIp;
+
+    #[test]
+    pub fn test_dialects() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        assert_eq!(
+            SQLBackend::default().build_query(&rule),
+            r#"(((LOWER(Image) LIKE '%\\cmd.exe' ESCAPE '\' OR LOWER(OriginalFileName) = 'cmd.exe') AND LOWER(ParentImage) LIKE '%\\7zfm.exe' ESCAPE '\') AND NOT ((LOWER(CommandLine) LIKE '%/c%' ESCAPE '\' OR LOWER(CommandLine) LIKE '%/k%' ESCAPE '\' OR LOWER(CommandLine) LIKE '%/r%' ESCAPE '\') OR CommandLine IS NULL))"#
+        );
+        assert_eq!(
+            SQLBackend::new(SqlDialect::POSTGRESQL).build_query(&rule),
+            r#"((("Image" ILIKE '%\\cmd.exe' ESCAPE '\' OR "OriginalFileName" ILIKE 'Cmd.Exe' ESCAPE '\') AND "ParentImage" ILIKE '%\\7zFM.exe' ESCAPE '\') AND NOT (("CommandLine" ILIKE '%/c%' ESCAPE '\' OR "CommandLine" ILIKE '%/k%' ESCAPE '\' OR "CommandLine" ILIKE '%/r%' ESCAPE '\') OR "CommandLine" IS NULL))"#
+        );
+        assert_eq!(
+            SQLBackend::new(SqlDialect::CLICKHOUSE).build_query(&rule),
+            r#"(((Image ILIKE '%\\\\cmd.exe' OR OriginalFileName ILIKE 'Cmd.Exe') AND ParentImage ILIKE '%\\\\7zFM.exe') AND NOT ((CommandLine ILIKE '%/c%' OR CommandLine ILIKE '%/k%' OR CommandLine ILIKE '%/r%') OR CommandLine IS NULL))"#
+        );
+    }
+
+    #[test]
+    pub fn test_cidr_and_aggregation() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Internal Scan
+logsource:
+    category: firewall
+detection:
+    selection:
+        dst_ip|cidr: 10.0.0.0/8
+        action: "deny_all"
+    condition: selection | count(dst_port) by src_ip > 10
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            SQLBackend::new(SqlDialect::MYSQL).convert_rule(rule.clone()),
+            "SELECT src_ip, COUNT(DISTINCT dst_port) AS event_count FROM eventlog WHERE (LOWER(action) = 'deny_all' AND INET_ATON(dst_ip) BETWEEN 167772160 AND 184549375) GROUP BY src_ip HAVING COUNT(DISTINCT dst_port) > 10"
+        );
+        assert_eq!(
+            SQLBackend::new(SqlDialect::SQLITE).convert_rule(rule),
+            "SELECT src_ip, COUNT(DISTINCT dst_port) AS event_count FROM eventlog WHERE (LOWER(action) = 'deny_all' AND dst_ip LIKE '10.%') GROUP BY src_ip HAVING COUNT(DISTINCT dst_port) > 10"
+        );
+    }
+
+    #[test]
+    pub fn test_cidr_prefixes() {
+        let dialect = SqlDialect::SQLITE;
+        assert_eq!(
+            dialect.cidr("dst_ip", &SiemIp::V4(0xAC100000), 12),
+            format!(
+                "({})",
+                (16..32)
+                    .map(|octet| format!("dst_ip LIKE '172.{octet}.%'"))
+                    .collect::<Vec<String>>()
+                    .join(" OR ")
+            )
+        );
+        assert_eq!(
+            dialect.cidr("dst_ip", &SiemIp::V4(0xC0A80102), 31),
+            "(dst_ip = '192.168.1.2' OR dst_ip = '192.168.1.3')"
+        );
+        assert_eq!(
+            dialect.cidr("dst_ip", &SiemIp::V4(0xC0A80102), 24),
+            "dst_ip LIKE '192.168.1.%'"
+        );
     }
 }
@@ -1,4 +1,4 @@
-use crate::backend::sql::SQLBackend;
+use crate::backend::sql::{SQLBackend, SqlDialect};
 use crate::backend::{BackEnd, QueryBuilder};
 use crate::prelude::rule::sigma::SigmaRule;
 
@@ -7,13 +7,12 @@ pub struct SQLiteBackend {}
 
 impl BackEnd for SQLiteBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        let query = self.build_query(&sigma_rule);
-        format!("SELECT * FROM eventlog WHERE {query}")
+        SQLBackend::new(SqlDialect::SQLITE).convert_rule(sigma_rule)
     }
 }
 
 impl QueryBuilder for SQLiteBackend {
     fn build_query(&self, rule: &SigmaRule) -> String {
-        SQLBackend::default().build_query(rule)
+        SQLBackend::new(SqlDialect::SQLITE).build_query(rule)
     }
 }
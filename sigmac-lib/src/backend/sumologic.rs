@@ -1,366 +1,404 @@
 use crate::backend::{BackEnd, QueryBuilder};
-use crate::prelude::rule::sigma::{ConditionExpression, SigmaDetectionCondition};
-use crate::prelude::rule::RuleCondition;
-use crate::prelude::SiemField;
-use crate::sigma::components::rule::sigma::SigmaRule;
+use crate::prelude::rule::sigma::{ConditionAggregation, SigmaRule, SigmaRuleLogSource};
+use crate::prelude::{AlertSeverity, SiemField};
 use crate::sigma::components::rule::RuleOperator;
 use crate::sigma::components::rule::SiemRule;
-use crate::sigma::utilities::types::LogString;
-use crate::utils::parse_condition;
+use crate::utils::{escape_string, join_predicates, parse_aggregation, render_condition};
+use serde_json::json;
 use std::process::exit;
 
+/// The artifacts the Sumo Logic backend is able to produce.
 #[derive(Clone, Debug, Default)]
-pub struct SumoLogicBackend {}
+pub enum SumoLogicOutput {
+    /// The bare search query.
+    #[default]
+    Query,
+    /// A scheduled search content JSON, importable from the Library.
+    ScheduledSearch,
+    /// A Cloud SIEM match rule JSON, as expected by the rules API.
+    CloudSiem,
+}
+
+/// Converts Sigma rules into Sumo Logic searches. The rule fields are expected to be extracted
+/// by Field Extraction Rules under their sigma names.
+#[derive(Clone, Debug, Default)]
+pub struct SumoLogicBackend {
+    output: SumoLogicOutput,
+}
+
+impl SumoLogicBackend {
+    pub fn new(output: SumoLogicOutput) -> Self {
+        SumoLogicBackend { output }
+    }
+
+    /// Renders the detection condition either as a `where` expression of the search language
+    /// or as a Cloud SIEM rule expression.
+    fn build_expression(&self, rule: &SigmaRule) -> String {
+        let cloud_siem = matches!(self.output, SumoLogicOutput::CloudSiem);
+        let keywords = if cloud_siem {
+            ("AND", "OR", "NOT")
+        } else {
+            ("and", "or", "!")
+        };
+        render_condition(rule, keywords, &|field, operator| {
+            if cloud_siem {
+                cse_predicate(&cse_field(field), operator)
+            } else {
+                where_predicate(field, operator)
+            }
+        })
+    }
+
+    fn build_scheduled_search(&self, sigma_rule: &SigmaRule, query: String) -> String {
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let search = json!({
+            "type": "SavedSearchWithScheduleSyncDefinition",
+            "name": sigma_rule.title,
+            "description": sigma_rule.description.as_deref().unwrap_or_default(),
+            "search": {
+                "queryText": query,
+                "defaultTimeRange": "-15m",
+                "byReceiptTime": false,
+                "viewName": "",
+                "viewStartTime": "1970-01-01T00:00:00Z",
+                "queryParameters": [],
+                "parsingMode": "Manual"
+            },
+            "searchSchedule": {
+                "cronExpression": "0 0/15 * * * ? *",
+                "displayableTimeRange": "-15m",
+                "parseableTimeRange": {
+                    "type": "BeginBoundedTimeRange",
+                    "from": {
+                        "type": "RelativeTimeRangeBoundary",
+                        "relativeTime": "-15m"
+                    },
+                    "to": null
+                },
+                "timeZone": "Etc/UTC",
+                "threshold": {
+                    "thresholdType": "group",
+                    "operator": "gt",
+                    "count": 0
+                },
+                "notification": {
+                    "taskType": "EmailSearchNotificationSyncDefinition",
+                    "toList": [],
+                    "subjectTemplate": format!(
+                        "[{}] {}",
+                        severity_name(&siem_rule.alert.severity),
+                        sigma_rule.title
+                    ),
+                    "includeQuery": true,
+                    "includeResultSet": true,
+                    "includeHistogram": false,
+                    "includeCsvAttachment": false
+                },
+                "scheduleType": "15Minutes",
+                "muteErrorEmails": false,
+                "parameters": []
+            }
+        });
+        serde_json::to_string_pretty(&search).unwrap_or_default()
+    }
+
+    fn build_cloud_siem_rule(&self, sigma_rule: &SigmaRule) -> String {
+        let siem_rule: SiemRule = sigma_rule.clone().into();
+        let mut tags = vec![];
+        for tactic in &siem_rule.mitre.tactics {
+            tags.push(format!("_mitreAttackTactic:{tactic}"));
+        }
+        for technique in &siem_rule.mitre.techniques {
+            tags.push(format!("_mitreAttackTechnique:{}", technique.parent_id()));
+        }
+        if let Some(id) = &sigma_rule.id {
+            tags.push(format!("sigma_id:{id}"));
+        }
+        let score = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => 1,
+            AlertSeverity::LOW => 3,
+            AlertSeverity::MEDIUM => 5,
+            AlertSeverity::HIGH => 8,
+            AlertSeverity::CRITICAL => 10,
+        };
+        let rule = json!({
+            "fields": {
+                "name": sigma_rule.title,
+                "enabled": true,
+                "expression": self.build_expression(sigma_rule),
+                "assetField": "device_hostname",
+                "score": score,
+                "stream": "record",
+                "tags": tags,
+                "nameExpression": sigma_rule.title,
+                "descriptionExpression": sigma_rule.description.as_deref().unwrap_or_default(),
+                "isPrototype": false
+            }
+        });
+        serde_json::to_string_pretty(&rule).unwrap_or_default()
+    }
+}
 
 impl BackEnd for SumoLogicBackend {
     fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
-        self.build_query(&sigma_rule)
+        match self.output {
+            SumoLogicOutput::Query => self.build_query(&sigma_rule),
+            SumoLogicOutput::ScheduledSearch => {
+                let query = self.build_query(&sigma_rule);
+                self.build_scheduled_search(&sigma_rule, query)
+            }
+            SumoLogicOutput::CloudSiem => self.build_cloud_siem_rule(&sigma_rule),
+        }
     }
 }
 
 impl QueryBuilder for SumoLogicBackend {
     fn build_query(&self, rule: &SigmaRule) -> String {
-        let mut query_str = String::new();
-        let siem_rule: SiemRule = rule.clone().into();
-        let conditions = match parse_condition(rule.detection.condition.to_string().as_str()) {
-            Ok(conds) => conds,
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
             Err(e) => {
                 eprintln!("{:?}", e);
                 exit(1);
             }
         };
-        let mut condition_queries: Vec<(String, String)> = vec![];
-        for (sub_rule_name, sub_rule) in siem_rule.subrules.as_ref().iter() {
-            // for conditions in subrules.
-            let mut conditions_list = vec![];
-            let mut subrule_conditions = vec![];
-            let src_name = RuleCondition {
-                field: LogString::Owned("_sourceName".to_string()),
-                operator: RuleOperator::Equals(SiemField::Text(LogString::Owned(
-                    "security".to_string(),
-                ))),
-            };
-            let src_category = RuleCondition {
-                field: LogString::Owned("_sourceCategory".to_string()),
-                operator: RuleOperator::Equals(SiemField::Text(LogString::Owned(
-                    "windows".to_string(),
-                ))),
-            };
-            subrule_conditions.push(src_name);
-            subrule_conditions.push(src_category);
-            subrule_conditions.append(&mut sub_rule.conditions.clone());
-            for condition in &subrule_conditions {
-                let mut query_str = String::new();
-                let (cond_name, cond_oper) =
-                    (condition.field.to_string(), condition.operator.clone());
-                let mapped_cond_name = cond_name;
-                match cond_oper.clone() {
-                    RuleOperator::StartsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\").replace('*', "");
-                        query_str.push_str(format!("\"{pattern}\"").as_str());
-                    }
-                    RuleOperator::EndsWith(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\").replace('*', "");
-                        query_str.push_str(format!("\"{pattern}\"").as_str());
-                    }
-                    RuleOperator::Contains(mut pattern) => {
-                        pattern = pattern.replace('\\', "\\\\").replace('*', "");
-                        query_str.push_str(format!("\"{pattern}\"").as_str());
-                    }
-                    RuleOperator::Equals(field) => match field {
-                        SiemField::Array(_) => {}
-                        _ => {
-                            if mapped_cond_name.eq("EventID") {
-                                query_str
-                                    .push_str(format!("{mapped_cond_name} = \"{field}\"").as_str());
-                            } else if mapped_cond_name.eq("_sourceName")
-                                || mapped_cond_name.eq("_sourceCategory")
-                            {
-                                query_str
-                                    .push_str(format!("{mapped_cond_name}=*\"{field}\"*").as_str());
-                            } else {
-                                query_str.push_str(format!("\"{field}\"").as_str());
-                            }
-                        }
-                    },
-                    RuleOperator::Any(cond_list) => {
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(field) => {
-                                    if mapped_cond_name.to_lowercase().eq("eventid") {
-                                        sub_queries
-                                            .push(format!("{mapped_cond_name} = \"{field}\""));
-                                    } else if mapped_cond_name.eq("_sourceName")
-                                        || mapped_cond_name.eq("_sourceCategory")
-                                    {
-                                        sub_queries
-                                            .push(format!("{mapped_cond_name}=*\"{field}\"*"));
-                                    } else {
-                                        sub_queries.push(format!("\"{field}\""));
-                                    }
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("\"{pattern}\""));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("\"{pattern}\""));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("\"{pattern}\""));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" OR ");
-                        query_str.push_str(format!("({joined_sub_queries})").as_str());
-                    }
-                    RuleOperator::All(cond_list) => {
-                        let mut sub_queries = vec![];
-                        for cond in cond_list {
-                            match cond.as_ref() {
-                                RuleOperator::Equals(val) => {
-                                    sub_queries.push(format!("\"{val}\""));
-                                }
-                                RuleOperator::StartsWith(pattern) => {
-                                    sub_queries.push(format!("\"{pattern}\""));
-                                }
-                                RuleOperator::EndsWith(pattern) => {
-                                    sub_queries.push(format!("\"{pattern}\""));
-                                }
-                                RuleOperator::Contains(pattern) => {
-                                    sub_queries.push(format!("\"{pattern}\""));
-                                }
-                                _ => {}
-                            }
-                        }
-                        let joined_sub_queries = sub_queries.join(" AND ");
-                        query_str.push_str(joined_sub_queries.to_string().as_str());
-                    }
-                    _ => {}
-                };
-                if !query_str.is_empty() {
-                    conditions_list.push(query_str);
-                }
+        let mut query = source_category(&rule.logsource);
+        let expression = self.build_expression(rule);
+        if !expression.is_empty() {
+            query.push_str(&format!("\n| where {expression}"));
+        }
+        if let Some(aggregation) = aggregation {
+            query.push_str(&aggregate(&aggregation));
+        }
+        query
+    }
+}
+
+/// The `_sourceCategory` scope of the logsource, following the usual `product/service`
+/// naming of the sources (Ex: windows/sysmon, linux/auditd, aws/cloudtrail).
+fn source_category(logsource: &SigmaRuleLogSource) -> String {
+    let parts: Vec<&str> = [
+        logsource.product.as_deref(),
+        logsource
+            .service
+            .as_deref()
+            .or(logsource.category.as_deref()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if parts.is_empty() {
+        "*".to_string()
+    } else {
+        format!("_sourceCategory=*{}*", parts.join("/"))
+    }
+}
+
+fn aggregate(aggregation: &ConditionAggregation) -> String {
+    let (function, result) = match (aggregation.function.as_ref(), &aggregation.field) {
+        ("count", None) => ("count".to_string(), "_count".to_string()),
+        ("count", Some(field)) => (
+            format!("count_distinct({field})"),
+            "_count_distinct".to_string(),
+        ),
+        (function, Some(field)) => (format!("{function}({field})"), format!("_{function}")),
+        (function, None) => (function.to_string(), format!("_{function}")),
+    };
+    let group_by = match &aggregation.group_by {
+        Some(group_by) => format!(" by {group_by}"),
+        None => String::new(),
+    };
+    let operator = match aggregation.operator.as_ref() {
+        "==" => "=",
+        operator => operator,
+    };
+    format!(
+        "\n| {function}{group_by}\n| where {result} {operator} {}",
+        aggregation.value
+    )
+}
+
+/// Predicate of the search `where` operator. Sigma matches being case insensitive, the text
+/// values are compared against the lowercased field.
+fn where_predicate(field: &str, operator: &RuleOperator) -> String {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => join_predicates(
+            values
+                .iter()
+                .map(|value| text_where_predicate(field, value, "", ""))
+                .collect(),
+            "or",
+        ),
+        RuleOperator::Equals(SiemField::Text(value)) => text_where_predicate(field, value, "", ""),
+        RuleOperator::Equals(value) => format!("{field} = {value}"),
+        RuleOperator::StartsWith(pattern) => text_where_predicate(field, pattern, "", "*"),
+        RuleOperator::EndsWith(pattern) => text_where_predicate(field, pattern, "*", ""),
+        RuleOperator::Contains(pattern) => text_where_predicate(field, pattern, "*", "*"),
+        RuleOperator::Matches(regex) => {
+            format!("{field} matches /{}/", regex.as_str().replace('/', "\\/"))
+        }
+        RuleOperator::IsNull(_) => format!("isBlank({field})"),
+        RuleOperator::Any(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| where_predicate(field, operator))
+                .collect(),
+            "or",
+        ),
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| where_predicate(field, operator))
+                .collect(),
+            "and",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = where_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("!{predicate}")
             }
-            let mut condition_query = String::new();
-            condition_query.push('(');
-            condition_query.push_str(conditions_list.join(" AND ").as_str());
-            condition_query.push(')');
-            condition_queries.push((sub_rule_name.to_string(), condition_query));
         }
+        _ => String::new(),
+    }
+}
 
-        // Check the conditions of this subrule
-        for condition in conditions {
-            match condition {
-                SigmaDetectionCondition::Plain(cond) => match cond {
-                    ConditionExpression::Any(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(&pattern))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" OR ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::All(pattern) => {
-                        let pattern = pattern.replace('*', "");
-                        let matches: Vec<String> = condition_queries
-                            .iter()
-                            .filter(|x| x.0.contains(pattern.trim()))
-                            .map(|x| x.1.clone())
-                            .collect();
-                        if matches.len() > 1 {
-                            query_str.push('(');
-                            query_str.push_str(matches.join(" AND ").as_str());
-                            query_str.push(')');
-                        } else {
-                            query_str.push_str(matches[0].clone().as_str());
-                        }
-                    }
-                    ConditionExpression::Not(cond_expr) => {
-                        query_str.push_str(" NOT ");
-                        match cond_expr.as_ref() {
-                            ConditionExpression::Any(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" OR ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            ConditionExpression::All(pattern) => {
-                                let pattern = pattern.replace('*', "");
-                                let matches: Vec<String> = condition_queries
-                                    .iter()
-                                    .filter(|x| x.0.contains(&pattern))
-                                    .map(|x| x.1.clone())
-                                    .collect();
-                                if matches.len() > 1 {
-                                    query_str.push('(');
-                                    query_str.push_str(matches.join(" AND ").as_str());
-                                    query_str.push(')');
-                                } else {
-                                    query_str.push_str(matches[0].clone().as_str());
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                SigmaDetectionCondition::And(cond) => {
-                    query_str.push_str(" AND ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                SigmaDetectionCondition::Or(cond) => {
-                    query_str.push_str(" OR ");
-                    match cond {
-                        ConditionExpression::Any(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" OR ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::All(pattern) => {
-                            let pattern = pattern.replace('*', "");
-                            let matches: Vec<String> = condition_queries
-                                .iter()
-                                .filter(|x| x.0.contains(&pattern))
-                                .map(|x| x.1.clone())
-                                .collect();
-                            if matches.len() > 1 {
-                                query_str.push('(');
-                                query_str.push_str(matches.join(" AND ").as_str());
-                            } else {
-                                query_str.push_str(matches[0].clone().as_str());
-                            }
-                        }
-                        ConditionExpression::Not(cond_expr) => {
-                            query_str.push_str(" NOT ");
-                            match cond_expr.as_ref() {
-                                ConditionExpression::Any(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" OR ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                ConditionExpression::All(pattern) => {
-                                    let pattern = pattern.replace('*', "");
-                                    let matches: Vec<String> = condition_queries
-                                        .iter()
-                                        .filter(|x| x.0.contains(&pattern))
-                                        .map(|x| x.1.clone())
-                                        .collect();
-                                    if matches.len() > 1 {
-                                        query_str.push('(');
-                                        query_str.push_str(matches.join(" AND ").as_str());
-                                        query_str.push(')');
-                                    } else {
-                                        query_str.push_str(matches[0].clone().as_str());
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
+fn text_where_predicate(field: &str, value: &str, prefix: &str, suffix: &str) -> String {
+    let value = escape_string(&value.to_lowercase());
+    if prefix.is_empty() && suffix.is_empty() && !value.contains('*') {
+        format!("toLowerCase({field}) = \"{value}\"")
+    } else {
+        format!("toLowerCase({field}) matches \"{prefix}{value}{suffix}\"")
+    }
+}
+
+/// Cloud SIEM normalized record field of a sigma field, the other ones being looked up in
+/// the unnormalized `fields` map.
+fn cse_field(field: &str) -> String {
+    match field {
+        "CommandLine" => "commandLine",
+        "ParentCommandLine" => "parentCommandLine",
+        "Image" => "baseImage",
+        "ParentImage" => "parentBaseImage",
+        "User" => "user_username",
+        "SourceIp" | "src_ip" => "srcDevice_ip",
+        "SourcePort" | "src_port" => "srcPort",
+        "DestinationIp" | "dst_ip" => "dstDevice_ip",
+        "DestinationPort" | "dst_port" => "dstPort",
+        "DestinationHostname" => "dstDevice_hostname",
+        "Computer" | "ComputerName" => "device_hostname",
+        "TargetFilename" => "file_path",
+        "Hashes" => "file_hash_md5",
+        _ => return format!("fields['{}']", field.replace('\'', "\\'")),
+    }
+    .to_string()
+}
+
+fn cse_predicate(field: &str, operator: &RuleOperator) -> String {
+    let like = |pattern: String| {
+        format!(
+            "lower({field}) LIKE '{}'",
+            escape_cse_string(&pattern.to_lowercase())
+        )
+    };
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => join_predicates(
+            values
+                .iter()
+                .map(|value| {
+                    cse_predicate(field, &RuleOperator::Equals(SiemField::Text(value.clone())))
+                })
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::Equals(SiemField::Text(value)) => format!(
+            "lower({field}) = '{}'",
+            escape_cse_string(&value.to_lowercase())
+        ),
+        RuleOperator::Equals(value) => format!("{field} = {value}"),
+        RuleOperator::StartsWith(pattern) => like(format!("{}%", escape_like(pattern))),
+        RuleOperator::EndsWith(pattern) => like(format!("%{}", escape_like(pattern))),
+        RuleOperator::Contains(pattern) => like(format!("%{}%", escape_like(pattern))),
+        RuleOperator::Matches(regex) => {
+            format!("{field} RLIKE '{}'", escape_cse_string(regex.as_str()))
+        }
+        RuleOperator::IsNull(_) => format!("isNull({field})"),
+        RuleOperator::Any(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| cse_predicate(field, operator))
+                .collect(),
+            "OR",
+        ),
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| cse_predicate(field, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = cse_predicate(field, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
             }
         }
-        query_str.clone()
+        _ => String::new(),
+    }
+}
+
+fn escape_cse_string(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// Escapes the LIKE wildcards of a literal value, before it gets its `%` anchors.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn severity_name(severity: &AlertSeverity) -> &'static str {
+    match severity {
+        AlertSeverity::INFORMATIONAL => "Informational",
+        AlertSeverity::LOW => "Low",
+        AlertSeverity::MEDIUM => "Medium",
+        AlertSeverity::HIGH => "High",
+        AlertSeverity::CRITICAL => "Critical",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::sumologic::{SumoLogicBackend, SumoLogicOutput};
+    use crate::backend::{BackEnd, QueryBuilder};
+    use crate::parse_sigma_rule;
+
+    #[test]
+    pub fn query_building() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let query = SumoLogicBackend::default().build_query(&rule);
+        assert_eq!(
+            query,
+            r#"_sourceCategory=*windows/process_creation*
+| where (((toLowerCase(Image) matches "*\\cmd.exe" or toLowerCase(OriginalFileName) = "cmd.exe") and toLowerCase(ParentImage) matches "*\\7zfm.exe") and !((toLowerCase(CommandLine) matches "*/c*" or toLowerCase(CommandLine) matches "*/k*" or toLowerCase(CommandLine) matches "*/r*") or isBlank(CommandLine)))"#
+        );
+    }
+
+    #[test]
+    pub fn test_cloud_siem_rule() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let json = SumoLogicBackend::new(SumoLogicOutput::CloudSiem).convert_rule(rule);
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["fields"]["score"], 8);
+        assert_eq!(
+            json["fields"]["expression"],
+            r#"(((lower(baseImage) LIKE '%\\\\cmd.exe' OR lower(fields['OriginalFileName']) = 'cmd.exe') AND lower(parentBaseImage) LIKE '%\\\\7zfm.exe') AND NOT ((lower(commandLine) LIKE '%/c%' OR lower(commandLine) LIKE '%/k%' OR lower(commandLine) LIKE '%/r%') OR isNull(commandLine)))"#
+        );
     }
 }
@@ -0,0 +1,584 @@
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::sigma::{ConditionAggregation, SigmaRule, SigmaRuleLogSource};
+use crate::prelude::rule::RuleOperator;
+use crate::prelude::{SiemField, SiemIp};
+use crate::sigma::utilities::ip_utils::{ipv4_to_str, ipv6_to_str};
+use crate::utils::{
+    backend_option, join_predicates, logsource_mapping, parse_aggregation, pascal_case,
+    render_condition, sysmon_event_ids,
+};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::process::exit;
+
+/// What the Velociraptor backend emits for a rule.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum VelociraptorOutput {
+    /// The VQL query.
+    #[default]
+    Query,
+    /// An artifact YAML, with a source per rule.
+    Artifact,
+    /// The log sources and field mappings configuration of the Velociraptor sigma compiler,
+    /// for the rules to be run by its own sigma plugin.
+    SigmaMapping,
+}
+
+/// Where the events of a logsource are read from.
+#[derive(Clone, Debug, PartialEq)]
+enum EventSource {
+    /// An event log file, hunted with `parse_evtx`.
+    Evtx(String),
+    /// A real time ETW provider, given by its GUID.
+    Etw(&'static str),
+    /// A VQL plugin call given by the `logsource.*` mappings.
+    Plugin(String),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct VelociraptorBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: VelociraptorOutput,
+}
+
+impl VelociraptorBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: VelociraptorOutput) -> Self {
+        VelociraptorBackend { mappings, output }
+    }
+
+    fn etw(&self) -> bool {
+        backend_option(self.mappings.as_ref(), "velociraptor.etw").is_some_and(|etw| etw == "true")
+    }
+
+    /// The source of the logsource events. The `logsource.*` mappings give either an event
+    /// log file or a VQL plugin call, the windows logsources defaulting to their event log
+    /// (or ETW provider with a `velociraptor.etw=true` option).
+    fn event_source(&self, title: &str, logsource: &SigmaRuleLogSource) -> EventSource {
+        let mapped = logsource_mapping(self.mappings.as_ref(), logsource);
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let service = logsource.service.as_deref().unwrap_or_default();
+        let category = logsource.category.as_deref().unwrap_or_default();
+        match mapped {
+            Some(path) if path.to_lowercase().ends_with(".evtx") => return EventSource::Evtx(path),
+            Some(plugin) => return EventSource::Plugin(plugin),
+            None => (),
+        }
+        if product != "windows" {
+            eprintln!(
+                "'{title}': no Velociraptor source is known for the logsource, it can be given with the logsource.* mappings"
+            );
+            return EventSource::Plugin("source()".to_string());
+        }
+        let (channel, provider) = match (service, category) {
+            ("security", _) => ("Security", None),
+            ("system", _) => ("System", None),
+            ("application", _) => ("Application", None),
+            ("powershell-classic", _) => ("Windows PowerShell", None),
+            ("powershell", _) | (_, "ps_script" | "ps_module") => (
+                "Microsoft-Windows-PowerShell%4Operational",
+                Some("{A0C1853B-5C40-4B15-8766-3CF1C58F985A}"),
+            ),
+            ("taskscheduler", _) => ("Microsoft-Windows-TaskScheduler%4Operational", None),
+            ("wmi", _) => ("Microsoft-Windows-WMI-Activity%4Operational", None),
+            ("windefend", _) => ("Microsoft-Windows-Windows Defender%4Operational", None),
+            ("sysmon", _) | ("", _) => (
+                "Microsoft-Windows-Sysmon%4Operational",
+                Some("{5770385F-C22A-43E0-BF4C-06F5698FFBD9}"),
+            ),
+            (service, _) => {
+                eprintln!("'{title}': the {service} event log is assumed to be named {service}");
+                return EventSource::Evtx(format!(
+                    "%SystemRoot%\\System32\\winevt\\Logs\\{service}.evtx"
+                ));
+            }
+        };
+        match provider {
+            Some(guid) if self.etw() => EventSource::Etw(guid),
+            _ => {
+                if self.etw() {
+                    eprintln!("'{title}': no ETW provider is known for the {channel} events, its event log is hunted instead");
+                }
+                EventSource::Evtx(format!(
+                    "%SystemRoot%\\System32\\winevt\\Logs\\{channel}.evtx"
+                ))
+            }
+        }
+    }
+
+    /// The filter keeping the Sysmon events of the logsource category, as the Sysmon event log
+    /// (or provider) holds the events of all the categories.
+    fn sysmon_filter(
+        &self,
+        logsource: &SigmaRuleLogSource,
+        source: &EventSource,
+    ) -> Option<String> {
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let service = logsource.service.as_deref().unwrap_or_default();
+        let category = logsource.category.as_deref().unwrap_or_default();
+        if product != "windows"
+            || !matches!(service, "sysmon" | "")
+            || matches!(source, EventSource::Plugin(_))
+        {
+            return None;
+        }
+        let column = self.column("EventID", matches!(source, EventSource::Etw(_)));
+        match sysmon_event_ids(category) {
+            [] => None,
+            [id] => Some(format!("{column} = {id}")),
+            ids => Some(format!(
+                "{column} in ({})",
+                ids.iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// The VQL expression of a sigma field, the system fields differing between the event
+    /// logs and ETW.
+    fn column(&self, field: &str, etw: bool) -> String {
+        let column = self.get_mapping(field.to_string());
+        if etw && column == "System.EventID.Value" {
+            "System.ID".to_string()
+        } else {
+            column
+                .split('.')
+                .map(|member| {
+                    if member
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    {
+                        member.to_string()
+                    } else {
+                        format!("`{member}`")
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(".")
+        }
+    }
+
+    fn build_where(&self, rule: &SigmaRule, etw: bool) -> String {
+        render_condition(rule, ("AND", "OR", "NOT"), &|field, operator| {
+            // keywords are searched in all the event data
+            let column = if field.is_empty() {
+                "serialize(item=EventData)".to_string()
+            } else {
+                self.column(field, etw)
+            };
+            build_predicate(&column, operator)
+        })
+    }
+
+    /// The VQL of the rule, its aggregation grouping the matching events.
+    fn build_vql(&self, rule: &SigmaRule) -> String {
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let source = self.event_source(&rule.title, &rule.logsource);
+        let etw = matches!(source, EventSource::Etw(_));
+        let plugin = match &source {
+            EventSource::Evtx(path) => {
+                format!("parse_evtx(filename=expand(path={}))", vql_string(path))
+            }
+            EventSource::Etw(guid) => format!("watch_etw(guid='{guid}')"),
+            EventSource::Plugin(plugin) => plugin.clone(),
+        };
+        // the rules selecting the event ids themselves don't need the ones of the category
+        let selects_event_id = rule
+            .detection
+            .search_identifiers
+            .values()
+            .flat_map(|search| search.condition_groups())
+            .flatten()
+            .any(|condition| condition.field == "EventID");
+        let filter = match self.sysmon_filter(&rule.logsource, &source) {
+            Some(sysmon) if !selects_event_id => {
+                format!("{sysmon} AND {}", self.build_where(rule, etw))
+            }
+            _ => self.build_where(rule, etw),
+        };
+        let query = format!("SELECT * FROM {plugin} WHERE {filter}");
+        match aggregation {
+            Some(aggregation) => {
+                if rule.detection.timeframe.is_some() {
+                    eprintln!(
+                        "'{}': the events are grouped over the whole hunt, the timeframe is dropped",
+                        rule.title
+                    );
+                }
+                self.build_aggregation(&aggregation, &query, etw)
+            }
+            None => query,
+        }
+    }
+
+    fn build_aggregation(
+        &self,
+        aggregation: &ConditionAggregation,
+        query: &str,
+        etw: bool,
+    ) -> String {
+        let field = aggregation
+            .field
+            .as_ref()
+            .map(|field| self.column(field, etw));
+        let group_by = match &aggregation.group_by {
+            Some(group_by) => self.column(group_by, etw),
+            None => "'all'".to_string(),
+        };
+        let operator = match aggregation.operator.as_ref() {
+            "==" => "=",
+            operator => operator,
+        };
+        let value = match (aggregation.function.to_lowercase().as_str(), field) {
+            // the distinct values are counted, grouping on them first
+            ("count", Some(field)) => {
+                return format!(
+                    "LET Matches = {query}\nLET Values = SELECT {group_by} AS GroupKey, {field} AS Value FROM Matches GROUP BY GroupKey, Value\nLET Groups = SELECT GroupKey, count() AS Count FROM Values GROUP BY GroupKey\nSELECT * FROM Groups WHERE Count {operator} {}",
+                    aggregation.value
+                )
+            }
+            ("count", None) => "count()".to_string(),
+            ("avg", Some(field)) => format!("sum(item={field}) / count()"),
+            (function, Some(field)) => format!("{function}(item={field})"),
+            (function, None) => format!("{function}()"),
+        };
+        format!(
+            "LET Matches = {query}\nLET Groups = SELECT {group_by} AS GroupKey, {value} AS Count FROM Matches GROUP BY GroupKey\nSELECT * FROM Groups WHERE Count {operator} {}",
+            aggregation.value
+        )
+    }
+
+    /// The artifact collecting the rules, a source each.
+    fn build_artifact(&self, sigma_rules: &[SigmaRule]) -> String {
+        let (name, description) = match sigma_rules {
+            [rule] => (
+                format!("Custom.Sigma.{}", pascal_case(&rule.title)),
+                rule.description
+                    .as_deref()
+                    .unwrap_or(&rule.title)
+                    .to_string(),
+            ),
+            _ => (
+                backend_option(self.mappings.as_ref(), "artifact.name")
+                    .unwrap_or("Custom.Sigma.Rules")
+                    .to_string(),
+                "Sigma rules".to_string(),
+            ),
+        };
+        let mut names: Vec<String> = vec![];
+        let mut event = false;
+        let sources: Vec<serde_json::Value> = sigma_rules
+            .iter()
+            .map(|rule| {
+                event |= matches!(
+                    self.event_source(&rule.title, &rule.logsource),
+                    EventSource::Etw(_)
+                );
+                let name = pascal_case(&rule.title);
+                // rules sharing a title get numbered
+                let name = (1..)
+                    .map(|n| match n {
+                        1 => name.clone(),
+                        n => format!("{name}{n}"),
+                    })
+                    .find(|name| !names.contains(name))
+                    .unwrap_or(name);
+                names.push(name.clone());
+                json!({
+                    "name": name,
+                    "description": rule.description.as_deref().unwrap_or(&rule.title),
+                    "query": self.build_vql(rule),
+                })
+            })
+            .collect();
+        let artifact = json!({
+            "name": name,
+            "description": description,
+            "type": if event { "CLIENT_EVENT" } else { "CLIENT" },
+            "sources": sources,
+        });
+        serde_yaml::to_string(&artifact).unwrap_or_default()
+    }
+
+    /// The configuration of the Velociraptor sigma compiler: the query of each logsource
+    /// (keyed `category/product/service`) and the VQL lambda of each field of the rules.
+    fn build_sigma_mapping(&self, sigma_rules: &[SigmaRule]) -> String {
+        let etw = self.etw();
+        let mut sources = BTreeMap::new();
+        let mut fields = BTreeMap::new();
+        for rule in sigma_rules {
+            let logsource = &rule.logsource;
+            let key = [&logsource.category, &logsource.product, &logsource.service]
+                .iter()
+                .map(|part| part.as_deref().unwrap_or("*"))
+                .collect::<Vec<&str>>()
+                .join("/");
+            let source = self.event_source(&rule.title, logsource);
+            let mut query = match &source {
+                EventSource::Evtx(path) => format!(
+                    "SELECT * FROM parse_evtx(filename=expand(path={}))",
+                    vql_string(path)
+                ),
+                EventSource::Etw(guid) => format!("SELECT * FROM watch_etw(guid='{guid}')"),
+                EventSource::Plugin(plugin) => format!("SELECT * FROM {plugin}"),
+            };
+            if let Some(sysmon) = self.sysmon_filter(logsource, &source) {
+                query.push_str(&format!(" WHERE {sysmon}"));
+            }
+            sources.insert(key, json!({ "query": query }));
+            for field in rule
+                .detection
+                .search_identifiers
+                .values()
+                .flat_map(|search| search.condition_groups())
+                .flatten()
+                .map(|condition| condition.field.to_string())
+                .filter(|field| !field.is_empty())
+            {
+                let lambda = format!("x=>x.{}", self.column(&field, etw));
+                fields.insert(field, lambda);
+            }
+        }
+        let config = json!({ "FieldMappings": fields, "Sources": sources });
+        serde_yaml::to_string(&config).unwrap_or_default()
+    }
+}
+
+impl BackEnd for VelociraptorBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        match self.output {
+            VelociraptorOutput::Query => self.build_vql(&sigma_rule),
+            VelociraptorOutput::Artifact => self.build_artifact(&[sigma_rule]),
+            VelociraptorOutput::SigmaMapping => self.build_sigma_mapping(&[sigma_rule]),
+        }
+    }
+
+    /// The rule set becomes a single artifact or sigma compiler configuration.
+    fn convert_rules(&self, sigma_rules: Vec<SigmaRule>) -> String {
+        match self.output {
+            VelociraptorOutput::Query => sigma_rules
+                .into_iter()
+                .map(|sigma_rule| self.convert_rule(sigma_rule))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            VelociraptorOutput::Artifact => self.build_artifact(&sigma_rules),
+            VelociraptorOutput::SigmaMapping => self.build_sigma_mapping(&sigma_rules),
+        }
+    }
+}
+
+impl RequiresMappings for VelociraptorBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        let field = args.unwrap_or_default();
+        match field.as_str() {
+            "EventID" => "System.EventID.Value".to_string(),
+            "Channel" => "System.Channel".to_string(),
+            "Computer" => "System.Computer".to_string(),
+            "Provider_Name" => "System.Provider.Name".to_string(),
+            _ => format!("EventData.{field}"),
+        }
+    }
+}
+
+impl QueryBuilder for VelociraptorBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        let etw = matches!(
+            self.event_source(&rule.title, &rule.logsource),
+            EventSource::Etw(_)
+        );
+        self.build_where(rule, etw)
+    }
+}
+
+fn build_predicate(column: &str, operator: &RuleOperator) -> String {
+    // VQL regexes match without minding the case
+    let regex = |pattern: String| format!("{column} =~ {}", vql_string(&pattern));
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => regex(format!(
+            "^({})$",
+            values
+                .iter()
+                .map(|value| regex::escape(value))
+                .collect::<Vec<String>>()
+                .join("|")
+        )),
+        RuleOperator::Equals(SiemField::Text(value)) => {
+            regex(format!("^{}$", regex::escape(value)))
+        }
+        RuleOperator::Equals(value) => format!("{column} = {value}"),
+        RuleOperator::StartsWith(pattern) => regex(format!("^{}", regex::escape(pattern))),
+        RuleOperator::EndsWith(pattern) => regex(format!("{}$", regex::escape(pattern))),
+        RuleOperator::Contains(pattern) => regex(regex::escape(pattern)),
+        RuleOperator::Matches(pattern) => regex(pattern.as_str().to_string()),
+        RuleOperator::GT(value) => format!("{column} > {value}"),
+        RuleOperator::GTE(value) => format!("{column} >= {value}"),
+        RuleOperator::LT(value) => format!("{column} < {value}"),
+        RuleOperator::LTE(value) => format!("{column} <= {value}"),
+        RuleOperator::SameNet((ip, net)) => {
+            let address = match ip {
+                SiemIp::V4(ip) => ipv4_to_str(*ip),
+                SiemIp::V6(ip) => ipv6_to_str(*ip),
+            };
+            format!("cidr_contains(ip={column}, ranges='{address}/{net}')")
+        }
+        RuleOperator::IsNull(_) => format!("NOT {column}"),
+        RuleOperator::Any(operators) => {
+            // Several text equalities of the same field are an alternation
+            let values: Option<Vec<String>> = operators
+                .iter()
+                .map(|operator| match operator.as_ref() {
+                    RuleOperator::Equals(SiemField::Text(value)) => Some(regex::escape(value)),
+                    _ => None,
+                })
+                .collect();
+            match values {
+                Some(values) if values.len() > 1 => regex(format!("^({})$", values.join("|"))),
+                _ => join_predicates(
+                    operators
+                        .iter()
+                        .map(|operator| build_predicate(column, operator))
+                        .collect(),
+                    "OR",
+                ),
+            }
+        }
+        RuleOperator::All(operators) => join_predicates(
+            operators
+                .iter()
+                .map(|operator| build_predicate(column, operator))
+                .collect(),
+            "AND",
+        ),
+        RuleOperator::Not(operator) => {
+            let predicate = build_predicate(column, operator);
+            if predicate.is_empty() {
+                predicate
+            } else {
+                format!("NOT {predicate}")
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// A VQL string literal, raw unless the value holds the raw string quotes.
+fn vql_string(value: &str) -> String {
+    if value.contains("'''") {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        format!("'''{value}'''")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::velociraptor::{VelociraptorBackend, VelociraptorOutput};
+    use crate::backend::{BackEnd, QueryBuilder};
+    use crate::parse_sigma_rule;
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_query() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        assert_eq!(
+            VelociraptorBackend::default().build_query(&rule),
+            r#"(((EventData.Image =~ '''\\cmd\.exe$''' OR EventData.OriginalFileName =~ '''^Cmd\.Exe$''') AND EventData.ParentImage =~ '''\\7zFM\.exe$''') AND NOT ((EventData.CommandLine =~ '''/c''' OR EventData.CommandLine =~ '''/k''' OR EventData.CommandLine =~ '''/r''') OR NOT EventData.CommandLine))"#
+        );
+    }
+
+    #[test]
+    pub fn test_artifact_and_sigma_mapping() {
+        let rules: Vec<_> = [
+            r#"
+title: Encoded PowerShell
+logsource:
+    product: windows
+    category: process_creation
+detection:
+    selection:
+        EventID: 1
+        Image|endswith:
+            - \powershell.exe
+            - \pwsh.exe
+    condition: selection
+"#,
+            r#"
+title: Failed Logons
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    condition: selection | count() by TargetUserName > 10
+"#,
+        ]
+        .iter()
+        .map(|rule| parse_sigma_rule(rule).unwrap())
+        .collect();
+        let mappings = HashMap::from([("option.velociraptor.etw".to_string(), "true".to_string())]);
+        let backend = VelociraptorBackend::new(Some(mappings), VelociraptorOutput::Artifact);
+        let artifact: serde_yaml::Value =
+            serde_yaml::from_str(&backend.convert_rules(rules.clone())).unwrap();
+        assert_eq!(artifact["name"], "Custom.Sigma.Rules");
+        assert_eq!(artifact["type"], "CLIENT_EVENT");
+        assert_eq!(artifact["sources"][0]["name"], "EncodedPowerShell");
+        assert_eq!(
+            artifact["sources"][0]["query"],
+            r#"SELECT * FROM watch_etw(guid='{5770385F-C22A-43E0-BF4C-06F5698FFBD9}') WHERE (System.ID = 1 AND (EventData.Image =~ '''\\powershell\.exe$''' OR EventData.Image =~ '''\\pwsh\.exe$'''))"#
+        );
+        assert_eq!(
+            artifact["sources"][1]["query"],
+            "LET Matches = SELECT * FROM parse_evtx(filename=expand(path='''%SystemRoot%\\System32\\winevt\\Logs\\Security.evtx''')) WHERE System.EventID.Value = 4625\nLET Groups = SELECT EventData.TargetUserName AS GroupKey, count() AS Count FROM Matches GROUP BY GroupKey\nSELECT * FROM Groups WHERE Count > 10"
+        );
+        let config: serde_yaml::Value = serde_yaml::from_str(
+            &VelociraptorBackend::new(None, VelociraptorOutput::SigmaMapping).convert_rules(rules),
+        )
+        .unwrap();
+        assert_eq!(
+            config["FieldMappings"]["EventID"],
+            "x=>x.System.EventID.Value"
+        );
+        assert_eq!(
+            config["Sources"]["*/windows/security"]["query"],
+            "SELECT * FROM parse_evtx(filename=expand(path='''%SystemRoot%\\System32\\winevt\\Logs\\Security.evtx'''))"
+        );
+        assert_eq!(
+            config["Sources"]["process_creation/windows/*"]["query"],
+            "SELECT * FROM parse_evtx(filename=expand(path='''%SystemRoot%\\System32\\winevt\\Logs\\Microsoft-Windows-Sysmon%4Operational.evtx''')) WHERE System.EventID.Value = 1"
+        );
+    }
+
+    #[test]
+    pub fn test_distinct_count() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Password Spraying
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    condition: selection | count(TargetUserName) by IpAddress > 20
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            VelociraptorBackend::default().convert_rule(rule),
+            "LET Matches = SELECT * FROM parse_evtx(filename=expand(path='''%SystemRoot%\\System32\\winevt\\Logs\\Security.evtx''')) WHERE System.EventID.Value = 4625\nLET Values = SELECT EventData.IpAddress AS GroupKey, EventData.TargetUserName AS Value FROM Matches GROUP BY GroupKey, Value\nLET Groups = SELECT GroupKey, count() AS Count FROM Values GROUP BY GroupKey\nSELECT * FROM Groups WHERE Count > 20"
+        );
+    }
+}
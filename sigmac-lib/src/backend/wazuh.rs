@@ -0,0 +1,814 @@
+use crate::backend::{BackEnd, QueryBuilder, RequiresMappings};
+use crate::prelude::rule::sigma::{ConditionTree, SigmaRule, SigmaRuleLogSource};
+use crate::prelude::rule::{RuleCondition, RuleOperator, SiemRule};
+use crate::prelude::types::LogString;
+use crate::prelude::{AlertSeverity, SiemField, SiemIp};
+use crate::sigma::utilities::ip_utils::ipv4_to_str;
+use crate::utils::{
+    backend_option, logsource_mapping, parse_aggregation, parse_condition_tree, stable_hash,
+    sysmon_event_ids, timeframe_seconds,
+};
+use std::collections::{HashMap, HashSet};
+use std::process::exit;
+
+/// Rule ids are allocated in blocks, the rules of a sigma rule getting consecutive ids.
+const ID_BLOCK: u64 = 10;
+
+/// Most rules a condition is expanded into, the negations of conjunctions multiplying them.
+const MAX_ALTERNATIVES: usize = 100;
+
+/// What the Wazuh backend emits for a rule.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum WazuhOutput {
+    /// The XML rules.
+    #[default]
+    Rules,
+    /// The CDB lists the rules look the long value lists up in.
+    Lists,
+}
+
+/// A search condition of a rule, negated or not.
+type Literal = (RuleCondition, bool);
+
+/// The rules converted from a sigma rule, with the CDB lists they look up.
+struct Conversion {
+    rules: Vec<String>,
+    lists: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WazuhBackend {
+    mappings: Option<HashMap<String, String>>,
+    output: WazuhOutput,
+}
+
+impl WazuhBackend {
+    pub fn new(mappings: Option<HashMap<String, String>>, output: WazuhOutput) -> Self {
+        WazuhBackend { mappings, output }
+    }
+
+    fn numeric_setting(&self, key: &str, default: u64) -> u64 {
+        backend_option(self.mappings.as_ref(), key)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    /// The dynamic field of a sigma field, the Windows ones being decoded under `win.`.
+    fn column(&self, field: &str, windows: bool) -> String {
+        match self
+            .mappings
+            .as_ref()
+            .and_then(|mappings| mappings.get(field))
+        {
+            Some(mapping) => mapping.to_string(),
+            None if windows => self.get_default_field_name(Some(field.to_string())),
+            None => field.to_string(),
+        }
+    }
+
+    /// The parent of the rules: the `if_sid`/`if_group` of the logsource given by the
+    /// `logsource.*` mappings (numeric values being rule ids), else the Windows channel
+    /// rules. The extra field conditions select the events of the logsource within its parent.
+    fn parent(&self, logsource: &SigmaRuleLogSource) -> (Option<String>, Vec<(String, String)>) {
+        let mapped = logsource_mapping(self.mappings.as_ref(), logsource);
+        let product = logsource.product.as_deref().unwrap_or_default();
+        let service = logsource.service.as_deref().unwrap_or_default();
+        let category = logsource.category.as_deref().unwrap_or_default();
+        let parent = |parent: &str| {
+            if parent
+                .split(',')
+                .all(|sid| sid.trim().parse::<u64>().is_ok())
+            {
+                format!("<if_sid>{}</if_sid>", escape_xml(parent))
+            } else {
+                format!("<if_group>{}</if_group>", escape_xml(parent))
+            }
+        };
+        if let Some(mapped) = mapped {
+            return (Some(parent(&mapped)), vec![]);
+        }
+        match (product, service) {
+            ("windows", "security") => (Some(parent("60001")), vec![]),
+            ("windows", "system") => (Some(parent("60002")), vec![]),
+            ("windows", "application") => (Some(parent("60003")), vec![]),
+            ("windows", "sysmon" | "") => {
+                // the sysmon categories are told apart by their event ids
+                let event_ids = sysmon_event_ids(category)
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<String>>()
+                    .join("|");
+                let conditions = if event_ids.is_empty() {
+                    vec![]
+                } else {
+                    vec![(self.column("EventID", true), format!("^(?:{event_ids})$"))]
+                };
+                (Some(parent("60004")), conditions)
+            }
+            ("windows", service) => {
+                let channel = match service {
+                    "powershell" => "Microsoft-Windows-PowerShell/Operational",
+                    "powershell-classic" => "Windows PowerShell",
+                    "taskscheduler" => "Microsoft-Windows-TaskScheduler/Operational",
+                    "wmi" => "Microsoft-Windows-WMI-Activity/Operational",
+                    "windefend" => "Microsoft-Windows-Windows Defender/Operational",
+                    service => service,
+                };
+                (
+                    Some(parent("60000")),
+                    vec![(
+                        self.column("Channel", true),
+                        format!("(?i)^{}$", regex::escape(channel)),
+                    )],
+                )
+            }
+            ("linux", "auditd") => (Some(parent("audit")), vec![]),
+            ("linux", _) => (Some(parent("syslog")), vec![]),
+            _ => (None, vec![]),
+        }
+    }
+
+    /// The condition of the rule as alternatives of conditions to be all met, a Wazuh rule
+    /// being a conjunction of its field matches.
+    fn alternatives(
+        &self,
+        rule: &SigmaRule,
+        tree: &ConditionTree,
+        negated: bool,
+    ) -> Vec<Vec<Literal>> {
+        match tree {
+            ConditionTree::Identifier(name) => {
+                let groups = rule
+                    .detection
+                    .search_identifiers
+                    .get(name.as_ref())
+                    .map(|search| search.condition_groups())
+                    .unwrap_or_default();
+                if negated {
+                    // not (a and b) or (c) => (not a or not b) and not c
+                    product(
+                        groups
+                            .into_iter()
+                            .map(|group| {
+                                group
+                                    .into_iter()
+                                    .map(|condition| vec![(condition, true)])
+                                    .collect()
+                            })
+                            .collect(),
+                    )
+                } else {
+                    groups
+                        .into_iter()
+                        .map(|group| {
+                            group
+                                .into_iter()
+                                .map(|condition| (condition, false))
+                                .collect()
+                        })
+                        .collect()
+                }
+            }
+            ConditionTree::And(nodes) | ConditionTree::Or(nodes) => {
+                let children: Vec<Vec<Vec<Literal>>> = nodes
+                    .iter()
+                    .map(|node| self.alternatives(rule, node, negated))
+                    .collect();
+                // negating swaps the conjunctions and the disjunctions
+                if matches!(tree, ConditionTree::And(_)) != negated {
+                    product(children)
+                } else {
+                    children.into_iter().flatten().collect()
+                }
+            }
+            ConditionTree::Not(node) => self.alternatives(rule, node, !negated),
+            // expanded away by convert
+            ConditionTree::AnyOf(_) | ConditionTree::AllOf(_) => vec![],
+        }
+    }
+
+    /// The first id of a block of `count` consecutive ids, derived from the rule so its
+    /// conversions keep the same ids. Blocks already `used` are skipped.
+    fn allocate(&self, rule: &SigmaRule, count: u64, used: &mut HashSet<u64>) -> u64 {
+        let start = self.numeric_setting("rule.id.start", 100000);
+        let end = self.numeric_setting("rule.id.end", 119999).max(start);
+        let slots = ((end - start + 1) / ID_BLOCK).max(1);
+        let blocks = count.div_ceil(ID_BLOCK).max(1);
+        let first = stable_hash(rule.id.as_ref().unwrap_or(&rule.title)) % slots;
+        let slot = (0..slots)
+            .map(|offset| (first + offset) % slots)
+            .find(|slot| {
+                slot + blocks <= slots && (*slot..slot + blocks).all(|block| !used.contains(&block))
+            })
+            .unwrap_or_else(|| {
+                eprintln!(
+                    "No free rule ids left between {start} and {end} for '{}'",
+                    rule.title
+                );
+                first
+            });
+        used.extend(slot..slot + blocks);
+        start + slot * ID_BLOCK
+    }
+
+    /// The field matches and list lookups of an alternative, with the conditions of the
+    /// logsource the alternative doesn't select itself.
+    fn field_elements(
+        &self,
+        rule: &SigmaRule,
+        alternative: &[Literal],
+        conditions: &[(String, String)],
+        first_id: u64,
+        lists: &mut Vec<(String, Vec<String>)>,
+    ) -> Vec<String> {
+        let windows = rule.logsource.product.as_deref() == Some("windows");
+        let threshold = self.numeric_setting("list.threshold", 10) as usize;
+        let mut lookups = vec![];
+        let mut fields: Vec<(String, Vec<(String, bool)>)> = conditions
+            .iter()
+            .filter(|(field, _)| {
+                // the rule may select the event ids itself
+                !alternative
+                    .iter()
+                    .any(|(condition, _)| &self.column(&condition.field, windows) == field)
+            })
+            .map(|(field, pattern)| (field.clone(), vec![(pattern.clone(), false)]))
+            .collect();
+        for (condition, negated) in alternative {
+            let field = if condition.field.is_empty() {
+                String::new()
+            } else {
+                self.column(&condition.field, windows)
+            };
+            match list_values(&condition.operator) {
+                Some(values) if !field.is_empty() && values.len() > threshold => {
+                    // long lists of values are looked up in a CDB list
+                    let index = match lists.iter().position(|(_, list)| list == &values) {
+                        Some(index) => index,
+                        None => {
+                            lists.push((
+                                format!("etc/lists/sigma_{first_id}_{}", lists.len() + 1),
+                                values,
+                            ));
+                            lists.len() - 1
+                        }
+                    };
+                    lookups.push(format!(
+                        "<list field=\"{}\" lookup=\"{}\">{}</list>",
+                        escape_xml(&field),
+                        if *negated {
+                            "not_match_key"
+                        } else {
+                            "match_key"
+                        },
+                        lists[index].0
+                    ));
+                    continue;
+                }
+                _ => (),
+            }
+            let Some(pattern) = build_pattern(&condition.operator) else {
+                eprintln!(
+                    "Wazuh can't match the condition of '{}' on '{}', it is dropped",
+                    rule.title, condition.field
+                );
+                continue;
+            };
+            match fields.iter_mut().find(|(name, _)| name == &field) {
+                Some((_, patterns)) => patterns.push((pattern, *negated)),
+                None => fields.push((field, vec![(pattern, *negated)])),
+            }
+        }
+        let matches: Vec<String> = fields
+            .into_iter()
+            .map(|(field, patterns)| {
+                // several matches of a field are merged in lookaheads
+                let (pattern, negated) = match patterns.as_slice() {
+                    [(pattern, negated)] => (pattern.clone(), *negated),
+                    patterns => (
+                        format!(
+                            "^{}",
+                            patterns
+                                .iter()
+                                .map(|(pattern, negated)| match negated {
+                                    true => format!("(?!.*?(?:{pattern}))"),
+                                    false => format!("(?=.*?(?:{pattern}))"),
+                                })
+                                .collect::<String>()
+                        ),
+                        false,
+                    ),
+                };
+                let negate = if negated { " negate=\"yes\"" } else { "" };
+                match field.as_str() {
+                    "" => format!(
+                        "<regex type=\"pcre2\"{negate}>{}</regex>",
+                        escape_xml(&pattern)
+                    ),
+                    field => format!(
+                        "<field name=\"{}\" type=\"pcre2\"{negate}>{}</field>",
+                        escape_xml(field),
+                        escape_xml(&pattern)
+                    ),
+                }
+            })
+            .collect();
+        matches.into_iter().chain(lookups).collect()
+    }
+
+    fn convert(&self, rule: &SigmaRule, used: &mut HashSet<u64>) -> Conversion {
+        let condition = match parse_condition_tree(&rule.detection.condition) {
+            Ok(condition) => condition,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let aggregation = match parse_aggregation(&rule.detection.condition) {
+            Ok(aggregation) => aggregation,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                exit(1);
+            }
+        };
+        let identifiers: Vec<LogString> =
+            rule.detection.search_identifiers.keys().cloned().collect();
+        let (detection, filters) = split_filters(condition.expand(&identifiers));
+        let mut alternatives = self.alternatives(rule, &detection, false);
+        alternatives.truncate(MAX_ALTERNATIVES);
+        // the excluded events match a level 0 child rule of the detection rules instead
+        let filters: Vec<Vec<Literal>> = filters
+            .iter()
+            .flat_map(|filter| self.alternatives(rule, filter, false))
+            .take(MAX_ALTERNATIVES)
+            .collect();
+        if alternatives.len() >= MAX_ALTERNATIVES || filters.len() >= MAX_ALTERNATIVES {
+            eprintln!(
+                "The condition of '{}' reaches the limit of {MAX_ALTERNATIVES} Wazuh rules, the rest of its alternatives are dropped",
+                rule.title
+            );
+        }
+        // Wazuh correlates the events of the rules matching them with a frequency rule
+        let correlation = aggregation.filter(|aggregation| {
+            let supported = aggregation.function.to_lowercase() == "count"
+                && matches!(aggregation.operator.as_ref(), ">" | ">=");
+            if !supported {
+                eprintln!(
+                    "Wazuh only correlates the number of events, the aggregation of '{}' is dropped",
+                    rule.title
+                );
+            }
+            supported
+        });
+        let count = (alternatives.len() + filters.len()) as u64 + correlation.is_some() as u64;
+        let first_id = self.allocate(rule, count, used);
+        let windows = rule.logsource.product.as_deref() == Some("windows");
+        let (parent, conditions) = self.parent(&rule.logsource);
+        let siem_rule: SiemRule = rule.clone().into();
+        let level = match siem_rule.alert.severity {
+            AlertSeverity::INFORMATIONAL => 3,
+            AlertSeverity::LOW => 5,
+            AlertSeverity::MEDIUM => 8,
+            AlertSeverity::HIGH => 12,
+            AlertSeverity::CRITICAL => 15,
+        };
+        let mut details = vec![format!(
+            "<description>{}</description>",
+            escape_xml(&rule.title)
+        )];
+        details.extend(
+            rule.references
+                .iter()
+                .flatten()
+                .map(|reference| format!("<info type=\"link\">{}</info>", escape_xml(reference))),
+        );
+        if !siem_rule.mitre.techniques.is_empty() {
+            details.push(format!(
+                "<mitre>\n{}\n</mitre>",
+                siem_rule
+                    .mitre
+                    .techniques
+                    .iter()
+                    .map(|technique| format!("  <id>{technique}</id>"))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ));
+        }
+        let mut lists: Vec<(String, Vec<String>)> = vec![];
+        let mut rules = vec![];
+        for (index, alternative) in alternatives.iter().enumerate() {
+            let elements: Vec<String> = parent
+                .iter()
+                .cloned()
+                .chain(self.field_elements(rule, alternative, &conditions, first_id, &mut lists))
+                .chain(details.iter().cloned())
+                .collect();
+            let level = if correlation.is_some() { 3 } else { level };
+            rules.push(rule_element(first_id + index as u64, level, "", &elements));
+        }
+        let detection_ids = (first_id..first_id + alternatives.len() as u64)
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        for (index, filter) in filters.iter().enumerate() {
+            let id = first_id + (alternatives.len() + index) as u64;
+            let elements: Vec<String> = [format!("<if_sid>{detection_ids}</if_sid>")]
+                .into_iter()
+                .chain(self.field_elements(rule, filter, &[], first_id, &mut lists))
+                .chain([format!(
+                    "<description>{} (filtered)</description>",
+                    escape_xml(&rule.title)
+                )])
+                .collect();
+            rules.push(rule_element(id, 0, "", &elements));
+        }
+        if let Some(aggregation) = correlation {
+            let id = first_id + (alternatives.len() + filters.len()) as u64;
+            let frequency = match aggregation.operator.as_ref() {
+                ">" => aggregation.value + 1,
+                _ => aggregation.value,
+            };
+            let timeframe = timeframe_seconds(rule.detection.timeframe.as_deref().unwrap_or("5m"));
+            let mut elements = vec![format!("<if_matched_sid>{detection_ids}</if_matched_sid>")];
+            if let Some(group_by) = &aggregation.group_by {
+                elements.push(format!(
+                    "<same_field>{}</same_field>",
+                    escape_xml(&self.column(group_by, windows))
+                ));
+            }
+            if let Some(field) = &aggregation.field {
+                elements.push(format!(
+                    "<different_field>{}</different_field>",
+                    escape_xml(&self.column(field, windows))
+                ));
+            }
+            elements.extend(details.iter().cloned());
+            rules.push(rule_element(
+                id,
+                level,
+                &format!(" frequency=\"{frequency}\" timeframe=\"{timeframe}\""),
+                &elements,
+            ));
+        }
+        Conversion { rules, lists }
+    }
+
+    fn convert_all(&self, sigma_rules: &[SigmaRule]) -> String {
+        let mut used = HashSet::new();
+        let conversions: Vec<Conversion> = sigma_rules
+            .iter()
+            .map(|rule| self.convert(rule, &mut used))
+            .collect();
+        match self.output {
+            WazuhOutput::Rules => format!(
+                "<group name=\"sigma,\">\n{}\n</group>\n",
+                conversions
+                    .iter()
+                    .flat_map(|conversion| conversion.rules.iter())
+                    .map(|rule| indent(rule, 2))
+                    .collect::<Vec<String>>()
+                    .join("\n\n")
+            ),
+            WazuhOutput::Lists => conversions
+                .iter()
+                .flat_map(|conversion| conversion.lists.iter())
+                .map(|(path, values)| {
+                    format!(
+                        "# {path}\n{}\n",
+                        values
+                            .iter()
+                            .map(|value| cdb_key(value))
+                            .collect::<Vec<String>>()
+                            .join("\n")
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+        }
+    }
+}
+
+impl BackEnd for WazuhBackend {
+    fn convert_rule(&self, sigma_rule: SigmaRule) -> String {
+        self.convert_all(&[sigma_rule])
+    }
+
+    /// The rules of a rule set share a group, their ids being allocated without collisions.
+    fn convert_rules(&self, sigma_rules: Vec<SigmaRule>) -> String {
+        self.convert_all(&sigma_rules)
+    }
+}
+
+impl RequiresMappings for WazuhBackend {
+    fn get_field_map(&self) -> Option<HashMap<String, String>> {
+        self.mappings.clone()
+    }
+
+    /// The fields of the Windows event channel decoder (Ex: `win.eventdata.parentImage`).
+    fn get_default_field_name(&self, args: Option<String>) -> String {
+        let field = args.unwrap_or_default();
+        match field.as_str() {
+            "EventID" => "win.system.eventID".to_string(),
+            "Channel" => "win.system.channel".to_string(),
+            "Computer" => "win.system.computer".to_string(),
+            "Provider_Name" => "win.system.providerName".to_string(),
+            "Level" => "win.system.level".to_string(),
+            _ => {
+                let mut chars = field.chars();
+                match chars.next() {
+                    Some(first) => format!(
+                        "win.eventdata.{}{}",
+                        first.to_ascii_lowercase(),
+                        chars.as_str()
+                    ),
+                    None => field,
+                }
+            }
+        }
+    }
+}
+
+impl QueryBuilder for WazuhBackend {
+    fn build_query(&self, rule: &SigmaRule) -> String {
+        self.convert(rule, &mut HashSet::new()).rules.join("\n\n")
+    }
+}
+
+/// The values of a long list lookup: the equalities of a field to text values.
+fn list_values(operator: &RuleOperator) -> Option<Vec<String>> {
+    match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => {
+            Some(values.iter().map(|value| value.to_string()).collect())
+        }
+        RuleOperator::Any(operators) => operators
+            .iter()
+            .map(|operator| match operator.as_ref() {
+                RuleOperator::Equals(SiemField::Text(value)) => Some(value.to_string()),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// The PCRE2 pattern of the condition of a field, its text matches ignoring the case.
+fn build_pattern(operator: &RuleOperator) -> Option<String> {
+    let pattern = match operator {
+        RuleOperator::Equals(SiemField::Array(values)) => format!(
+            "(?i)^(?:{})$",
+            values
+                .iter()
+                .map(|value| regex::escape(value))
+                .collect::<Vec<String>>()
+                .join("|")
+        ),
+        RuleOperator::Equals(SiemField::Text(value)) => format!("(?i)^{}$", regex::escape(value)),
+        RuleOperator::Equals(value) => format!("^{}$", regex::escape(&value.to_string())),
+        RuleOperator::StartsWith(pattern) => format!("(?i)^{}", regex::escape(pattern)),
+        RuleOperator::EndsWith(pattern) => format!("(?i){}$", regex::escape(pattern)),
+        RuleOperator::Contains(pattern) => format!("(?i){}", regex::escape(pattern)),
+        RuleOperator::Matches(regex) => regex.as_str().to_string(),
+        RuleOperator::SameNet((SiemIp::V4(ip), net)) if net % 8 == 0 => {
+            // networks ending on an octet boundary are a prefix of their addresses
+            let octets = (*net / 8) as usize;
+            let address = ipv4_to_str(*ip);
+            let prefix: Vec<&str> = address.split('.').take(octets).collect();
+            match octets {
+                0 => ".".to_string(),
+                4 => format!("^{}$", regex::escape(&address)),
+                _ => format!("^{}\\.", regex::escape(&prefix.join("."))),
+            }
+        }
+        RuleOperator::IsNull(_) => "^$".to_string(),
+        RuleOperator::Any(operators) => format!(
+            "(?:{})",
+            operators
+                .iter()
+                .map(|operator| build_pattern(operator))
+                .collect::<Option<Vec<String>>>()?
+                .join("|")
+        ),
+        RuleOperator::All(operators) => format!(
+            "^{}",
+            operators
+                .iter()
+                .map(|operator| build_pattern(operator).map(|p| format!("(?=.*?(?:{p}))")))
+                .collect::<Option<String>>()?
+        ),
+        RuleOperator::Not(operator) => format!("^(?!.*?(?:{}))", build_pattern(operator)?),
+        _ => return None,
+    };
+    Some(pattern)
+}
+
+/// Splits the `not` operands off a conjunction, the filters of the rest of the condition.
+fn split_filters(tree: ConditionTree) -> (ConditionTree, Vec<ConditionTree>) {
+    match tree {
+        ConditionTree::And(nodes) => {
+            let (filters, detection): (Vec<ConditionTree>, Vec<ConditionTree>) = nodes
+                .into_iter()
+                .partition(|node| matches!(node, ConditionTree::Not(_)));
+            let filters: Vec<ConditionTree> = filters
+                .into_iter()
+                .filter_map(|node| match node {
+                    ConditionTree::Not(node) => Some(*node),
+                    _ => None,
+                })
+                .collect();
+            if detection.is_empty() {
+                let nodes = filters
+                    .into_iter()
+                    .map(|node| ConditionTree::Not(Box::new(node)))
+                    .collect();
+                (ConditionTree::And(nodes), vec![])
+            } else {
+                (ConditionTree::And(detection), filters)
+            }
+        }
+        tree => (tree, vec![]),
+    }
+}
+
+/// The alternatives of all the combinations of an alternative of each item, up to
+/// `MAX_ALTERNATIVES` of them.
+fn product(items: Vec<Vec<Vec<Literal>>>) -> Vec<Vec<Literal>> {
+    items
+        .into_iter()
+        .fold(vec![vec![]], |combinations, alternatives| {
+            combinations
+                .iter()
+                .flat_map(|combination| {
+                    alternatives.iter().map(move |alternative| {
+                        let mut combination = combination.clone();
+                        combination.extend(alternative.iter().cloned());
+                        combination
+                    })
+                })
+                .take(MAX_ALTERNATIVES)
+                .collect()
+        })
+}
+
+fn rule_element(id: u64, level: u8, attributes: &str, elements: &[String]) -> String {
+    format!(
+        "<rule id=\"{id}\" level=\"{level}\"{attributes}>\n{}\n</rule>",
+        elements
+            .iter()
+            .map(|element| indent(element, 2))
+            .collect::<Vec<String>>()
+            .join("\n")
+    )
+}
+
+/// A key of a CDB list, quoted when it holds the key separator.
+fn cdb_key(value: &str) -> String {
+    if value.contains(':') {
+        format!("\"{}\":", value.replace('"', "\\\""))
+    } else {
+        format!("{value}:")
+    }
+}
+
+fn indent(text: &str, spaces: usize) -> String {
+    text.lines()
+        .map(|line| format!("{}{line}", " ".repeat(spaces)))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::wazuh::{WazuhBackend, WazuhOutput};
+    use crate::backend::BackEnd;
+    use crate::parse_sigma_rule;
+
+    #[test]
+    pub fn test_rules() {
+        let rule =
+            parse_sigma_rule(include_str!("../sigma/components/rule/7zip_sigma_rule.yml")).unwrap();
+        let rules = WazuhBackend::default().convert_rule(rule);
+        // the ids are derived from the sigma id
+        assert!(rules.starts_with("<group name=\"sigma,\">\n  <rule id=\"116240\" "));
+        assert!(rules.contains("<rule id=\"116241\" "));
+        // the image alternatives are a rule each, the filter alternatives a child rule each
+        assert_eq!(rules.matches("<rule ").count(), 4);
+        assert!(rules
+            .contains(r#"<field name="win.eventdata.image" type="pcre2">(?i)\\cmd\.exe$</field>"#));
+        assert!(rules.contains(
+            r#"  <rule id="116242" level="0">
+    <if_sid>116240,116241</if_sid>
+    <field name="win.eventdata.commandLine" type="pcre2">(?:(?i)/c|(?i)/k|(?i)/r)</field>"#
+        ));
+        assert!(
+            rules.contains(r#"<field name="win.eventdata.commandLine" type="pcre2">^$</field>"#)
+        );
+    }
+
+    #[test]
+    pub fn test_filters() {
+        let filters: String = (1..=5)
+            .map(|index| {
+                format!(
+                    "    filter_{index}:\n        ParentImage: C:\\Tools\\{index}.exe\n        User: svc_{index}\n        CommandLine|contains: task_{index}\n"
+                )
+            })
+            .collect();
+        let rule = parse_sigma_rule(&format!(
+            r#"
+title: Shell Spawned
+logsource:
+    product: windows
+    category: process_creation
+detection:
+    selection:
+        Image|endswith: \cmd.exe
+{filters}    condition: selection and not 1 of filter_*
+"#
+        ))
+        .unwrap();
+        let rules = WazuhBackend::default().convert_rule(rule);
+        // a detection rule and a child rule per filter, instead of the 3^5 combinations
+        assert_eq!(rules.matches("<rule ").count(), 6);
+        assert_eq!(rules.matches("level=\"0\"").count(), 5);
+    }
+
+    #[test]
+    pub fn test_lists_and_correlation() {
+        let rule = parse_sigma_rule(
+            r#"
+title: Recon Tools
+id: 7d0a2c3e-0000-4000-8000-000000000002
+logsource:
+    product: windows
+    category: process_creation
+detection:
+    selection:
+        OriginalFileName:
+            - whoami.exe
+            - net.exe
+            - net1.exe
+            - nltest.exe
+    condition: selection | count() by Computer > 5
+    timeframe: 10m
+tags:
+    - attack.discovery
+    - attack.t1033
+level: high
+"#,
+        )
+        .unwrap();
+        let mappings = [
+            ("option.list.threshold", "3"),
+            ("option.rule.id.start", "200000"),
+            ("option.rule.id.end", "200009"),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let backend = WazuhBackend::new(Some(mappings), WazuhOutput::Rules);
+        assert_eq!(
+            backend.convert_rule(rule.clone()),
+            r#"<group name="sigma,">
+  <rule id="200000" level="3">
+    <if_sid>60004</if_sid>
+    <field name="win.system.eventID" type="pcre2">^(?:1)$</field>
+    <list field="win.eventdata.originalFileName" lookup="match_key">etc/lists/sigma_200000_1</list>
+    <description>Recon Tools</description>
+    <mitre>
+      <id>T1033</id>
+    </mitre>
+  </rule>
+
+  <rule id="200001" level="12" frequency="6" timeframe="600">
+    <if_matched_sid>200000</if_matched_sid>
+    <same_field>win.system.computer</same_field>
+    <description>Recon Tools</description>
+    <mitre>
+      <id>T1033</id>
+    </mitre>
+  </rule>
+</group>
+"#
+        );
+        let mappings = [
+            ("option.list.threshold", "3"),
+            ("option.rule.id.start", "200000"),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        assert_eq!(
+            WazuhBackend::new(Some(mappings), WazuhOutput::Lists).convert_rule(rule),
+            "# etc/lists/sigma_200000_1\nwhoami.exe:\nnet.exe:\nnet1.exe:\nnltest.exe:\n"
+        );
+    }
+}
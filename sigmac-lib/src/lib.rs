@@ -1,9 +1,14 @@
-use crate::backend::arcsight::ArcSightBackend;
-use crate::backend::aws_opensearch::AWSOpenSearchBackend;
+use crate::backend::arcsight::{ArcSightBackend, ArcSightOutput};
+use crate::backend::aws_opensearch::{AWSOpenSearchBackend, OpenSearchOutput};
+use crate::backend::carbon_black::{CarbonBlackBackend, CarbonBlackOutput};
 use crate::{
     backend::{
-        elastalert::ElastAlertBackend, humio_alert::HumioAlertBackend,
-        kibana::KibanaSavedSearchBackend, qradar::QradarBackend, splunk::SplunkBackend, BackEnd,
+        elastalert::ElastAlertBackend,
+        humio_alert::HumioAlertBackend,
+        kibana::KibanaSavedSearchBackend,
+        qradar::{QradarBackend, QradarOutput},
+        splunk::{SplunkBackend, SplunkOutput},
+        BackEnd,
     },
     error::Error::{self, SerdeError},
     sigma::components::rule::sigma::SigmaRule,
@@ -17,17 +22,26 @@ mod sigma;
 mod utils;
 
 use crate::backend::chronicle::ChronicleBackend;
-use crate::backend::devo::DevoBackend;
-use crate::backend::dnif::DNIFBackend;
-use crate::backend::graylog::GrayLogBackend;
-use crate::backend::kafka_sql::KafkaSqlBackend;
-use crate::backend::logrhythm::LogRhythmBackend;
-use crate::backend::securonix::SecuronixBackend;
-use crate::backend::sentinel::SentinelBackend;
-use crate::backend::snowflake::SnowflakeBackend;
-use crate::backend::sql::SQLBackend;
+use crate::backend::cortex_xdr::{CortexXdrBackend, CortexXdrOutput};
+use crate::backend::data_lake::{DataLakeBackend, DataLakeEngine};
+use crate::backend::datadog::{DatadogBackend, DatadogOutput};
+use crate::backend::devo::{DevoBackend, DevoOutput};
+use crate::backend::dnif::{DNIFBackend, DNIFOutput};
+use crate::backend::esql::EsqlBackend;
+use crate::backend::graylog::{GrayLogBackend, GrayLogOutput};
+use crate::backend::kafka_sql::{KafkaSqlBackend, KafkaSqlOutput};
+use crate::backend::logrhythm::{LogRhythmBackend, LogRhythmOutput};
+use crate::backend::loki::{LokiBackend, LokiOutput};
+use crate::backend::osquery::{OsqueryBackend, OsqueryOutput};
+use crate::backend::securonix::{SecuronixBackend, SecuronixOutput};
+use crate::backend::sentinel::{SentinelBackend, SentinelOutput, SentinelSchema};
+use crate::backend::sentinel_one::{SentinelOneBackend, SentinelOneOutput};
+use crate::backend::snowflake::{SnowflakeBackend, SnowflakeOutput};
+use crate::backend::sql::{SQLBackend, SqlDialect};
 use crate::backend::sqlite::SQLiteBackend;
-use crate::backend::sumologic::SumoLogicBackend;
+use crate::backend::sumologic::{SumoLogicBackend, SumoLogicOutput};
+use crate::backend::velociraptor::{VelociraptorBackend, VelociraptorOutput};
+use crate::backend::wazuh::{WazuhBackend, WazuhOutput};
 pub use crate::backend::Backends;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -42,37 +56,198 @@ pub fn from_sigma(
     replace_fields: Option<String>,
     keep_fields: Option<String>,
 ) -> Result<String> {
-    match parse_sigma_rule(sigma_yml_str) {
-        Ok(rule) => match Backends::parse(convert_to.to_lowercase().as_str())? {
-            Backends::ElastAlert => Ok(ElastAlertBackend::new(
-                field_map,
-                add_alerting,
-                add_fields,
-                keep_fields,
-                replace_fields,
-            )
-            .convert_rule(rule)),
-            Backends::Kibana => Ok(KibanaSavedSearchBackend::default().convert_rule(rule)),
-            Backends::HumioAlert => Ok(HumioAlertBackend::default().convert_rule(rule)),
-            Backends::ArcSight => Ok(ArcSightBackend::default().convert_rule(rule)),
-            Backends::Qradar => Ok(QradarBackend::new(field_map).convert_rule(rule)),
-            Backends::Splunk => Ok(SplunkBackend::default().convert_rule(rule)),
-            Backends::Chronicle => Ok(ChronicleBackend::default().convert_rule(rule)),
-            Backends::Devo => Ok(DevoBackend::default().convert_rule(rule)),
-            Backends::LogRhythm => Ok(LogRhythmBackend::default().convert_rule(rule)),
-            Backends::KafkaSQL => Ok(KafkaSqlBackend::default().convert_rule(rule)),
-            Backends::AwsOpenSearch => Ok(AWSOpenSearchBackend::default().convert_rule(rule)),
-            Backends::Dnif => Ok(DNIFBackend::default().convert_rule(rule)),
-            Backends::GrayLog => Ok(GrayLogBackend::default().convert_rule(rule)),
-            Backends::SQL => Ok(SQLBackend::default().convert_rule(rule)),
-            Backends::SQLite => Ok(SQLiteBackend::default().convert_rule(rule)),
-            Backends::Securonix => Ok(SecuronixBackend::default().convert_rule(rule)),
-            Backends::Sentinel => Ok(SentinelBackend::default().convert_rule(rule)),
-            Backends::Snowflake => Ok(SnowflakeBackend::default().convert_rule(rule)),
-            Backends::SumoLogic => Ok(SumoLogicBackend::default().convert_rule(rule)),
-        },
-        Err(e) => Err(e),
+    let rule = parse_sigma_rule(sigma_yml_str)?;
+    let backend = get_backend(
+        convert_to,
+        field_map,
+        add_alerting,
+        add_fields,
+        replace_fields,
+        keep_fields,
+    )?;
+    Ok(backend.convert_rule(rule))
+}
+
+/// Convert a set of Sigma Rules to a single output of the destination type,
+/// Ex: a Splunk savedsearches.conf holding a stanza for each rule
+pub fn from_sigma_rules(
+    sigma_yml_strs: &[String],
+    convert_to: &str,
+    field_map: Option<HashMap<String, String>>,
+    add_alerting: Option<String>,
+    add_fields: Option<String>,
+    replace_fields: Option<String>,
+    keep_fields: Option<String>,
+) -> Result<String> {
+    let mut rules = Vec::with_capacity(sigma_yml_strs.len());
+    for sigma_yml_str in sigma_yml_strs {
+        rules.push(parse_sigma_rule(sigma_yml_str)?);
     }
+    let backend = get_backend(
+        convert_to,
+        field_map,
+        add_alerting,
+        add_fields,
+        replace_fields,
+        keep_fields,
+    )?;
+    Ok(backend.convert_rules(rules))
+}
+
+/// Builds the backend converting into the destination type
+fn get_backend(
+    convert_to: &str,
+    field_map: Option<HashMap<String, String>>,
+    add_alerting: Option<String>,
+    add_fields: Option<String>,
+    replace_fields: Option<String>,
+    keep_fields: Option<String>,
+) -> Result<Box<dyn BackEnd>> {
+    let backend: Box<dyn BackEnd> = match Backends::parse(convert_to.to_lowercase().as_str())? {
+        Backends::ElastAlert => Box::new(ElastAlertBackend::new(
+            field_map,
+            add_alerting,
+            add_fields,
+            keep_fields,
+            replace_fields,
+        )),
+        Backends::Kibana => Box::new(KibanaSavedSearchBackend::default()),
+        Backends::HumioAlert => Box::new(HumioAlertBackend::default()),
+        Backends::ArcSight => Box::new(ArcSightBackend::new(field_map, ArcSightOutput::Query)),
+        Backends::ArcSightEsmRule => {
+            Box::new(ArcSightBackend::new(field_map, ArcSightOutput::EsmRule))
+        }
+        Backends::Qradar => Box::new(QradarBackend::new(field_map, QradarOutput::Query)),
+        Backends::QradarRuleXml => Box::new(QradarBackend::new(field_map, QradarOutput::RuleXml)),
+        Backends::QradarBuildingBlock => {
+            Box::new(QradarBackend::new(field_map, QradarOutput::BuildingBlock))
+        }
+        Backends::Splunk => Box::new(SplunkBackend::default()),
+        Backends::SplunkSavedSearches => Box::new(SplunkBackend::new(
+            SplunkOutput::SavedSearches,
+            add_alerting,
+        )),
+        Backends::SplunkCorrelationSearch => Box::new(SplunkBackend::new(
+            SplunkOutput::CorrelationSearch,
+            add_alerting,
+        )),
+        Backends::SplunkDataModel => Box::new(SplunkBackend::new(SplunkOutput::DataModel, None)),
+        Backends::Chronicle => Box::new(ChronicleBackend::default()),
+        Backends::Devo => Box::new(DevoBackend::new(field_map, DevoOutput::Query)),
+        Backends::DevoAlertDefinition => {
+            Box::new(DevoBackend::new(field_map, DevoOutput::AlertDefinition))
+        }
+        Backends::LogRhythm => Box::new(LogRhythmBackend::new(field_map, LogRhythmOutput::Query)),
+        Backends::LogRhythmWebConsoleSearch => Box::new(LogRhythmBackend::new(
+            field_map,
+            LogRhythmOutput::WebConsoleSearch,
+        )),
+        Backends::LogRhythmAiEngine => Box::new(LogRhythmBackend::new(
+            field_map,
+            LogRhythmOutput::AiEngineRule,
+        )),
+        Backends::KafkaSQL => Box::new(KafkaSqlBackend::new(field_map, KafkaSqlOutput::Query)),
+        Backends::KafkaSQLStream => {
+            Box::new(KafkaSqlBackend::new(field_map, KafkaSqlOutput::Stream))
+        }
+        Backends::AwsOpenSearch => Box::new(AWSOpenSearchBackend::default()),
+        Backends::AwsOpenSearchSecurityAnalytics => Box::new(AWSOpenSearchBackend::new(
+            OpenSearchOutput::SecurityAnalytics,
+        )),
+        Backends::AwsOpenSearchMonitor => {
+            Box::new(AWSOpenSearchBackend::new(OpenSearchOutput::Monitor))
+        }
+        Backends::Dnif => Box::new(DNIFBackend::new(field_map, DNIFOutput::Query)),
+        Backends::DnifWorkbook => Box::new(DNIFBackend::new(field_map, DNIFOutput::Workbook)),
+        Backends::GrayLog => Box::new(GrayLogBackend::new(field_map, GrayLogOutput::Query)),
+        Backends::GrayLogEventDefinition => Box::new(GrayLogBackend::new(
+            field_map,
+            GrayLogOutput::EventDefinition,
+        )),
+        Backends::Loki => Box::new(LokiBackend::new(field_map, LokiOutput::Query)),
+        Backends::LokiRuler => Box::new(LokiBackend::new(field_map, LokiOutput::Ruler)),
+        Backends::SQL => Box::new(SQLBackend::default()),
+        Backends::SQLite => Box::new(SQLiteBackend::default()),
+        Backends::PostgreSQL => Box::new(SQLBackend::new(SqlDialect::POSTGRESQL)),
+        Backends::MySQL => Box::new(SQLBackend::new(SqlDialect::MYSQL)),
+        Backends::ClickHouse => Box::new(SQLBackend::new(SqlDialect::CLICKHOUSE)),
+        Backends::DuckDB => Box::new(SQLBackend::new(SqlDialect::DUCKDB)),
+        Backends::Securonix => Box::new(SecuronixBackend::new(field_map, SecuronixOutput::Query)),
+        Backends::SecuronixPolicy => {
+            Box::new(SecuronixBackend::new(field_map, SecuronixOutput::Policy))
+        }
+        Backends::Sentinel => Box::new(SentinelBackend::default()),
+        Backends::SentinelAnalyticsRule => Box::new(SentinelBackend::new(
+            SentinelSchema::SecurityEvent,
+            SentinelOutput::ArmTemplate,
+        )),
+        Backends::SentinelAnalyticsRuleYaml => Box::new(SentinelBackend::new(
+            SentinelSchema::SecurityEvent,
+            SentinelOutput::Yaml,
+        )),
+        Backends::DefenderXdr => Box::new(SentinelBackend::new(
+            SentinelSchema::DefenderXdr,
+            SentinelOutput::Query,
+        )),
+        Backends::Snowflake => Box::new(SnowflakeBackend::new(field_map, SnowflakeOutput::Query)),
+        Backends::SnowflakeView => {
+            Box::new(SnowflakeBackend::new(field_map, SnowflakeOutput::View))
+        }
+        Backends::SnowflakeTask => {
+            Box::new(SnowflakeBackend::new(field_map, SnowflakeOutput::Task))
+        }
+        Backends::SumoLogic => Box::new(SumoLogicBackend::default()),
+        Backends::SumoLogicScheduledSearch => {
+            Box::new(SumoLogicBackend::new(SumoLogicOutput::ScheduledSearch))
+        }
+        Backends::SumoLogicCloudSiem => Box::new(SumoLogicBackend::new(SumoLogicOutput::CloudSiem)),
+        Backends::Datadog => Box::new(DatadogBackend::new(field_map, DatadogOutput::Query)),
+        Backends::DatadogDetectionRule => {
+            Box::new(DatadogBackend::new(field_map, DatadogOutput::DetectionRule))
+        }
+        Backends::CortexXdr => Box::new(CortexXdrBackend::new(field_map, CortexXdrOutput::Query)),
+        Backends::CortexXdrBioc => {
+            Box::new(CortexXdrBackend::new(field_map, CortexXdrOutput::Bioc))
+        }
+        Backends::SentinelOne => {
+            Box::new(SentinelOneBackend::new(field_map, SentinelOneOutput::Query))
+        }
+        Backends::SentinelOnePowerQuery => Box::new(SentinelOneBackend::new(
+            field_map,
+            SentinelOneOutput::PowerQuery,
+        )),
+        Backends::SentinelOneStarRule => Box::new(SentinelOneBackend::new(
+            field_map,
+            SentinelOneOutput::StarRule,
+        )),
+        Backends::CarbonBlack => {
+            Box::new(CarbonBlackBackend::new(field_map, CarbonBlackOutput::Query))
+        }
+        Backends::CarbonBlackWatchlist => Box::new(CarbonBlackBackend::new(
+            field_map,
+            CarbonBlackOutput::WatchlistReport,
+        )),
+        Backends::Osquery => Box::new(OsqueryBackend::new(field_map, OsqueryOutput::Query)),
+        Backends::OsqueryPack => Box::new(OsqueryBackend::new(field_map, OsqueryOutput::Pack)),
+        Backends::Velociraptor => Box::new(VelociraptorBackend::new(
+            field_map,
+            VelociraptorOutput::Query,
+        )),
+        Backends::VelociraptorArtifact => Box::new(VelociraptorBackend::new(
+            field_map,
+            VelociraptorOutput::Artifact,
+        )),
+        Backends::VelociraptorSigmaMapping => Box::new(VelociraptorBackend::new(
+            field_map,
+            VelociraptorOutput::SigmaMapping,
+        )),
+        Backends::Wazuh => Box::new(WazuhBackend::new(field_map, WazuhOutput::Rules)),
+        Backends::WazuhLists => Box::new(WazuhBackend::new(field_map, WazuhOutput::Lists)),
+        Backends::Esql => Box::new(EsqlBackend::new(field_map)),
+        Backends::BigQuery => Box::new(DataLakeBackend::new(field_map, DataLakeEngine::BigQuery)),
+        Backends::SparkSql => Box::new(DataLakeBackend::new(field_map, DataLakeEngine::Spark)),
+    };
+    Ok(backend)
 }
 
 /// Parse the Sigma Rule contents passed in yml string
@@ -82,10 +257,12 @@ fn parse_sigma_rule(rule_content: &str) -> Result<SigmaRule> {
     // if rule_content.contains("- '{")
     // NOTE: When there are conditions with wildcards get replaced with a " " characters
     // and this breaks the condition builder later on, at least for sql.rs
+    let source = rule_content.to_string();
     let rule_content = rule_content.replace(['\'', '*', '%'], "");
-    let parsed_rule = match serde_yaml::from_str::<SigmaRule>(rule_content.as_str()) {
+    let mut parsed_rule = match serde_yaml::from_str::<SigmaRule>(rule_content.as_str()) {
         Ok(parsed_rule) => parsed_rule,
         Err(e) => return Err(SerdeError(e)),
     };
+    parsed_rule.source = Some(source.into());
     Ok(parsed_rule)
 }
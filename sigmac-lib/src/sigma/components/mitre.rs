@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 
 pub const TACTIC_RECONNAISSANCE_ENTERPRISE: MitreTactics = MitreTactics::TA0043;
 pub const TACTIC_RESOURCE_DEVELOPMENT: MitreTactics = MitreTactics::TA0042;
@@ -177,12 +178,55 @@ impl TryFrom<&str> for MitreTactics {
             "ta0034" => Ok(MitreTactics::TA0034),
             "ta0038" => Ok(MitreTactics::TA0038),
             "ta0039" => Ok(MitreTactics::TA0039),
+            "reconnaissance" => Ok(TACTIC_RECONNAISSANCE_ENTERPRISE),
+            "resource_development" => Ok(TACTIC_RESOURCE_DEVELOPMENT),
+            "initial_access" => Ok(TACTIC_INITIAL_ACCESS_ENTERPRISE),
+            "execution" => Ok(TACTIC_EXECUTION_ENTERPRISE),
+            "persistence" => Ok(TACTIC_PERSISTENCE_ENTERPRISE),
+            "privilege_escalation" => Ok(TACTIC_PRIVILEGE_ESCALATION_ENTERPRISE),
+            "defense_evasion" => Ok(TACTIC_DEFENSE_EVASION_ENTERPRISE),
+            "credential_access" => Ok(TACTIC_CREDENTIAL_ACCESS_ENTERPRISE),
+            "discovery" => Ok(TACTIC_DISCOVERY_ENTERPRISE),
+            "lateral_movement" => Ok(TACTIC_LATERAL_MOVEMENT_ENTERPRISE),
+            "collection" => Ok(TACTIC_COLLECTION_ENTERPRISE),
             "command_and_control" => Ok(TACTIC_COMMAND_AND_CONTROL_ENTERPRISE),
+            "exfiltration" => Ok(TACTIC_EXFILTRATION_ENTERPRISE),
+            "impact" => Ok(TACTIC_IMPACT_ENTERPRISE),
             _ => Err("Invalid Mitre Tactic"),
         }
     }
 }
 
+impl MitreTactics {
+    /// The name of the tactic as shown in the ATT&CK matrix. Ex: "Command and Control"
+    pub fn name(&self) -> &'static str {
+        match self {
+            MitreTactics::TA0043 => "Reconnaissance",
+            MitreTactics::TA0042 => "Resource Development",
+            MitreTactics::TA0001 | MitreTactics::TA0027 => "Initial Access",
+            MitreTactics::TA0002 | MitreTactics::TA0041 => "Execution",
+            MitreTactics::TA0003 | MitreTactics::TA0028 => "Persistence",
+            MitreTactics::TA0004 | MitreTactics::TA0029 => "Privilege Escalation",
+            MitreTactics::TA0005 | MitreTactics::TA0030 => "Defense Evasion",
+            MitreTactics::TA0006 | MitreTactics::TA0031 => "Credential Access",
+            MitreTactics::TA0007 | MitreTactics::TA0032 => "Discovery",
+            MitreTactics::TA0008 | MitreTactics::TA0033 => "Lateral Movement",
+            MitreTactics::TA0009 | MitreTactics::TA0035 => "Collection",
+            MitreTactics::TA0011 | MitreTactics::TA0037 => "Command and Control",
+            MitreTactics::TA0010 | MitreTactics::TA0036 => "Exfiltration",
+            MitreTactics::TA0040 | MitreTactics::TA0034 => "Impact",
+            MitreTactics::TA0038 => "Network Effects",
+            MitreTactics::TA0039 => "Remote Service Effects",
+        }
+    }
+}
+
+impl Display for MitreTactics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 pub const TECHNIQUE_DATA_OBFUSCATION: MitreTechniques = MitreTechniques::T1001;
 pub const TECHNIQUE_DATA_OBFUSCATION_JUNK_DATA: MitreTechniques = MitreTechniques::T1001_001;
 pub const TECHNIQUE_DATA_OBFUSCATION_STEGANOGRAPHY: MitreTechniques = MitreTechniques::T1001_002;
@@ -4213,3 +4257,21 @@ impl TryFrom<&str> for MitreTechniques {
         })
     }
 }
+
+impl MitreTechniques {
+    /// The parent technique of a sub-technique, or the technique itself. Ex: "T1059" for T1059.001
+    pub fn parent_id(&self) -> String {
+        let id = self.to_string();
+        match id.split_once('.') {
+            Some((parent, _)) => parent.to_string(),
+            None => id,
+        }
+    }
+}
+
+impl Display for MitreTechniques {
+    /// Writes the ATT&CK identifier of the technique. Ex: "T1059.001"
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{:?}", self).replace('_', "."))
+    }
+}
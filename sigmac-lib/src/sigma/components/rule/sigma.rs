@@ -2,8 +2,9 @@ use std::{borrow::Cow, collections::BTreeMap, fmt::Display};
 
 use crate::sigma::components::alert::AlertSeverity;
 use crate::sigma::components::mitre::{MitreTactics, MitreTechniques};
-use crate::sigma::events::field::SiemField;
+use crate::sigma::events::field::{SiemField, SiemIp};
 use crate::sigma::utilities::types::LogString;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use super::{
@@ -91,6 +92,9 @@ pub struct SigmaRule {
     pub level: Option<LogString>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tags: Option<Vec<LogString>>,
+    /// The text the rule was parsed from, as written (its wildcards included).
+    #[serde(skip)]
+    pub source: Option<LogString>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -105,6 +109,9 @@ pub struct SigmaRuleDetection {
     #[serde(flatten)]
     pub search_identifiers: BTreeMap<LogString, SigmaRuleCondition>,
     pub condition: LogString,
+    /// Time window of the aggregation expression (`| count() by field > 5`) of the condition
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeframe: Option<LogString>,
 }
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
@@ -116,6 +123,29 @@ pub enum SigmaRuleCondition {
     None,
 }
 
+impl SigmaRuleCondition {
+    /// Returns the conditions of the search identifier as OR'ed groups of AND'ed conditions,
+    /// so a list of maps keeps the alternation between its items.
+    pub fn condition_groups(&self) -> Vec<Vec<RuleCondition>> {
+        match self {
+            SigmaRuleCondition::Map(condition_list) => vec![condition_list
+                .iter()
+                .map(|(field, value)| parse_rule_condition(field.clone(), value.clone()))
+                .collect()],
+            SigmaRuleCondition::List(condition_list) => condition_list
+                .iter()
+                .map(|condition| {
+                    condition
+                        .iter()
+                        .map(|(field, value)| parse_rule_condition(field.clone(), value.clone()))
+                        .collect()
+                })
+                .collect(),
+            SigmaRuleCondition::None => vec![],
+        }
+    }
+}
+
 impl Into<SiemSubRule> for SigmaRuleCondition {
     fn into(self) -> SiemSubRule {
         match self {
@@ -269,6 +299,38 @@ fn translate_operator(operator: &str, extra: Option<&str>, value: SigmaValue) ->
             }
             // RuleOperator::StartsWith(value.to_string())
         }
+        "cidr" => {
+            let to_net = |value: &str| {
+                let (ip, net) = value.split_once('/')?;
+                Some(RuleOperator::SameNet((
+                    SiemIp::from_ip_str(ip.trim()).ok()?,
+                    net.trim().parse().ok()?,
+                )))
+            };
+            match value {
+                SigmaValue::Text(v) => to_net(&v).unwrap_or(RuleOperator::All(vec![])),
+                SigmaValue::Array(v) => RuleOperator::Any(
+                    v.iter()
+                        .filter_map(|v| to_net(&v.to_string()))
+                        .map(Box::new)
+                        .collect(),
+                ),
+                _ => RuleOperator::All(vec![]),
+            }
+        }
+        "re" => match value {
+            SigmaValue::Text(v) => match Regex::new(&v) {
+                Ok(regex) => RuleOperator::Matches(regex),
+                Err(_) => RuleOperator::All(vec![]),
+            },
+            SigmaValue::Array(v) => RuleOperator::Any(
+                v.iter()
+                    .filter_map(|v| Regex::new(&v.to_string()).ok())
+                    .map(|regex| Box::new(RuleOperator::Matches(regex)))
+                    .collect(),
+            ),
+            _ => RuleOperator::All(vec![]),
+        },
         _ => RuleOperator::All(vec![]),
     }
 }
@@ -408,6 +470,134 @@ pub enum SigmaDetectionCondition {
     Or(ConditionExpression),
 }
 
+/// Boolean expression of a detection condition, keeping the precedence and grouping of
+/// `and`, `or`, `not` and the parenthesis
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConditionTree {
+    /// A single search identifier (`selection`)
+    Identifier(LogString),
+    /// (1 of value*) expressions, `them` is kept as `*`
+    AnyOf(LogString),
+    /// (all of value*) expressions, `them` is kept as `*`
+    AllOf(LogString),
+    And(Vec<ConditionTree>),
+    Or(Vec<ConditionTree>),
+    Not(Box<ConditionTree>),
+}
+
+impl ConditionTree {
+    /// Renders the expression with the given boolean keywords, `resolve` being called
+    /// with the name of every search identifier matched by the expression.
+    pub fn render(
+        &self,
+        identifiers: &[LogString],
+        keywords: (&str, &str, &str),
+        resolve: &dyn Fn(&str) -> String,
+    ) -> String {
+        let (and, or, not) = keywords;
+        let join = |items: Vec<String>, operator: &str| match items.len() {
+            0 => String::new(),
+            1 => items[0].clone(),
+            _ => format!("({})", items.join(&format!(" {operator} "))),
+        };
+        match self {
+            ConditionTree::Identifier(name) => resolve(name),
+            ConditionTree::AnyOf(pattern) | ConditionTree::AllOf(pattern) => {
+                let items = identifiers
+                    .iter()
+                    .filter(|name| matches_identifier(pattern, name))
+                    .map(|name| resolve(name))
+                    .filter(|query| !query.is_empty())
+                    .collect();
+                match self {
+                    ConditionTree::AnyOf(_) => join(items, or),
+                    _ => join(items, and),
+                }
+            }
+            ConditionTree::And(nodes) | ConditionTree::Or(nodes) => {
+                let items = nodes
+                    .iter()
+                    .map(|node| node.render(identifiers, keywords, resolve))
+                    .filter(|query| !query.is_empty())
+                    .collect();
+                match self {
+                    ConditionTree::And(_) => join(items, and),
+                    _ => join(items, or),
+                }
+            }
+            ConditionTree::Not(node) => {
+                let query = node.render(identifiers, keywords, resolve);
+                if query.is_empty() {
+                    query
+                } else if not.ends_with(char::is_alphanumeric) {
+                    format!("{not} {query}")
+                } else {
+                    // symbolic negations such as `!` stick to the operand
+                    format!("{not}{query}")
+                }
+            }
+        }
+    }
+
+    /// Replaces the `1 of`/`all of` expressions by the search identifiers they match, for the
+    /// backends walking the tree instead of rendering it.
+    pub fn expand(&self, identifiers: &[LogString]) -> ConditionTree {
+        match self {
+            ConditionTree::Identifier(name) => ConditionTree::Identifier(name.clone()),
+            ConditionTree::AnyOf(pattern) | ConditionTree::AllOf(pattern) => {
+                let nodes = identifiers
+                    .iter()
+                    .filter(|name| matches_identifier(pattern, name))
+                    .map(|name| ConditionTree::Identifier(name.clone()))
+                    .collect();
+                match self {
+                    ConditionTree::AnyOf(_) => ConditionTree::Or(nodes),
+                    _ => ConditionTree::And(nodes),
+                }
+            }
+            ConditionTree::And(nodes) => {
+                ConditionTree::And(nodes.iter().map(|node| node.expand(identifiers)).collect())
+            }
+            ConditionTree::Or(nodes) => {
+                ConditionTree::Or(nodes.iter().map(|node| node.expand(identifiers)).collect())
+            }
+            ConditionTree::Not(node) => ConditionTree::Not(Box::new(node.expand(identifiers))),
+        }
+    }
+}
+
+/// Matches a search identifier against a `1 of`/`all of` pattern which may contain `*`.
+/// A pattern without any `*` is a prefix, as the wildcards are stripped when parsing the rule.
+fn matches_identifier(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return name.starts_with(pattern);
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || !name[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Aggregation expression following the pipe of a condition (`| count(field) by group > 5`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConditionAggregation {
+    /// count, min, max, avg or sum
+    pub function: LogString,
+    pub field: Option<LogString>,
+    pub group_by: Option<LogString>,
+    pub operator: LogString,
+    pub value: i64,
+}
+
 fn level_to_severity(level: &str) -> AlertSeverity {
     match level {
         "info" => AlertSeverity::INFORMATIONAL,
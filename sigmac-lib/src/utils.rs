@@ -1,8 +1,14 @@
 use crate::prelude::rule::sigma::ConditionExpression::{All, Any, Not};
-use crate::prelude::rule::sigma::SigmaDetectionCondition;
+use crate::prelude::rule::sigma::{
+    ConditionAggregation, ConditionTree, SigmaDetectionCondition, SigmaRule, SigmaRuleLogSource,
+};
+use crate::prelude::rule::RuleOperator;
 use crate::prelude::types::LogString;
 use crate::prelude::Error::SigmaValueError;
 use crate::Result;
+use std::collections::HashMap;
+use std::process::exit;
+use uuid::Uuid;
 
 pub fn parse_condition(condition: &str) -> Result<Vec<SigmaDetectionCondition>> {
     let mut condition = condition.to_string();
@@ -92,9 +98,318 @@ pub fn parse_condition(condition: &str) -> Result<Vec<SigmaDetectionCondition>>
     Ok(sigma_detection_conditions)
 }
 
+/// Parses the boolean part of a detection condition (before any `|`) into a [`ConditionTree`],
+/// `not` binding tighter than `and` which binds tighter than `or`.
+pub fn parse_condition_tree(condition: &str) -> Result<ConditionTree> {
+    let expression = condition.split('|').next().unwrap_or_default();
+    let tokens: Vec<String> = expression
+        .replace('(', " ( ")
+        .replace(')', " ) ")
+        .split_whitespace()
+        .map(|token| token.to_string())
+        .collect();
+    let mut position = 0;
+    let tree = parse_or(&tokens, &mut position)?;
+    if position != tokens.len() {
+        return Err(SigmaValueError(format!(
+            "Invalid condition string provided: unexpected `{}`",
+            tokens[position]
+        )));
+    }
+    Ok(tree)
+}
+
+fn parse_or(tokens: &[String], position: &mut usize) -> Result<ConditionTree> {
+    let mut nodes = vec![parse_and(tokens, position)?];
+    while *position < tokens.len() && tokens[*position].eq_ignore_ascii_case("or") {
+        *position += 1;
+        nodes.push(parse_and(tokens, position)?);
+    }
+    Ok(if nodes.len() == 1 {
+        nodes.remove(0)
+    } else {
+        ConditionTree::Or(nodes)
+    })
+}
+
+fn parse_and(tokens: &[String], position: &mut usize) -> Result<ConditionTree> {
+    let mut nodes = vec![parse_not(tokens, position)?];
+    while *position < tokens.len() && tokens[*position].eq_ignore_ascii_case("and") {
+        *position += 1;
+        nodes.push(parse_not(tokens, position)?);
+    }
+    Ok(if nodes.len() == 1 {
+        nodes.remove(0)
+    } else {
+        ConditionTree::And(nodes)
+    })
+}
+
+fn parse_not(tokens: &[String], position: &mut usize) -> Result<ConditionTree> {
+    let token = match tokens.get(*position) {
+        Some(token) => token,
+        None => {
+            return Err(SigmaValueError(
+                "Invalid condition string provided: unexpected end".to_string(),
+            ))
+        }
+    };
+    *position += 1;
+    if token.eq_ignore_ascii_case("not") {
+        return Ok(ConditionTree::Not(Box::new(parse_not(tokens, position)?)));
+    }
+    if token == "(" {
+        let tree = parse_or(tokens, position)?;
+        if tokens.get(*position).map(|token| token.as_str()) != Some(")") {
+            return Err(SigmaValueError(
+                "Invalid condition string provided: missing `)`".to_string(),
+            ));
+        }
+        *position += 1;
+        return Ok(tree);
+    }
+    let quantifier = token.to_lowercase();
+    let is_quantified = matches!(quantifier.as_str(), "1" | "any" | "all")
+        && tokens
+            .get(*position)
+            .is_some_and(|token| token.eq_ignore_ascii_case("of"));
+    if !is_quantified {
+        return Ok(ConditionTree::Identifier(LogString::from(
+            token.to_string(),
+        )));
+    }
+    let pattern = match tokens.get(*position + 1) {
+        Some(pattern) if pattern.eq_ignore_ascii_case("them") => "*".to_string(),
+        Some(pattern) => pattern.to_string(),
+        None => {
+            return Err(SigmaValueError(
+                "Invalid condition string provided: missing pattern".to_string(),
+            ))
+        }
+    };
+    *position += 2;
+    Ok(match quantifier.as_str() {
+        "all" => ConditionTree::AllOf(LogString::from(pattern)),
+        _ => ConditionTree::AnyOf(LogString::from(pattern)),
+    })
+}
+
+/// Parses the aggregation following the pipe of a detection condition,
+/// eg: `selection | count(dst_port) by src_ip > 10`
+pub fn parse_aggregation(condition: &str) -> Result<Option<ConditionAggregation>> {
+    let aggregation = match condition.split_once('|') {
+        Some((_, aggregation)) => aggregation.trim(),
+        None => return Ok(None),
+    };
+    let invalid = || SigmaValueError(format!("Invalid aggregation provided: {aggregation}"));
+    let (function, rest) = aggregation.split_once('(').ok_or_else(invalid)?;
+    let (field, rest) = rest.split_once(')').ok_or_else(invalid)?;
+    let operator_index = rest.find(['<', '>', '=']).ok_or_else(invalid)?;
+    let (group, comparison) = rest.split_at(operator_index);
+    let operator: String = comparison
+        .chars()
+        .take_while(|c| matches!(c, '<' | '>' | '='))
+        .collect();
+    let value = comparison[operator.len()..]
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| invalid())?;
+    let group_by = group
+        .trim()
+        .strip_prefix("by")
+        .map(|group| LogString::from(group.trim().to_string()));
+    let field = field.trim();
+    Ok(Some(ConditionAggregation {
+        function: LogString::from(function.trim().to_lowercase()),
+        field: (!field.is_empty()).then(|| LogString::from(field.to_string())),
+        group_by,
+        operator: LogString::from(operator),
+        value,
+    }))
+}
+
+/// Renders the detection condition of the rule, `keywords` being the `and`, `or` and `not` of
+/// the query language and `predicate` rendering the condition of a single sigma field.
+pub fn render_condition(
+    rule: &SigmaRule,
+    keywords: (&str, &str, &str),
+    predicate: &dyn Fn(&str, &RuleOperator) -> String,
+) -> String {
+    let condition = match parse_condition_tree(&rule.detection.condition) {
+        Ok(condition) => condition,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            exit(1);
+        }
+    };
+    let (and, or, _) = keywords;
+    let identifiers: Vec<LogString> = rule.detection.search_identifiers.keys().cloned().collect();
+    condition.render(&identifiers, keywords, &|name| {
+        let groups = rule
+            .detection
+            .search_identifiers
+            .get(name)
+            .map(|search| search.condition_groups())
+            .unwrap_or_default()
+            .iter()
+            .map(|group| {
+                join_predicates(
+                    group
+                        .iter()
+                        .map(|condition| predicate(&condition.field, &condition.operator))
+                        .collect(),
+                    and,
+                )
+            })
+            .collect();
+        join_predicates(groups, or)
+    })
+}
+
+/// Joins the non empty predicates with `operator`, within parentheses when there are several.
+pub fn join_predicates(predicates: Vec<String>, operator: &str) -> String {
+    let predicates: Vec<String> = predicates.into_iter().filter(|p| !p.is_empty()).collect();
+    match predicates.len() {
+        0 => String::new(),
+        1 => predicates[0].clone(),
+        _ => format!("({})", predicates.join(&format!(" {operator} "))),
+    }
+}
+
+/// The `logsource.*` mapping of the logsource, the `logsource.<product>.<service>` one winning
+/// over `logsource.<product>` and `logsource.<category>`.
+pub fn logsource_mapping(
+    mappings: Option<&HashMap<String, String>>,
+    logsource: &SigmaRuleLogSource,
+) -> Option<String> {
+    let product = logsource.product.as_deref().unwrap_or_default();
+    let service = logsource.service.as_deref().unwrap_or_default();
+    let category = logsource.category.as_deref().unwrap_or_default();
+    let mappings = mappings?;
+    [
+        format!("logsource.{product}.{service}"),
+        format!("logsource.{product}"),
+        format!("logsource.{category}"),
+    ]
+    .iter()
+    .find_map(|key| mappings.get(key))
+    .cloned()
+}
+
+/// A backend option, given in the mappings as `option.<key>` so the options never collide with
+/// the field names (the CLI `--option <key>=<value>` adds them).
+pub fn backend_option<'a>(
+    mappings: Option<&'a HashMap<String, String>>,
+    key: &str,
+) -> Option<&'a str> {
+    mappings?.get(&format!("option.{key}")).map(String::as_str)
+}
+
+/// The body of a double quoted string, backslashes and double quotes being escaped.
+pub fn escape_string(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Splits a sigma timeframe (`30s`, `5m`, `1h`, `2d`) into its amount and unit, defaulting
+/// to 5 minutes.
+pub fn parse_timeframe(timeframe: &str) -> (u64, char) {
+    let timeframe = timeframe.trim();
+    let split = timeframe
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(timeframe.len());
+    let (amount, unit) = timeframe.split_at(split);
+    let amount = amount.parse::<u64>().unwrap_or(5);
+    let unit = match unit.trim() {
+        "s" => 's',
+        "h" => 'h',
+        "d" => 'd',
+        _ => 'm',
+    };
+    (amount, unit)
+}
+
+/// Converts a sigma timeframe into seconds.
+pub fn timeframe_seconds(timeframe: &str) -> u64 {
+    let (amount, unit) = parse_timeframe(timeframe);
+    amount
+        * match unit {
+            's' => 1,
+            'h' => 3_600,
+            'd' => 86_400,
+            _ => 60,
+        }
+}
+
+/// The Sysmon event ids of a sigma logsource category, the Sysmon events being told apart
+/// by their id.
+pub fn sysmon_event_ids(category: &str) -> &'static [u16] {
+    match category {
+        "process_creation" => &[1],
+        "file_change" => &[2],
+        "network_connection" => &[3],
+        "process_termination" => &[5],
+        "driver_load" => &[6],
+        "image_load" => &[7],
+        "create_remote_thread" => &[8],
+        "raw_access_thread" => &[9],
+        "process_access" => &[10],
+        "file_event" => &[11],
+        "registry_add" | "registry_delete" => &[12],
+        "registry_set" => &[13],
+        "registry_rename" => &[14],
+        "registry_event" => &[12, 13, 14],
+        "create_stream_hash" => &[15],
+        "pipe_created" => &[17, 18],
+        "wmi_event" => &[19, 20, 21],
+        "dns_query" => &[22],
+        "file_delete" => &[23, 26],
+        _ => &[],
+    }
+}
+
+/// UUID derived from a name (UUIDv5), so the resources generated for a rule keep their
+/// identifiers whenever it's converted again.
+pub fn stable_uuid(name: &str) -> Uuid {
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, name.as_bytes())
+}
+
+/// Number derived from a name, the first bytes of its [`stable_uuid`].
+pub fn stable_hash(name: &str) -> u64 {
+    stable_uuid(name).as_u64_pair().0
+}
+
+/// Lower case identifier made of the words of a name (Ex: `suspicious_shell`).
+pub fn snake_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("_")
+        .to_lowercase()
+}
+
+/// Upper case identifier made of the words of a name (Ex: `SUSPICIOUS_SHELL`).
+pub fn upper_snake_case(name: &str) -> String {
+    snake_case(name).to_uppercase()
+}
+
+/// Identifier made of the capitalized words of a name (Ex: `SuspiciousShell`).
+pub fn pascal_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::utils::parse_condition;
+    use crate::prelude::rule::sigma::ConditionTree;
+    use crate::utils::{parse_aggregation, parse_condition, parse_condition_tree};
 
     #[test]
     pub fn test_parsing_condition() {
@@ -102,4 +417,34 @@ mod tests {
         let conditions = parse_condition(condition).unwrap();
         assert_eq!(conditions.len(), 2);
     }
+
+    #[test]
+    pub fn test_parsing_condition_tree() {
+        let tree =
+            parse_condition_tree("selection and not (1 of filter_* or all of them)").unwrap();
+        assert_eq!(
+            tree,
+            ConditionTree::And(vec![
+                ConditionTree::Identifier("selection".into()),
+                ConditionTree::Not(Box::new(ConditionTree::Or(vec![
+                    ConditionTree::AnyOf("filter_*".into()),
+                    ConditionTree::AllOf("*".into()),
+                ]))),
+            ])
+        );
+        assert!(parse_condition_tree("selection and (filter").is_err());
+    }
+
+    #[test]
+    pub fn test_parsing_aggregation() {
+        let aggregation = parse_aggregation("selection | count(dst_port) by src_ip > 10")
+            .unwrap()
+            .unwrap();
+        assert_eq!(aggregation.function, "count");
+        assert_eq!(aggregation.field.as_deref(), Some("dst_port"));
+        assert_eq!(aggregation.group_by.as_deref(), Some("src_ip"));
+        assert_eq!(aggregation.operator, ">");
+        assert_eq!(aggregation.value, 10);
+        assert!(parse_aggregation("selection").unwrap().is_none());
+    }
 }